@@ -1,4 +1,5 @@
 use core::arch::asm;
+use super::board::{BOARD, MAX_CORES};
 
 // Exception levels
 pub const EL0: u8 = 0;
@@ -19,6 +20,139 @@ pub unsafe fn invalidate_dcache_all() {
     asm!("dsb sy");
 }
 
+// Smallest data cache line size in bytes, from CTR_EL0.DminLine
+pub fn dcache_line_size() -> usize {
+    let ctr: u64;
+    unsafe {
+        asm!("mrs {}, ctr_el0", out(reg) ctr);
+    }
+    4 << ((ctr >> 16) & 0xF)
+}
+
+// Write back [start, start + len) to the point of coherency, so a device
+// reading memory sees what the CPU wrote
+pub fn clean_dcache_range(start: usize, len: usize) {
+    let line = dcache_line_size();
+    for addr in (start & !(line - 1)..start + len).step_by(line) {
+        unsafe { asm!("dc cvac, {}", in(reg) addr, options(nostack)); }
+        crate::maybe_yield!();
+    }
+    unsafe { asm!("dsb sy", options(nostack)); }
+}
+
+// Discard cached copies of [start, start + len), so the CPU reads what a
+// device wrote
+// Dirty lines are dropped, not written back: partial lines at either end lose
+// any CPU writes to their other bytes.
+pub unsafe fn invalidate_dcache_range(start: usize, len: usize) {
+    let line = dcache_line_size();
+    for addr in (start & !(line - 1)..start + len).step_by(line) {
+        asm!("dc ivac, {}", in(reg) addr, options(nostack));
+        crate::maybe_yield!();
+    }
+    asm!("dsb sy", options(nostack));
+}
+
+// Write back and discard [start, start + len)
+pub fn clean_invalidate_dcache_range(start: usize, len: usize) {
+    let line = dcache_line_size();
+    for addr in (start & !(line - 1)..start + len).step_by(line) {
+        unsafe { asm!("dc civac, {}", in(reg) addr, options(nostack)); }
+        crate::maybe_yield!();
+    }
+    unsafe { asm!("dsb sy", options(nostack)); }
+}
+
+// Allow FP/SIMD instructions at EL1 and EL0 (CPACR_EL1.FPEN = 0b11)
+pub fn enable_fpu() {
+    unsafe {
+        let mut cpacr: u64;
+        asm!("mrs {}, cpacr_el1", out(reg) cpacr);
+        cpacr |= 3 << 20;
+        asm!("msr cpacr_el1, {}", "isb", in(reg) cpacr);
+    }
+}
+
+// FP/SIMD register file of a task
+#[repr(C, align(16))]
+pub struct FpContext {
+    pub q: [u128; 32],
+    pub fpcr: u64,
+    pub fpsr: u64,
+}
+
+impl FpContext {
+    pub const fn new() -> Self {
+        FpContext { q: [0; 32], fpcr: 0, fpsr: 0 }
+    }
+}
+
+// Save q0-q31, FPCR and FPSR
+// The kernel is built soft-float, so these registers only ever hold task state
+pub unsafe fn save_fp(ctx: *mut FpContext) {
+    asm!(
+        ".arch_extension fp",
+        ".arch_extension simd",
+        "stp q0, q1, [{0}, #32 * 0]",
+        "stp q2, q3, [{0}, #32 * 1]",
+        "stp q4, q5, [{0}, #32 * 2]",
+        "stp q6, q7, [{0}, #32 * 3]",
+        "stp q8, q9, [{0}, #32 * 4]",
+        "stp q10, q11, [{0}, #32 * 5]",
+        "stp q12, q13, [{0}, #32 * 6]",
+        "stp q14, q15, [{0}, #32 * 7]",
+        "stp q16, q17, [{0}, #32 * 8]",
+        "stp q18, q19, [{0}, #32 * 9]",
+        "stp q20, q21, [{0}, #32 * 10]",
+        "stp q22, q23, [{0}, #32 * 11]",
+        "stp q24, q25, [{0}, #32 * 12]",
+        "stp q26, q27, [{0}, #32 * 13]",
+        "stp q28, q29, [{0}, #32 * 14]",
+        "stp q30, q31, [{0}, #32 * 15]",
+        "mrs {1}, fpcr",
+        "mrs {2}, fpsr",
+        // Past the stp immediate range, so two plain stores
+        "str {1}, [{0}, #32 * 16]",
+        "str {2}, [{0}, #32 * 16 + 8]",
+        in(reg) ctx,
+        out(reg) _,
+        out(reg) _,
+        options(nostack)
+    );
+}
+
+// Restore the registers saved by save_fp
+pub unsafe fn restore_fp(ctx: *const FpContext) {
+    asm!(
+        ".arch_extension fp",
+        ".arch_extension simd",
+        "ldp q0, q1, [{0}, #32 * 0]",
+        "ldp q2, q3, [{0}, #32 * 1]",
+        "ldp q4, q5, [{0}, #32 * 2]",
+        "ldp q6, q7, [{0}, #32 * 3]",
+        "ldp q8, q9, [{0}, #32 * 4]",
+        "ldp q10, q11, [{0}, #32 * 5]",
+        "ldp q12, q13, [{0}, #32 * 6]",
+        "ldp q14, q15, [{0}, #32 * 7]",
+        "ldp q16, q17, [{0}, #32 * 8]",
+        "ldp q18, q19, [{0}, #32 * 9]",
+        "ldp q20, q21, [{0}, #32 * 10]",
+        "ldp q22, q23, [{0}, #32 * 11]",
+        "ldp q24, q25, [{0}, #32 * 12]",
+        "ldp q26, q27, [{0}, #32 * 13]",
+        "ldp q28, q29, [{0}, #32 * 14]",
+        "ldp q30, q31, [{0}, #32 * 15]",
+        "ldr {1}, [{0}, #32 * 16]",
+        "ldr {2}, [{0}, #32 * 16 + 8]",
+        "msr fpcr, {1}",
+        "msr fpsr, {2}",
+        in(reg) ctx,
+        out(reg) _,
+        out(reg) _,
+        options(nostack)
+    );
+}
+
 // Enable IRQ interrupts
 pub unsafe fn enable_irq() {
     // Enable interrupts using MSR instruction directly
@@ -31,6 +165,41 @@ pub unsafe fn disable_irq() {
     asm!("msr daifset, #2");
 }
 
+// Mask IRQs and return the previous DAIF value, for restore_irqs
+pub fn save_and_disable_irqs() -> u64 {
+    let daif: u64;
+    unsafe {
+        asm!("mrs {}, daif", out(reg) daif);
+        asm!("msr daifset, #2");
+    }
+    daif
+}
+
+// Restore a DAIF value returned by save_and_disable_irqs
+pub fn restore_irqs(daif: u64) {
+    unsafe {
+        asm!("msr daif, {}", in(reg) daif);
+    }
+}
+
+// Check if IRQs are masked in DAIF
+pub fn irqs_masked() -> bool {
+    let daif: u64;
+    unsafe {
+        asm!("mrs {}, daif", out(reg) daif);
+    }
+    daif & (1 << 7) != 0
+}
+
+// Run `f` with IRQs masked, restoring the previous mask afterwards
+// Nests, and is safe to use from code that may already run with IRQs masked
+pub fn with_irqs_masked<R>(f: impl FnOnce() -> R) -> R {
+    let daif = save_and_disable_irqs();
+    let result = f();
+    restore_irqs(daif);
+    result
+}
+
 // Enable FIQ interrupts
 pub unsafe fn enable_fiq() {
     asm!("msr daifclr, #1");
@@ -42,13 +211,11 @@ pub unsafe fn disable_fiq() {
 }
 
 // Check if currently in IRQ context
+// Uses the per-CPU handler nesting count rather than the GIC running
+// priority: with split EOI a handler may drop its priority early and keep
+// running, and the running priority then reads idle.
 pub fn is_in_irq() -> bool {
-    let spsr: u64;
-    unsafe {
-        asm!("mrs {}, spsr_el1", out(reg) spsr);
-    }
-    // Check M[3:0] in SPSR for IRQ mode
-    (spsr & 0xF) == 0x2
+    super::percpu::in_isr()
 }
 
 // Get the current exception level
@@ -60,13 +227,43 @@ pub fn current_el() -> u8 {
     ((el >> 2) & 0x3) as u8
 }
 
-// Get the CPU ID
-pub fn cpu_id() -> u8 {
-    let mut mpidr: u64;
+// Raw MPIDR_EL1, for code that needs the affinity fields themselves
+pub fn mpidr_raw() -> u64 {
+    let mpidr: u64;
+    unsafe {
+        asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nomem, nostack));
+    }
+    mpidr
+}
+
+// Linear position of this core (Aff1 = cluster, Aff0 = core within it)
+// The one notion of "core id": it indexes per-core state, GIC redistributors
+// and the SMP boot code alike.
+pub fn core_pos() -> usize {
+    let mpidr = mpidr_raw();
+    let aff0 = (mpidr & 0xFF) as usize;
+    let aff1 = ((mpidr >> 8) & 0xFF) as usize;
+    (aff1 * BOARD.cores_per_cluster + aff0).min(MAX_CORES - 1)
+}
+
+// Busy-wait for roughly `n` loop iterations, with no timer dependencies
+// Each iteration is a subs + b.ne pair (about 1-2 cycles on Cortex-A53), so
+// at the nominal 1.3 GHz core clock 1_000_000 iterations is ~1 ms. Only
+// meant for the earliest boot steps, before the STM or CNTFRQ are trusted.
+// The loop is in asm so the optimizer can neither elide nor shorten it.
+#[inline(never)]
+pub fn delay_cycles(n: u64) {
+    if n == 0 {
+        return;
+    }
     unsafe {
-        asm!("mrs {}, mpidr_el1", out(reg) mpidr);
+        asm!(
+            "1: subs {n}, {n}, #1",
+            "b.ne 1b",
+            n = inout(reg) n => _,
+            options(nomem, nostack)
+        );
     }
-    (mpidr & 0xFF) as u8
 }
 
 // Wait for event
@@ -74,6 +271,11 @@ pub fn wfe() {
     unsafe { asm!("wfe"); }
 }
 
+// Send event, waking cores waiting in wfe
+pub fn sev() {
+    unsafe { asm!("sev", options(nostack)); }
+}
+
 // Wait for interrupt
 pub fn wfi() {
     unsafe { asm!("wfi"); }
@@ -94,27 +296,76 @@ pub fn isb() {
     unsafe { asm!("isb"); }
 }
 
-// System register access helpers
+// Typed system register access
+// Each register is a unit type whose accessors compile to a single mrs/msr,
+// e.g. SctlrEl1::read(). Adding a register is one sysreg! line.
+pub trait SysReg {
+    unsafe fn read() -> u64;
+}
+
+// A system register EL1 may also write
+pub trait SysRegWrite: SysReg {
+    unsafe fn write(val: u64);
+}
+
+macro_rules! sysreg {
+    ($name:ident, $reg:literal) => {
+        pub struct $name;
+
+        impl SysReg for $name {
+            #[inline(always)]
+            unsafe fn read() -> u64 {
+                let val: u64;
+                asm!(concat!("mrs {}, ", $reg), out(reg) val, options(nomem, nostack, preserves_flags));
+                val
+            }
+        }
+    };
+    ($name:ident, $reg:literal, rw) => {
+        sysreg!($name, $reg);
+
+        impl SysRegWrite for $name {
+            #[inline(always)]
+            unsafe fn write(val: u64) {
+                asm!(concat!("msr ", $reg, ", {}"), in(reg) val, options(nostack, preserves_flags));
+            }
+        }
+    };
+}
+
+sysreg!(VbarEl1, "vbar_el1", rw);
+sysreg!(Ttbr0El1, "ttbr0_el1", rw);
+sysreg!(TcrEl1, "tcr_el1", rw);
+sysreg!(MairEl1, "mair_el1", rw);
+sysreg!(SctlrEl1, "sctlr_el1", rw);
+sysreg!(CpacrEl1, "cpacr_el1", rw);
+sysreg!(TpidrEl1, "tpidr_el1", rw);
+sysreg!(ActlrEl1, "actlr_el1", rw);
+sysreg!(CntkctlEl1, "cntkctl_el1", rw);
+sysreg!(CntfrqEl0, "cntfrq_el0");
+sysreg!(MpidrEl1, "mpidr_el1");
+
+// String-named system register access, kept for old callers
+#[deprecated(note = "use the typed SysReg accessors, e.g. SctlrEl1::write")]
 pub unsafe fn write_sysreg(reg: &str, val: u64) {
     match reg {
-        "vbar_el1" => asm!("msr vbar_el1, {}", in(reg) val),
-        "ttbr0_el1" => asm!("msr ttbr0_el1, {}", in(reg) val),
-        "tcr_el1" => asm!("msr tcr_el1, {}", in(reg) val),
-        "mair_el1" => asm!("msr mair_el1, {}", in(reg) val),
-        "sctlr_el1" => asm!("msr sctlr_el1, {}", in(reg) val),
+        "vbar_el1" => VbarEl1::write(val),
+        "ttbr0_el1" => Ttbr0El1::write(val),
+        "tcr_el1" => TcrEl1::write(val),
+        "mair_el1" => MairEl1::write(val),
+        "sctlr_el1" => SctlrEl1::write(val),
         _ => panic!("Unsupported system register write"),
     }
 }
 
+#[deprecated(note = "use the typed SysReg accessors, e.g. SctlrEl1::read")]
 pub unsafe fn read_sysreg(reg: &str) -> u64 {
-    let val: u64;
     match reg {
-        "vbar_el1" => asm!("mrs {}, vbar_el1", out(reg) val),
-        "ttbr0_el1" => asm!("mrs {}, ttbr0_el1", out(reg) val),
-        "tcr_el1" => asm!("mrs {}, tcr_el1", out(reg) val),
-        "mair_el1" => asm!("mrs {}, mair_el1", out(reg) val),
-        "sctlr_el1" => asm!("mrs {}, sctlr_el1", out(reg) val),
+        "vbar_el1" => VbarEl1::read(),
+        "ttbr0_el1" => Ttbr0El1::read(),
+        "tcr_el1" => TcrEl1::read(),
+        "mair_el1" => MairEl1::read(),
+        "sctlr_el1" => SctlrEl1::read(),
         _ => panic!("Unsupported system register read"),
     }
-    val
 }
\ No newline at end of file
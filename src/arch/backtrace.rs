@@ -0,0 +1,59 @@
+// Frame-pointer backtraces
+// Walks the AAPCS64 frame record chain: x29 points at {previous x29, saved
+// LR}. This only works when every frame keeps its record, so the target
+// rustflags in .cargo/config.toml build with -C force-frame-pointers=yes.
+// Addresses are printed one per line, ready to paste into
+// `addr2line -e <elf> -f -C`.
+
+use core::arch::asm;
+use core::fmt::Write;
+use crate::drivers::uart;
+use super::mem;
+
+// Deepest chain followed, so a corrupted stack cannot loop forever
+const MAX_FRAMES: usize = 32;
+
+// Size of a frame record (saved x29 and LR)
+const FRAME_RECORD_SIZE: usize = 16;
+
+// A frame record must sit on the boot stack or on a task stack (heap)
+fn frame_in_stack(fp: usize) -> bool {
+    fp % 8 == 0 &&
+        (mem::stack_region().contains(fp, FRAME_RECORD_SIZE) ||
+         mem::heap_region().contains(fp, FRAME_RECORD_SIZE))
+}
+
+// Print the return addresses of the callers of this function
+// Goes straight to the UART so it is usable from the panic handler
+#[inline(never)]
+pub fn print_backtrace() {
+    let mut fp: usize;
+    unsafe {
+        asm!("mov {}, x29", out(reg) fp, options(nomem, nostack));
+    }
+
+    uart::puts("Backtrace:\r\n");
+    for depth in 0..MAX_FRAMES {
+        if !frame_in_stack(fp) {
+            return;
+        }
+
+        let (next_fp, lr) = unsafe {
+            let record = fp as *const usize;
+            (record.read(), record.add(1).read())
+        };
+        if lr == 0 {
+            return;
+        }
+
+        // LR is the return address; the call itself is the instruction before
+        let _ = write!(uart::RawWriter, "  #{:<2} 0x{:016x}\r\n", depth, lr.saturating_sub(4));
+
+        // Frames grow down, so callers' records are at higher addresses
+        if next_fp <= fp {
+            return;
+        }
+        fp = next_fp;
+    }
+    uart::puts("  ... (truncated)\r\n");
+}
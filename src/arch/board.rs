@@ -0,0 +1,97 @@
+// Board memory map and clock configuration
+// All peripheral base addresses and clock rates live here so that porting to
+// a sibling SoC (or a board with a different memory map) is a one-file change.
+// The active board is selected with a cargo feature.
+
+// Upper bound on cores across all supported boards, for sizing per-core arrays
+pub const MAX_CORES: usize = 8;
+
+// Peripheral bases and clocks for one board
+pub struct BoardConfig {
+    pub name: &'static str,
+    pub uart_base: usize,           // LinFLEX UART0 base address
+    pub uart1_base: usize,          // LinFLEX UART1 base address
+    pub uart_clock_hz: u32,         // Nominal LinFLEX baud clock (see arch/clocks.rs)
+    pub gic_dist_base: usize,       // GIC-500 Distributor
+    pub gic_redist_base: usize,     // GIC-500 Redistributor for core 0
+    pub gic_redist_stride: usize,   // Distance between per-core redistributors
+    pub stm_base: usize,            // System Timer Module 0
+    pub stm_clock_hz: u64,          // Nominal STM counter clock (see arch/clocks.rs)
+    pub siul2_base: usize,          // SIUL2_0 pin mux and GPIO controller
+    pub mc_cgm0_base: usize,        // Clock Generation Module 0 (peripheral clock muxes)
+    pub periph_pll_base: usize,     // PERIPH_PLL (PLLDIG)
+    pub fxosc_hz: u64,              // External crystal oscillator
+    pub swt_base: usize,            // Software Watchdog Timer for the Cortex-A53 cluster
+    pub swt_clock_hz: u32,          // SWT counter clock (FIRC)
+    pub spi0_base: usize,           // SPI0 (DSPI) controller
+    pub spi_clock_hz: u32,          // SPI module clock
+    pub i2c0_base: usize,           // I2C0 controller
+    pub i2c_clock_hz: u32,          // I2C module clock
+    pub can0_base: usize,           // FlexCAN0 controller
+    pub can_clock_hz: u32,          // Nominal FlexCAN protocol engine clock (see arch/clocks.rs)
+    pub num_cores: usize,           // Number of Cortex-A53 cores
+    pub cores_per_cluster: usize,   // Cores sharing one MPIDR Aff1 value
+}
+
+#[cfg(all(feature = "s32g3", feature = "s32g2"))]
+compile_error!("Only one board feature (s32g3 or s32g2) may be enabled");
+
+#[cfg(not(any(feature = "s32g3", feature = "s32g2")))]
+compile_error!("A board feature (s32g3 or s32g2) must be enabled");
+
+// NXP S32G3: 2 clusters of 4 Cortex-A53
+#[cfg(feature = "s32g3")]
+pub const BOARD: BoardConfig = BoardConfig {
+    name: "S32G3",
+    uart_base: 0x401C8000,
+    uart1_base: 0x401CC000,
+    uart_clock_hz: 80_000_000,
+    gic_dist_base: 0x50800000,
+    gic_redist_base: 0x50880000,
+    gic_redist_stride: 0x20000,
+    stm_base: 0x40054000,
+    stm_clock_hz: 80_000_000,
+    siul2_base: 0x4009C000,
+    mc_cgm0_base: 0x40030000,
+    periph_pll_base: 0x4003C000,
+    fxosc_hz: 40_000_000,
+    swt_base: 0x40100000,
+    swt_clock_hz: 48_000_000,
+    spi0_base: 0x401D4000,
+    spi_clock_hz: 80_000_000,
+    i2c0_base: 0x401E4000,
+    i2c_clock_hz: 133_333_333,
+    can0_base: 0x401B4000,
+    can_clock_hz: 80_000_000,
+    num_cores: 8,
+    cores_per_cluster: 4,
+};
+
+// NXP S32G2: 2 clusters of 2 Cortex-A53, same peripheral map as S32G3
+// Not defined alongside S32G3, so enabling both only hits the compile_error
+#[cfg(all(feature = "s32g2", not(feature = "s32g3")))]
+pub const BOARD: BoardConfig = BoardConfig {
+    name: "S32G2",
+    uart_base: 0x401C8000,
+    uart1_base: 0x401CC000,
+    uart_clock_hz: 80_000_000,
+    gic_dist_base: 0x50800000,
+    gic_redist_base: 0x50880000,
+    gic_redist_stride: 0x20000,
+    stm_base: 0x40054000,
+    stm_clock_hz: 80_000_000,
+    siul2_base: 0x4009C000,
+    mc_cgm0_base: 0x40030000,
+    periph_pll_base: 0x4003C000,
+    fxosc_hz: 40_000_000,
+    swt_base: 0x40100000,
+    swt_clock_hz: 48_000_000,
+    spi0_base: 0x401D4000,
+    spi_clock_hz: 80_000_000,
+    i2c0_base: 0x401E4000,
+    i2c_clock_hz: 133_333_333,
+    can0_base: 0x401B4000,
+    can_clock_hz: 80_000_000,
+    num_cores: 4,
+    cores_per_cluster: 2,
+};
@@ -0,0 +1,116 @@
+// Peripheral clock frequencies
+// Rates are decoded at runtime from the MC_CGM mux and divider settings left
+// by the bootloader, following the selected source back to FIRC, FXOSC or a
+// PERIPH_PLL output. Boards clocked differently then get correct baud rates
+// and tick periods without touching the board file. If a mux selects a source
+// this module does not model (or reads back nonsense), the nominal rate from
+// the board configuration is used instead.
+
+use core::ptr::read_volatile;
+use core::sync::atomic::{AtomicU64, Ordering};
+use super::board::BOARD;
+use super::s32g3::{
+    MC_CGM_MUX_CSS, MC_CGM_MUX_DC0, MC_CGM_MUX_STRIDE, MC_CGM_MUX_PER,
+    MC_CGM_MUX_CAN_PE, MC_CGM_MUX_LIN_BAUD,
+    MUX_CSS_SELSTAT_SHIFT, MUX_CSS_SELSTAT_MASK, MUX_DC_DE, MUX_DC_DIV_SHIFT, MUX_DC_DIV_MASK,
+    CGM_SRC_FIRC, CGM_SRC_FXOSC, CGM_SRC_PERIPH_PLL_PHI0, PERIPH_PLL_NUM_PHI, FIRC_HZ,
+    PLLDIG_PLLCR, PLLDIG_PLLDV, PLLDIG_PLLFD, PLLDIG_PLLCLKMUX, PLLDIG_PLLODIV0,
+    PLLCR_PLLPD, PLLDV_MFI_MASK, PLLDV_RDIV_SHIFT, PLLDV_RDIV_MASK, PLLFD_MFN_MASK,
+    PLLFD_MFN_DEN, PLLCLKMUX_FXOSC, PLLODIV_DE, PLLODIV_DIV_SHIFT, PLLODIV_DIV_MASK
+};
+
+// Decoded rates, zero until first queried
+// The bootloader sets the clock tree up once, so the first decode is reused.
+static UART_CLOCK_HZ: AtomicU64 = AtomicU64::new(0);
+static STM_CLOCK_HZ: AtomicU64 = AtomicU64::new(0);
+static CAN_CLOCK_HZ: AtomicU64 = AtomicU64::new(0);
+
+fn read_reg(base: usize, offset: usize) -> u32 {
+    unsafe { read_volatile((base + offset) as *const u32) }
+}
+
+// Output frequency of PERIPH_PLL PHI `phi`, None if the PLL or output is off
+fn periph_pll_phi_hz(phi: u32) -> Option<u64> {
+    let base = BOARD.periph_pll_base;
+    if read_reg(base, PLLDIG_PLLCR) & PLLCR_PLLPD != 0 {
+        return None;
+    }
+
+    let reference = if read_reg(base, PLLDIG_PLLCLKMUX) & PLLCLKMUX_FXOSC != 0 {
+        BOARD.fxosc_hz
+    } else {
+        FIRC_HZ
+    };
+
+    let dv = read_reg(base, PLLDIG_PLLDV);
+    let mfi = (dv & PLLDV_MFI_MASK) as u64;
+    let rdiv = ((dv >> PLLDV_RDIV_SHIFT) & PLLDV_RDIV_MASK).max(1) as u64;
+    let mfn = (read_reg(base, PLLDIG_PLLFD) & PLLFD_MFN_MASK) as u64;
+
+    // VCO = reference * (MFI + MFN / 18432) / RDIV
+    let vco = reference * (mfi * PLLFD_MFN_DEN + mfn) / (PLLFD_MFN_DEN * rdiv);
+
+    let odiv = read_reg(base, PLLDIG_PLLODIV0 + phi as usize * 4);
+    if odiv & PLLODIV_DE == 0 {
+        return None;
+    }
+    Some(vco / (((odiv >> PLLODIV_DIV_SHIFT) & PLLODIV_DIV_MASK) as u64 + 1))
+}
+
+// Frequency of an MC_CGM clock source
+fn source_hz(source: u32) -> Option<u64> {
+    match source {
+        CGM_SRC_FIRC => Some(FIRC_HZ),
+        CGM_SRC_FXOSC => Some(BOARD.fxosc_hz),
+        s if (CGM_SRC_PERIPH_PLL_PHI0..CGM_SRC_PERIPH_PLL_PHI0 + PERIPH_PLL_NUM_PHI).contains(&s) => {
+            periph_pll_phi_hz(s - CGM_SRC_PERIPH_PLL_PHI0)
+        }
+        _ => None,
+    }
+}
+
+// Output frequency of MC_CGM_0 mux `mux`
+// `divided` says whether the mux has a DC_0 divider behind it
+fn mux_hz(mux: usize, divided: bool) -> Option<u64> {
+    let base = BOARD.mc_cgm0_base + mux * MC_CGM_MUX_STRIDE;
+    let css = read_reg(base, MC_CGM_MUX_CSS);
+    let hz = source_hz((css >> MUX_CSS_SELSTAT_SHIFT) & MUX_CSS_SELSTAT_MASK)?;
+
+    if !divided {
+        return Some(hz);
+    }
+    let dc = read_reg(base, MC_CGM_MUX_DC0);
+    if dc & MUX_DC_DE == 0 {
+        return None;
+    }
+    Some(hz / (((dc >> MUX_DC_DIV_SHIFT) & MUX_DC_DIV_MASK) as u64 + 1))
+}
+
+// Decode a rate once, falling back to the nominal value
+fn cached(slot: &AtomicU64, decode: impl FnOnce() -> Option<u64>, nominal: u64) -> u64 {
+    let hz = slot.load(Ordering::Relaxed);
+    if hz != 0 {
+        return hz;
+    }
+
+    let hz = decode().filter(|&hz| hz != 0).unwrap_or(nominal);
+    slot.store(hz, Ordering::Relaxed);
+    hz
+}
+
+// LinFLEX baud clock (LIN_BAUD_CLK)
+pub fn uart_clock_hz() -> u32 {
+    let hz = cached(&UART_CLOCK_HZ, || mux_hz(MC_CGM_MUX_LIN_BAUD, false), BOARD.uart_clock_hz as u64);
+    hz as u32
+}
+
+// STM counter clock (PER_CLK)
+pub fn stm_clock_hz() -> u64 {
+    cached(&STM_CLOCK_HZ, || mux_hz(MC_CGM_MUX_PER, true), BOARD.stm_clock_hz)
+}
+
+// FlexCAN protocol engine clock (CAN_PE_CLK)
+pub fn can_clock_hz() -> u32 {
+    let hz = cached(&CAN_CLOCK_HZ, || mux_hz(MC_CGM_MUX_CAN_PE, false), BOARD.can_clock_hz as u64);
+    hz as u32
+}
@@ -0,0 +1,48 @@
+// Atomic set of core positions, one bit per core
+// Used to track which cores are online; "all cores up" checks are a single
+// atomic load and a popcount.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+pub struct CoreSet {
+    bits: AtomicU64,
+}
+
+impl CoreSet {
+    // Create an empty set
+    pub const fn new() -> Self {
+        CoreSet {
+            bits: AtomicU64::new(0),
+        }
+    }
+
+    // Bit for a core position, zero if out of range
+    fn bit(pos: usize) -> u64 {
+        if pos < 64 { 1 << pos } else { 0 }
+    }
+
+    // Mark a core as online
+    pub fn mark_online(&self, pos: usize) {
+        self.bits.fetch_or(Self::bit(pos), Ordering::AcqRel);
+    }
+
+    // Mark a core as offline
+    pub fn mark_offline(&self, pos: usize) {
+        self.bits.fetch_and(!Self::bit(pos), Ordering::AcqRel);
+    }
+
+    // Check if a core is online
+    pub fn is_online(&self, pos: usize) -> bool {
+        self.bits.load(Ordering::Acquire) & Self::bit(pos) != 0
+    }
+
+    // Number of online cores
+    pub fn online_count(&self) -> u32 {
+        self.bits.load(Ordering::Acquire).count_ones()
+    }
+
+    // Raw bitmask of online cores
+    pub fn mask(&self) -> u64 {
+        self.bits.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,83 @@
+// Buffers for peripheral DMA
+// DRAM is mapped cacheable, so a DMA buffer stays cached and the driver
+// hands ownership back and forth with flush (before the device reads) and
+// invalidate (after the device wrote). Buffers are whole pages, so no cache
+// line is shared with other heap data and an invalidate cannot discard
+// someone else's writes. Memory is identity mapped, so va == pa for now.
+
+use alloc::alloc::{alloc_zeroed, dealloc, Layout};
+use super::aarch64;
+
+const PAGE_SIZE: usize = 4096;
+
+// A page-aligned buffer a device may access
+pub struct DmaBuffer {
+    va: usize,
+    pa: usize,
+    len: usize,     // Rounded up to whole pages
+}
+
+impl DmaBuffer {
+    // Address for the CPU
+    pub fn va(&self) -> usize {
+        self.va
+    }
+
+    // Address to program into the device
+    pub fn pa(&self) -> usize {
+        self.pa
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.va as *const u8, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.va as *mut u8, self.len) }
+    }
+
+    // Write the CPU's changes out to memory before the device reads them
+    pub fn flush(&self) {
+        aarch64::clean_dcache_range(self.va, self.len);
+    }
+
+    // Drop stale cached data before the CPU reads what the device wrote
+    // Any CPU writes since the last flush are lost.
+    pub fn invalidate(&self) {
+        unsafe { aarch64::invalidate_dcache_range(self.va, self.len) };
+    }
+}
+
+fn layout(len: usize) -> Option<Layout> {
+    Layout::from_size_align(len, PAGE_SIZE).ok()
+}
+
+// Allocate a zeroed DMA buffer of at least `size` bytes
+// The zeroes are already flushed. Returns None for a zero size or when the
+// heap has no room.
+pub fn alloc_coherent(size: usize) -> Option<DmaBuffer> {
+    if size == 0 {
+        return None;
+    }
+    let len = size.checked_next_multiple_of(PAGE_SIZE)?;
+    let va = unsafe { alloc_zeroed(layout(len)?) } as usize;
+    if va == 0 {
+        return None;
+    }
+
+    let buffer = DmaBuffer { va, pa: va, len };
+    buffer.flush();
+    Some(buffer)
+}
+
+// Give a buffer back to the heap
+// The device must be done with it.
+pub fn free_coherent(buffer: DmaBuffer) {
+    if let Some(layout) = layout(buffer.len) {
+        unsafe { dealloc(buffer.va as *mut u8, layout) };
+    }
+}
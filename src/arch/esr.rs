@@ -0,0 +1,299 @@
+// Exception Syndrome Register (ESR_EL1) decoding
+// See ARM Architecture Reference Manual ARMv8, section D13.2.37
+
+use core::arch::asm;
+use core::fmt;
+
+// Exception classes (ESR_EL1.EC)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ExceptionClass {
+    Unknown,                // 0x00
+    WfiWfe,                 // 0x01
+    SimdFp,                 // 0x07
+    IllegalState,           // 0x0E
+    Svc64,                  // 0x15
+    Hvc64,                  // 0x16
+    Smc64,                  // 0x17
+    SysReg,                 // 0x18
+    InstrAbortLower,        // 0x20
+    InstrAbortSame,         // 0x21
+    PcAlignment,            // 0x22
+    DataAbortLower,         // 0x24
+    DataAbortSame,          // 0x25
+    SpAlignment,            // 0x26
+    SError,                 // 0x2F
+    BreakpointLower,        // 0x30
+    BreakpointSame,         // 0x31
+    SoftwareStepLower,      // 0x32
+    SoftwareStepSame,       // 0x33
+    WatchpointLower,        // 0x34
+    WatchpointSame,         // 0x35
+    Brk64,                  // 0x3C
+    Other(u8),
+}
+
+impl ExceptionClass {
+    pub fn from_bits(ec: u8) -> Self {
+        match ec {
+            0x00 => ExceptionClass::Unknown,
+            0x01 => ExceptionClass::WfiWfe,
+            0x07 => ExceptionClass::SimdFp,
+            0x0E => ExceptionClass::IllegalState,
+            0x15 => ExceptionClass::Svc64,
+            0x16 => ExceptionClass::Hvc64,
+            0x17 => ExceptionClass::Smc64,
+            0x18 => ExceptionClass::SysReg,
+            0x20 => ExceptionClass::InstrAbortLower,
+            0x21 => ExceptionClass::InstrAbortSame,
+            0x22 => ExceptionClass::PcAlignment,
+            0x24 => ExceptionClass::DataAbortLower,
+            0x25 => ExceptionClass::DataAbortSame,
+            0x26 => ExceptionClass::SpAlignment,
+            0x2F => ExceptionClass::SError,
+            0x30 => ExceptionClass::BreakpointLower,
+            0x31 => ExceptionClass::BreakpointSame,
+            0x32 => ExceptionClass::SoftwareStepLower,
+            0x33 => ExceptionClass::SoftwareStepSame,
+            0x34 => ExceptionClass::WatchpointLower,
+            0x35 => ExceptionClass::WatchpointSame,
+            0x3C => ExceptionClass::Brk64,
+            other => ExceptionClass::Other(other),
+        }
+    }
+
+    // Human-readable description
+    pub fn description(&self) -> &'static str {
+        match self {
+            ExceptionClass::Unknown => "Unknown reason",
+            ExceptionClass::WfiWfe => "Trapped WFI/WFE",
+            ExceptionClass::SimdFp => "Trapped SIMD/FP access",
+            ExceptionClass::IllegalState => "Illegal execution state",
+            ExceptionClass::Svc64 => "SVC instruction execution in AArch64",
+            ExceptionClass::Hvc64 => "HVC instruction execution in AArch64",
+            ExceptionClass::Smc64 => "SMC instruction execution in AArch64",
+            ExceptionClass::SysReg => "Trapped system register access",
+            ExceptionClass::InstrAbortLower => "Instruction abort from lower EL",
+            ExceptionClass::InstrAbortSame => "Instruction abort from current EL",
+            ExceptionClass::PcAlignment => "PC alignment fault",
+            ExceptionClass::DataAbortLower => "Data abort from lower EL",
+            ExceptionClass::DataAbortSame => "Data abort from current EL",
+            ExceptionClass::SpAlignment => "SP alignment fault",
+            ExceptionClass::SError => "SError interrupt",
+            ExceptionClass::BreakpointLower => "Breakpoint from lower EL",
+            ExceptionClass::BreakpointSame => "Breakpoint from current EL",
+            ExceptionClass::SoftwareStepLower => "Software step from lower EL",
+            ExceptionClass::SoftwareStepSame => "Software step from current EL",
+            ExceptionClass::WatchpointLower => "Watchpoint from lower EL",
+            ExceptionClass::WatchpointSame => "Watchpoint from current EL",
+            ExceptionClass::Brk64 => "BRK instruction execution in AArch64",
+            ExceptionClass::Other(_) => "Unrecognized exception class",
+        }
+    }
+}
+
+// Raw ESR_EL1 value
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Esr(pub u64);
+
+impl Esr {
+    // Read ESR_EL1 for the exception being handled
+    pub fn read() -> Self {
+        let esr: u64;
+        unsafe {
+            asm!(
+                "mrs {x}, esr_el1",
+                x = out(reg) esr,
+                options(nostack)
+            );
+        }
+        Esr(esr)
+    }
+
+    // Raw exception class field, bits [31:26]
+    pub fn ec_bits(&self) -> u8 {
+        ((self.0 >> 26) & 0x3F) as u8
+    }
+
+    // Exception class
+    pub fn ec(&self) -> ExceptionClass {
+        ExceptionClass::from_bits(self.ec_bits())
+    }
+
+    // Instruction length: true for a 32-bit instruction, bit [25]
+    pub fn il(&self) -> bool {
+        (self.0 >> 25) & 1 != 0
+    }
+
+    // Instruction specific syndrome, bits [24:0]
+    pub fn iss(&self) -> u32 {
+        (self.0 & 0x1FF_FFFF) as u32
+    }
+
+    // Decode the ISS for a data abort, None for other classes
+    pub fn data_abort(&self) -> Option<DataAbortIss> {
+        match self.ec() {
+            ExceptionClass::DataAbortLower | ExceptionClass::DataAbortSame => Some(DataAbortIss(self.iss())),
+            _ => None,
+        }
+    }
+
+    // Decode the ISS for an instruction abort, None for other classes
+    pub fn instr_abort(&self) -> Option<InstrAbortIss> {
+        match self.ec() {
+            ExceptionClass::InstrAbortLower | ExceptionClass::InstrAbortSame => Some(InstrAbortIss(self.iss())),
+            _ => None,
+        }
+    }
+
+    // Immediate of an SVC/HVC/SMC/BRK instruction, None for other classes
+    pub fn imm16(&self) -> Option<u16> {
+        match self.ec() {
+            ExceptionClass::Svc64 | ExceptionClass::Hvc64 | ExceptionClass::Smc64 | ExceptionClass::Brk64 => {
+                Some((self.iss() & 0xFFFF) as u16)
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Esr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ESR=0x{:08X} EC=0x{:02X} ({}) IL={} ISS=0x{:07X}",
+               self.0, self.ec_bits(), self.ec().description(), self.il() as u8, self.iss())?;
+        if let Some(da) = self.data_abort() {
+            write!(f, ", {}", da)?;
+        }
+        if let Some(ia) = self.instr_abort() {
+            write!(f, ", {}", ia)?;
+        }
+        if let Some(imm) = self.imm16() {
+            write!(f, ", imm16=0x{:04X}", imm)?;
+        }
+        Ok(())
+    }
+}
+
+// Data abort instruction specific syndrome
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DataAbortIss(pub u32);
+
+impl DataAbortIss {
+    // Data fault status code, bits [5:0]
+    pub fn dfsc(&self) -> u8 {
+        (self.0 & 0x3F) as u8
+    }
+
+    // Write not read, bit [6]
+    pub fn wnr(&self) -> bool {
+        (self.0 >> 6) & 1 != 0
+    }
+
+    // FAR not valid, bit [10]
+    pub fn fnv(&self) -> bool {
+        (self.0 >> 10) & 1 != 0
+    }
+
+    // External abort, bit [9]
+    pub fn ea(&self) -> bool {
+        (self.0 >> 9) & 1 != 0
+    }
+
+    // Instruction syndrome valid, bit [24]
+    pub fn isv(&self) -> bool {
+        (self.0 >> 24) & 1 != 0
+    }
+}
+
+impl fmt::Display for DataAbortIss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DFSC=0x{:02X} {} on {}", self.dfsc(), fault_status_description(self.dfsc()),
+               if self.wnr() { "write" } else { "read" })?;
+        if self.fnv() {
+            write!(f, " (FAR not valid)")?;
+        }
+        if self.ea() {
+            write!(f, " (external abort)")?;
+        }
+        Ok(())
+    }
+}
+
+// Instruction abort instruction specific syndrome
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct InstrAbortIss(pub u32);
+
+impl InstrAbortIss {
+    // Instruction fault status code, bits [5:0]
+    pub fn ifsc(&self) -> u8 {
+        (self.0 & 0x3F) as u8
+    }
+
+    // FAR not valid, bit [10]
+    pub fn fnv(&self) -> bool {
+        (self.0 >> 10) & 1 != 0
+    }
+
+    // External abort, bit [9]
+    pub fn ea(&self) -> bool {
+        (self.0 >> 9) & 1 != 0
+    }
+}
+
+impl fmt::Display for InstrAbortIss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IFSC=0x{:02X} {}", self.ifsc(), fault_status_description(self.ifsc()))?;
+        if self.fnv() {
+            write!(f, " (FAR not valid)")?;
+        }
+        if self.ea() {
+            write!(f, " (external abort)")?;
+        }
+        Ok(())
+    }
+}
+
+// Human-readable reason for a DFSC/IFSC fault status code
+// The low two bits of translation, access flag and permission faults give
+// the lookup level.
+pub fn fault_status_description(fsc: u8) -> &'static str {
+    match fsc {
+        0x00 => "address size fault at level 0",
+        0x01 => "address size fault at level 1",
+        0x02 => "address size fault at level 2",
+        0x03 => "address size fault at level 3",
+        0x04 => "translation fault at level 0",
+        0x05 => "translation fault at level 1",
+        0x06 => "translation fault at level 2",
+        0x07 => "translation fault at level 3",
+        0x09 => "access flag fault at level 1",
+        0x0A => "access flag fault at level 2",
+        0x0B => "access flag fault at level 3",
+        0x0D => "permission fault at level 1",
+        0x0E => "permission fault at level 2",
+        0x0F => "permission fault at level 3",
+        0x10 => "synchronous external abort",
+        0x11 => "synchronous tag check fault",
+        0x14..=0x17 => "synchronous external abort on table walk",
+        0x18 => "synchronous parity/ECC error",
+        0x1C..=0x1F => "parity/ECC error on table walk",
+        0x21 => "alignment fault",
+        0x30 => "TLB conflict abort",
+        0x31 => "unsupported atomic hardware update",
+        0x34 => "lockdown abort",
+        0x35 => "unsupported exclusive or atomic access",
+        _ => "reserved fault status",
+    }
+}
+
+// Faulting virtual address of the last synchronous abort
+pub fn read_far() -> u64 {
+    let far: u64;
+    unsafe {
+        asm!(
+            "mrs {x}, far_el1",
+            x = out(reg) far,
+            options(nostack)
+        );
+    }
+    far
+}
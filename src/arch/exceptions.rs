@@ -3,13 +3,99 @@
 
 use core::arch::global_asm;
 use core::arch::asm;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicPtr, Ordering};
 use crate::drivers::uart;
-use crate::arch::gic;
+use crate::freertos::port;
+use crate::arch::{gic, profile, psci};
+use crate::arch::esr::{self, Esr, ExceptionClass};
+use crate::arch::s32g3::{self, timer};
 
 // Define exception vector table for AArch64
 global_asm!(
     // Ensure the section is correctly defined
     ".section .text.exceptions, \"ax\"",
+    
+    // Save x0-x30, sp_el0, elr_el1 and spsr_el1 as a TrapFrame on the stack
+    // (offsets come from the TrapFrame definition below)
+    ".macro save_context",
+    "   sub sp, sp, #{frame_size}",
+    "   stp x0, x1, [sp, #16 * 0]",
+    "   stp x2, x3, [sp, #16 * 1]",
+    "   stp x4, x5, [sp, #16 * 2]",
+    "   stp x6, x7, [sp, #16 * 3]",
+    "   stp x8, x9, [sp, #16 * 4]",
+    "   stp x10, x11, [sp, #16 * 5]",
+    "   stp x12, x13, [sp, #16 * 6]",
+    "   stp x14, x15, [sp, #16 * 7]",
+    "   stp x16, x17, [sp, #16 * 8]",
+    "   stp x18, x19, [sp, #16 * 9]",
+    "   stp x20, x21, [sp, #16 * 10]",
+    "   stp x22, x23, [sp, #16 * 11]",
+    "   stp x24, x25, [sp, #16 * 12]",
+    "   stp x26, x27, [sp, #16 * 13]",
+    "   stp x28, x29, [sp, #16 * 14]",
+    "   str x30, [sp, #{off_x30}]",
+    "   mrs x21, sp_el0",
+    "   mrs x22, elr_el1",
+    "   mrs x23, spsr_el1",
+    "   str x21, [sp, #{off_sp_el0}]",
+    "   str x22, [sp, #{off_elr}]",
+    "   str x23, [sp, #{off_spsr}]",
+    ".endm",
+    
+    // Restore the TrapFrame saved by save_context
+    ".macro restore_context",
+    "   ldr x21, [sp, #{off_sp_el0}]",
+    "   ldr x22, [sp, #{off_elr}]",
+    "   ldr x23, [sp, #{off_spsr}]",
+    "   msr sp_el0, x21",
+    "   msr elr_el1, x22",
+    "   msr spsr_el1, x23",
+    "   ldr x30, [sp, #{off_x30}]",
+    "   ldp x0, x1, [sp, #16 * 0]",
+    "   ldp x2, x3, [sp, #16 * 1]",
+    "   ldp x4, x5, [sp, #16 * 2]",
+    "   ldp x6, x7, [sp, #16 * 3]",
+    "   ldp x8, x9, [sp, #16 * 4]",
+    "   ldp x10, x11, [sp, #16 * 5]",
+    "   ldp x12, x13, [sp, #16 * 6]",
+    "   ldp x14, x15, [sp, #16 * 7]",
+    "   ldp x16, x17, [sp, #16 * 8]",
+    "   ldp x18, x19, [sp, #16 * 9]",
+    "   ldp x20, x21, [sp, #16 * 10]",
+    "   ldp x22, x23, [sp, #16 * 11]",
+    "   ldp x24, x25, [sp, #16 * 12]",
+    "   ldp x26, x27, [sp, #16 * 13]",
+    "   ldp x28, x29, [sp, #16 * 14]",
+    "   add sp, sp, #{frame_size}",
+    ".endm",
+    
+    // Save state, call the Rust handler with a pointer to the TrapFrame, restore
+    ".macro exception_entry handler",
+    "   save_context",
+    "   mov x0, sp",
+    "   bl \\handler",
+    "   restore_context",
+    "   eret",
+    ".endm",
+    
+    // As exception_entry, but gives the scheduler a chance to switch tasks on
+    // the way out: port_switch_context returns the frame to resume, which may
+    // be on another task's stack. port_switch_finish runs once sp is off the
+    // old task's stack, so another core cannot resume that task before then.
+    ".macro exception_entry_switch handler",
+    "   save_context",
+    "   mov x0, sp",
+    "   bl \\handler",
+    "   mov x0, sp",
+    "   bl port_switch_context",
+    "   mov sp, x0",
+    "   bl port_switch_finish",
+    "   restore_context",
+    "   eret",
+    ".endm",
+    
     ".align 11",  // 2048-byte alignment for vector table
     
     // Vector table must be 2048 bytes
@@ -62,240 +148,161 @@ global_asm!(
     
     // Exception handlers
     "el1_sp0_sync:",
-    "   // Save the current state",
-    "   sub sp, sp, #16 * 17",  // Space for x0-x30, elr_el1, spsr_el1
-    "   stp x0, x1, [sp, #16 * 0]",
-    "   stp x2, x3, [sp, #16 * 1]",
-    "   stp x4, x5, [sp, #16 * 2]",
-    "   stp x6, x7, [sp, #16 * 3]",
-    "   stp x8, x9, [sp, #16 * 4]",
-    "   stp x10, x11, [sp, #16 * 5]",
-    "   stp x12, x13, [sp, #16 * 6]",
-    "   stp x14, x15, [sp, #16 * 7]",
-    "   stp x16, x17, [sp, #16 * 8]",
-    "   stp x18, x19, [sp, #16 * 9]",
-    "   stp x20, x21, [sp, #16 * 10]",
-    "   stp x22, x23, [sp, #16 * 11]",
-    "   stp x24, x25, [sp, #16 * 12]",
-    "   stp x26, x27, [sp, #16 * 13]",
-    "   stp x28, x29, [sp, #16 * 14]",
-    "   mrs x21, elr_el1",
-    "   mrs x22, spsr_el1",
-    "   stp x30, x21, [sp, #16 * 15]",
-    "   str x22, [sp, #16 * 16]",
-    "   bl exception_handler_sp0_sync",
-    "   // Restore state",
-    "   ldp x30, x21, [sp, #16 * 15]",
-    "   ldr x22, [sp, #16 * 16]",
-    "   msr elr_el1, x21",
-    "   msr spsr_el1, x22",
-    "   ldp x0, x1, [sp, #16 * 0]",
-    "   ldp x2, x3, [sp, #16 * 1]",
-    "   ldp x4, x5, [sp, #16 * 2]",
-    "   ldp x6, x7, [sp, #16 * 3]",
-    "   ldp x8, x9, [sp, #16 * 4]",
-    "   ldp x10, x11, [sp, #16 * 5]",
-    "   ldp x12, x13, [sp, #16 * 6]",
-    "   ldp x14, x15, [sp, #16 * 7]",
-    "   ldp x16, x17, [sp, #16 * 8]",
-    "   ldp x18, x19, [sp, #16 * 9]",
-    "   ldp x20, x21, [sp, #16 * 10]",
-    "   ldp x22, x23, [sp, #16 * 11]",
-    "   ldp x24, x25, [sp, #16 * 12]",
-    "   ldp x26, x27, [sp, #16 * 13]",
-    "   ldp x28, x29, [sp, #16 * 14]",
-    "   add sp, sp, #16 * 17",
-    "   eret",
+    "   exception_entry exception_handler_sp0_sync",
     
     "el1_sp0_irq:",
-    "   // Save the current state",
-    "   sub sp, sp, #16 * 17",  // Space for x0-x30, elr_el1, spsr_el1
-    "   stp x0, x1, [sp, #16 * 0]",
-    "   stp x2, x3, [sp, #16 * 1]",
-    "   stp x4, x5, [sp, #16 * 2]",
-    "   stp x6, x7, [sp, #16 * 3]",
-    "   stp x8, x9, [sp, #16 * 4]",
-    "   stp x10, x11, [sp, #16 * 5]",
-    "   stp x12, x13, [sp, #16 * 6]",
-    "   stp x14, x15, [sp, #16 * 7]",
-    "   stp x16, x17, [sp, #16 * 8]",
-    "   stp x18, x19, [sp, #16 * 9]",
-    "   stp x20, x21, [sp, #16 * 10]",
-    "   stp x22, x23, [sp, #16 * 11]",
-    "   stp x24, x25, [sp, #16 * 12]",
-    "   stp x26, x27, [sp, #16 * 13]",
-    "   stp x28, x29, [sp, #16 * 14]",
-    "   mrs x21, elr_el1",
-    "   mrs x22, spsr_el1",
-    "   stp x30, x21, [sp, #16 * 15]",
-    "   str x22, [sp, #16 * 16]",
-    "   bl exception_handler_sp0_irq",
-    "   // Restore state",
-    "   ldp x30, x21, [sp, #16 * 15]",
-    "   ldr x22, [sp, #16 * 16]",
-    "   msr elr_el1, x21",
-    "   msr spsr_el1, x22",
-    "   ldp x0, x1, [sp, #16 * 0]",
-    "   ldp x2, x3, [sp, #16 * 1]",
-    "   ldp x4, x5, [sp, #16 * 2]",
-    "   ldp x6, x7, [sp, #16 * 3]",
-    "   ldp x8, x9, [sp, #16 * 4]",
-    "   ldp x10, x11, [sp, #16 * 5]",
-    "   ldp x12, x13, [sp, #16 * 6]",
-    "   ldp x14, x15, [sp, #16 * 7]",
-    "   ldp x16, x17, [sp, #16 * 8]",
-    "   ldp x18, x19, [sp, #16 * 9]",
-    "   ldp x20, x21, [sp, #16 * 10]",
-    "   ldp x22, x23, [sp, #16 * 11]",
-    "   ldp x24, x25, [sp, #16 * 12]",
-    "   ldp x26, x27, [sp, #16 * 13]",
-    "   ldp x28, x29, [sp, #16 * 14]",
-    "   add sp, sp, #16 * 17",
-    "   eret",
+    "   exception_entry exception_handler_sp0_irq",
     
     "el1_sp0_fiq:",
-    "   bl exception_handler_sp0_fiq",
-    "   eret",
+    "   exception_entry exception_handler_sp0_fiq",
     
     "el1_sp0_serror:",
-    "   bl exception_handler_sp0_serror",
-    "   eret",
+    "   exception_entry exception_handler_sp0_serror",
     
     "el1_sync:",
-    "   // Save the current state",
-    "   sub sp, sp, #16 * 17",
-    "   stp x0, x1, [sp, #16 * 0]",
-    "   stp x2, x3, [sp, #16 * 1]",
-    "   stp x4, x5, [sp, #16 * 2]",
-    "   stp x6, x7, [sp, #16 * 3]",
-    "   stp x8, x9, [sp, #16 * 4]",
-    "   stp x10, x11, [sp, #16 * 5]",
-    "   stp x12, x13, [sp, #16 * 6]",
-    "   stp x14, x15, [sp, #16 * 7]",
-    "   stp x16, x17, [sp, #16 * 8]",
-    "   stp x18, x19, [sp, #16 * 9]",
-    "   stp x20, x21, [sp, #16 * 10]",
-    "   stp x22, x23, [sp, #16 * 11]",
-    "   stp x24, x25, [sp, #16 * 12]",
-    "   stp x26, x27, [sp, #16 * 13]",
-    "   stp x28, x29, [sp, #16 * 14]",
-    "   mrs x21, elr_el1",
-    "   mrs x22, spsr_el1",
-    "   stp x30, x21, [sp, #16 * 15]",
-    "   str x22, [sp, #16 * 16]",
-    "   bl exception_handler_sync",
-    "   // Restore state",
-    "   ldp x30, x21, [sp, #16 * 15]",
-    "   ldr x22, [sp, #16 * 16]",
-    "   msr elr_el1, x21",
-    "   msr spsr_el1, x22",
-    "   ldp x0, x1, [sp, #16 * 0]",
-    "   ldp x2, x3, [sp, #16 * 1]",
-    "   ldp x4, x5, [sp, #16 * 2]",
-    "   ldp x6, x7, [sp, #16 * 3]",
-    "   ldp x8, x9, [sp, #16 * 4]",
-    "   ldp x10, x11, [sp, #16 * 5]",
-    "   ldp x12, x13, [sp, #16 * 6]",
-    "   ldp x14, x15, [sp, #16 * 7]",
-    "   ldp x16, x17, [sp, #16 * 8]",
-    "   ldp x18, x19, [sp, #16 * 9]",
-    "   ldp x20, x21, [sp, #16 * 10]",
-    "   ldp x22, x23, [sp, #16 * 11]",
-    "   ldp x24, x25, [sp, #16 * 12]",
-    "   ldp x26, x27, [sp, #16 * 13]",
-    "   ldp x28, x29, [sp, #16 * 14]",
-    "   add sp, sp, #16 * 17",
-    "   eret",
+    "   exception_entry_switch exception_handler_sync",
     
     "el1_irq:",
-    "   // Save the current state",
-    "   sub sp, sp, #16 * 17",
-    "   stp x0, x1, [sp, #16 * 0]",
-    "   stp x2, x3, [sp, #16 * 1]",
-    "   stp x4, x5, [sp, #16 * 2]",
-    "   stp x6, x7, [sp, #16 * 3]",
-    "   stp x8, x9, [sp, #16 * 4]",
-    "   stp x10, x11, [sp, #16 * 5]",
-    "   stp x12, x13, [sp, #16 * 6]",
-    "   stp x14, x15, [sp, #16 * 7]",
-    "   stp x16, x17, [sp, #16 * 8]",
-    "   stp x18, x19, [sp, #16 * 9]",
-    "   stp x20, x21, [sp, #16 * 10]",
-    "   stp x22, x23, [sp, #16 * 11]",
-    "   stp x24, x25, [sp, #16 * 12]",
-    "   stp x26, x27, [sp, #16 * 13]",
-    "   stp x28, x29, [sp, #16 * 14]",
-    "   mrs x21, elr_el1",
-    "   mrs x22, spsr_el1",
-    "   stp x30, x21, [sp, #16 * 15]",
-    "   str x22, [sp, #16 * 16]",
-    "   bl exception_handler_irq",
-    "   // Restore state",
-    "   ldp x30, x21, [sp, #16 * 15]",
-    "   ldr x22, [sp, #16 * 16]",
-    "   msr elr_el1, x21",
-    "   msr spsr_el1, x22",
-    "   ldp x0, x1, [sp, #16 * 0]",
-    "   ldp x2, x3, [sp, #16 * 1]",
-    "   ldp x4, x5, [sp, #16 * 2]",
-    "   ldp x6, x7, [sp, #16 * 3]",
-    "   ldp x8, x9, [sp, #16 * 4]",
-    "   ldp x10, x11, [sp, #16 * 5]",
-    "   ldp x12, x13, [sp, #16 * 6]",
-    "   ldp x14, x15, [sp, #16 * 7]",
-    "   ldp x16, x17, [sp, #16 * 8]",
-    "   ldp x18, x19, [sp, #16 * 9]",
-    "   ldp x20, x21, [sp, #16 * 10]",
-    "   ldp x22, x23, [sp, #16 * 11]",
-    "   ldp x24, x25, [sp, #16 * 12]",
-    "   ldp x26, x27, [sp, #16 * 13]",
-    "   ldp x28, x29, [sp, #16 * 14]",
-    "   add sp, sp, #16 * 17",
-    "   eret",
+    "   exception_entry_switch exception_handler_irq",
     
     "el1_fiq:",
-    "   bl exception_handler_fiq",
-    "   eret",
+    "   exception_entry exception_handler_fiq",
     
     "el1_serror:",
-    "   bl exception_handler_serror",
-    "   eret",
+    "   exception_entry exception_handler_serror",
     
     "lower_el_aarch64_sync:",
-    "   bl exception_handler_lower_sync",
-    "   eret",
+    "   exception_entry exception_handler_lower_sync",
     
     "lower_el_aarch64_irq:",
-    "   bl exception_handler_lower_irq",
-    "   eret",
+    "   exception_entry exception_handler_lower_irq",
     
     "lower_el_aarch64_fiq:",
-    "   bl exception_handler_lower_fiq",
-    "   eret",
+    "   exception_entry exception_handler_lower_fiq",
     
     "lower_el_aarch64_serror:",
-    "   bl exception_handler_lower_serror",
-    "   eret",
+    "   exception_entry exception_handler_lower_serror",
     
     "lower_el_aarch32_sync:",
-    "   bl exception_handler_lower32_sync",
-    "   eret",
+    "   exception_entry exception_handler_lower32_sync",
     
     "lower_el_aarch32_irq:",
-    "   bl exception_handler_lower32_irq",
-    "   eret",
+    "   exception_entry exception_handler_lower32_irq",
     
     "lower_el_aarch32_fiq:",
-    "   bl exception_handler_lower32_fiq",
-    "   eret",
+    "   exception_entry exception_handler_lower32_fiq",
     
     "lower_el_aarch32_serror:",
-    "   bl exception_handler_lower32_serror",
+    "   exception_entry exception_handler_lower32_serror",
+    
+    // Resume a TrapFrame at x0 with sp just above it; used to start the first task
+    ".global port_restore_context",
+    "port_restore_context:",
+    "   mov sp, x0",
+    "   restore_context",
     "   eret",
+    
+    frame_size = const TRAP_FRAME_SIZE,
+    off_x30 = const core::mem::offset_of!(TrapFrame, regs) + 30 * 8,
+    off_sp_el0 = const core::mem::offset_of!(TrapFrame, sp_el0),
+    off_elr = const core::mem::offset_of!(TrapFrame, elr),
+    off_spsr = const core::mem::offset_of!(TrapFrame, spsr),
 );
 
+// Register state saved by the vector entry
+// This is the only definition of the layout: save_context and
+// restore_context take their offsets from it, and x0-x29 are stored pairwise
+// from offset 0.
+#[repr(C)]
+pub struct TrapFrame {
+    pub regs: [u64; 31],    // x0-x30
+    pub sp_el0: u64,        // sp_el0
+    pub elr: u64,           // elr_el1
+    pub spsr: u64,          // spsr_el1
+}
+
+impl TrapFrame {
+    // Argument register `n` (x0-x7) of an SVC
+    pub fn arg(&self, n: usize) -> u64 {
+        assert!(n < 8, "SVC argument x{} out of range", n);
+        self.regs[n]
+    }
+
+    // SVC function number, passed in x8
+    pub fn syscall_number(&self) -> u64 {
+        self.regs[8]
+    }
+
+    // Set the value returned to the caller in x0
+    pub fn set_return(&mut self, value: u64) {
+        self.regs[0] = value;
+    }
+
+    // Step ELR past the faulting instruction, so resuming continues after it
+    pub fn skip_instruction(&mut self) {
+        self.elr += 4;
+    }
+}
+
+// Stack space save_context reserves for a TrapFrame (a multiple of 16 bytes)
+pub const TRAP_FRAME_SIZE: usize = core::mem::size_of::<TrapFrame>().next_multiple_of(16);
+
+// What to do after a user fault handler has inspected an exception
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FaultAction {
+    Panic,      // Panic with the exception details
+    Resume,     // Return to the frame's ELR; for a synchronous fault the
+                // handler must fix the cause or call skip_instruction, or
+                // the same instruction faults again
+    Reboot,     // Reset the system
+}
+
 // Exception handler typedefs
 pub type ExceptionHandler = fn() -> ();
+pub type FaultHandler = fn(&mut TrapFrame) -> FaultAction;
+
+// User fault handler, null when none is registered
+static FAULT_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+// Register a handler consulted for unhandled synchronous exceptions and SErrors
+pub fn set_fault_handler(handler: FaultHandler) {
+    FAULT_HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+// Remove the user fault handler, restoring the default (report and resume)
+pub fn clear_fault_handler() {
+    FAULT_HANDLER.store(core::ptr::null_mut(), Ordering::Release);
+}
+
+// Print the faulting address of an abort alongside the faulting instruction
+fn report_fault_address(far_valid: bool, frame: &TrapFrame) {
+    if far_valid {
+        let _ = write!(uart::RawWriter, "  FAR=0x{:016X}", esr::read_far());
+    } else {
+        uart::puts("  FAR not valid");
+    }
+    let _ = write!(uart::RawWriter, " ELR=0x{:016X}\r\n", frame.elr);
+}
+
+// Consult the user fault handler and apply its decision
+fn dispatch_fault(frame: &mut TrapFrame, description: &str) {
+    let handler = FAULT_HANDLER.load(Ordering::Acquire);
+    if handler.is_null() {
+        return;
+    }
+
+    let handler: FaultHandler = unsafe { core::mem::transmute(handler) };
+    match handler(frame) {
+        FaultAction::Resume => {},
+        FaultAction::Panic => {
+            panic!("{} at ELR=0x{:016X} SPSR=0x{:016X}", description, frame.elr, frame.spsr);
+        },
+        FaultAction::Reboot => {
+            uart::puts("Fault handler requested reboot\r\n");
+            psci::system_reset();
+        },
+    }
+}
 
 // Initialize exception vectors
 pub fn init_vectors() {
@@ -311,17 +318,35 @@ pub fn init_vectors() {
 }
 
 #[no_mangle]
-extern "C" fn exception_handler_irq() {
+extern "C" fn exception_handler_irq(_frame: &mut TrapFrame) {
+    let _span = profile::start("irq_dispatch");
+    
     // Get interrupt ID from GIC
     let irq_id = gic::get_interrupt_id();
     
-    // Check for spurious interrupt
-    if irq_id == 1023 {
+    // 1020-1023 are special IDs (1023 = spurious) and must not be EOI'd
+    if irq_id >= 1020 {
         return;
     }
     
-    // Handle the specific interrupt
-    handle_interrupt(irq_id);
+    // Registered handlers take precedence over the built-in cases
+    // Outside SMP builds IRQs are unmasked while the handler runs, so a more
+    // urgent interrupt (the GIC only signals those above this one's running
+    // priority) preempts it. The *_from_isr APIs raise the priority mask to
+    // port::MAX_SYSCALL_INTERRUPT_PRIORITY around kernel state, so only
+    // interrupts above that threshold fire inside them. SMP builds hold the
+    // kernel lock for the whole handler (see port::enter_isr), so handlers
+    // there do not nest.
+    port::enter_isr();
+    #[cfg(not(feature = "smp"))]
+    unsafe { crate::arch::aarch64::enable_irq() };
+    match gic::handler(irq_id) {
+        Some(handler) => handler(),
+        None => handle_interrupt(irq_id),
+    }
+    #[cfg(not(feature = "smp"))]
+    unsafe { crate::arch::aarch64::disable_irq() };
+    port::exit_isr();
     
     // Signal end of interrupt to GIC
     gic::end_of_interrupt(irq_id);
@@ -329,124 +354,132 @@ extern "C" fn exception_handler_irq() {
 
 // IRQ handler for SP0 mode
 #[no_mangle]
-extern "C" fn exception_handler_sp0_irq() {
+extern "C" fn exception_handler_sp0_irq(frame: &mut TrapFrame) {
     uart::puts("SP0 IRQ Exception\r\n");
-    exception_handler_irq();
+    exception_handler_irq(frame);
 }
 
 // IRQ handler for lower EL AArch64
 #[no_mangle]
-extern "C" fn exception_handler_lower_irq() {
+extern "C" fn exception_handler_lower_irq(frame: &mut TrapFrame) {
     uart::puts("Lower AArch64 IRQ Exception\r\n");
-    exception_handler_irq();
+    exception_handler_irq(frame);
 }
 
 // IRQ handler for lower EL AArch32
 #[no_mangle]
-extern "C" fn exception_handler_lower32_irq() {
+extern "C" fn exception_handler_lower32_irq(frame: &mut TrapFrame) {
     uart::puts("Lower AArch32 IRQ Exception\r\n");
-    exception_handler_irq();
+    exception_handler_irq(frame);
 }
 
 // FIQ handler
+// Nothing routes FIQs to EL1 yet, but count it as ISR context like an IRQ so
+// anything added here sees is_inside_isr() set
 #[no_mangle]
-extern "C" fn exception_handler_fiq() {
+extern "C" fn exception_handler_fiq(_frame: &mut TrapFrame) {
+    port::enter_isr();
     uart::puts("FIQ Exception\r\n");
+    port::exit_isr();
 }
 
 // SP0 FIQ handler
 #[no_mangle]
-extern "C" fn exception_handler_sp0_fiq() {
+extern "C" fn exception_handler_sp0_fiq(_frame: &mut TrapFrame) {
     uart::puts("SP0 FIQ Exception\r\n");
 }
 
 // Lower EL FIQ handler (AArch64)
 #[no_mangle]
-extern "C" fn exception_handler_lower_fiq() {
+extern "C" fn exception_handler_lower_fiq(_frame: &mut TrapFrame) {
     uart::puts("Lower AArch64 FIQ Exception\r\n");
 }
 
 // Lower EL FIQ handler (AArch32)
 #[no_mangle]
-extern "C" fn exception_handler_lower32_fiq() {
+extern "C" fn exception_handler_lower32_fiq(_frame: &mut TrapFrame) {
     uart::puts("Lower AArch32 FIQ Exception\r\n");
 }
 
 // Synchronous exception handler
 #[no_mangle]
-extern "C" fn exception_handler_sync() {
+extern "C" fn exception_handler_sync(frame: &mut TrapFrame) {
     // Read exception syndrome register
-    let esr: u64;
-    unsafe {
-        asm!(
-            "mrs {x}, esr_el1",
-            x = out(reg) esr,
-            options(nostack)
-        );
-    }
+    let esr = Esr::read();
     
-    // Extract exception class (EC) from ESR
-    let ec = (esr >> 26) & 0x3F;
+    // SVC is how tasks yield (see port::yield_task); the switch itself
+    // happens on the way out of the vector
+    if esr.ec() == ExceptionClass::Svc64 {
+        return;
+    }
     
     // Print information about the exception
-    uart::puts("Synchronous Exception: ESR=0x");
-    print_hex(esr);
+    uart::puts("Synchronous Exception: ");
+    let _ = write!(uart::RawWriter, "{}", esr);
     uart::puts("\r\n");
     
-    match ec {
-        0x15 => uart::puts("SVC instruction execution in AArch64\r\n"),
-        0x24 => uart::puts("Data abort from current EL\r\n"),
+    match esr.ec() {
+        ExceptionClass::DataAbortLower | ExceptionClass::DataAbortSame => {
+            report_fault_address(esr.data_abort().is_some_and(|da| !da.fnv()), frame);
+            dispatch_fault(frame, "Data abort");
+        },
+        ExceptionClass::InstrAbortLower | ExceptionClass::InstrAbortSame => {
+            report_fault_address(esr.instr_abort().is_some_and(|ia| !ia.fnv()), frame);
+            dispatch_fault(frame, "Instruction abort");
+        },
         _ => {
-            uart::puts("Unknown exception class: 0x");
-            print_hex(ec);
-            uart::puts("\r\n");
+            dispatch_fault(frame, "Unhandled synchronous exception");
         }
     }
 }
 
 // SP0 synchronous exception handler
 #[no_mangle]
-extern "C" fn exception_handler_sp0_sync() {
+extern "C" fn exception_handler_sp0_sync(frame: &mut TrapFrame) {
     uart::puts("SP0 Synchronous Exception\r\n");
-    exception_handler_sync();
+    exception_handler_sync(frame);
 }
 
 // Lower EL synchronous exception handler (AArch64)
 #[no_mangle]
-extern "C" fn exception_handler_lower_sync() {
+extern "C" fn exception_handler_lower_sync(frame: &mut TrapFrame) {
     uart::puts("Lower AArch64 Synchronous Exception\r\n");
-    exception_handler_sync();
+    exception_handler_sync(frame);
 }
 
 // Lower EL synchronous exception handler (AArch32)
 #[no_mangle]
-extern "C" fn exception_handler_lower32_sync() {
+extern "C" fn exception_handler_lower32_sync(frame: &mut TrapFrame) {
     uart::puts("Lower AArch32 Synchronous Exception\r\n");
-    exception_handler_sync();
+    exception_handler_sync(frame);
 }
 
 // SError handler
 #[no_mangle]
-extern "C" fn exception_handler_serror() {
+extern "C" fn exception_handler_serror(frame: &mut TrapFrame) {
     uart::puts("SError Exception\r\n");
+    dispatch_fault(frame, "SError");
 }
 
 // SP0 SError handler
 #[no_mangle]
-extern "C" fn exception_handler_sp0_serror() {
+extern "C" fn exception_handler_sp0_serror(frame: &mut TrapFrame) {
     uart::puts("SP0 SError Exception\r\n");
+    dispatch_fault(frame, "SError");
 }
 
 // Lower EL SError handler (AArch64)
 #[no_mangle]
-extern "C" fn exception_handler_lower_serror() {
+extern "C" fn exception_handler_lower_serror(frame: &mut TrapFrame) {
     uart::puts("Lower AArch64 SError Exception\r\n");
+    dispatch_fault(frame, "SError");
 }
 
 // Lower EL SError handler (AArch32)
 #[no_mangle]
-extern "C" fn exception_handler_lower32_serror() {
+extern "C" fn exception_handler_lower32_serror(frame: &mut TrapFrame) {
     uart::puts("Lower AArch32 SError Exception\r\n");
+    dispatch_fault(frame, "SError");
 }
 
 // Vector base address (defined in assembly)
@@ -463,6 +496,16 @@ fn handle_interrupt(irq_id: u32) {
             // Handle UART interrupt
         },
         
+        // Another core panicked
+        gic::SGI_STOP => {
+            gic::park_core();
+        },
+        
+        // STM0 compare interrupt, before the kernel registers its tick handler
+        s32g3::S32G_STM0_IRQ => {
+            timer::handle_tick();
+        },
+        
         // Timer interrupt
         27 => {
             uart::puts("Timer Interrupt received\r\n");
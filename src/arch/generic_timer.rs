@@ -0,0 +1,77 @@
+// ARM generic timer
+// The 64-bit system counter (CNTPCT_EL0) runs at the rate firmware programs
+// into CNTFRQ_EL0, so timekeeping built on it is wrap-free and correct on any
+// board without a hardcoded clock.
+
+use core::arch::asm;
+use super::aarch64::{CntkctlEl1, SysReg, SysRegWrite};
+
+// Target period of the event stream
+pub const EVENT_STREAM_US: u64 = 100;
+
+// CNTKCTL_EL1 event stream enable and trigger bit select
+const CNTKCTL_EVNTEN: u64 = 1 << 2;
+const CNTKCTL_EVNTI_SHIFT: u64 = 4;
+const CNTKCTL_EVNTI_MASK: u64 = 0xF << CNTKCTL_EVNTI_SHIFT;
+
+// Counter frequency in Hz, as programmed by firmware
+pub fn frequency() -> u64 {
+    let freq: u64;
+    unsafe {
+        asm!("mrs {}, cntfrq_el0", out(reg) freq, options(nomem, nostack));
+    }
+    freq
+}
+
+// Current counter value
+// The isb keeps the read from being speculated ahead of earlier instructions
+pub fn count() -> u64 {
+    let count: u64;
+    unsafe {
+        asm!("isb", "mrs {}, cntpct_el0", out(reg) count, options(nostack));
+    }
+    count
+}
+
+// Convert counter ticks to nanoseconds
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    let freq = frequency();
+    if freq == 0 {
+        return 0;
+    }
+    ((ticks as u128 * 1_000_000_000) / freq as u128) as u64
+}
+
+// Nanoseconds since the counter started
+pub fn now_ns() -> u64 {
+    ticks_to_ns(count())
+}
+
+// Start this core's event stream
+// The counter then sets the event register every EVENT_STREAM_US or so, which
+// wakes a wfe that missed its sev instead of leaving it asleep for good.
+pub fn enable_event_stream() {
+    // An event fires each time counter bit EVNTI changes, i.e. every
+    // 2^(EVNTI + 1) ticks
+    let period = (frequency() * EVENT_STREAM_US / 1_000_000).max(2);
+    let evnti = (63 - period.leading_zeros() as u64).saturating_sub(1).min(15);
+    unsafe {
+        let ctl = CntkctlEl1::read() & !CNTKCTL_EVNTI_MASK;
+        CntkctlEl1::write(ctl | CNTKCTL_EVNTEN | (evnti << CNTKCTL_EVNTI_SHIFT));
+    }
+}
+
+// Busy-wait for `us` microseconds
+pub fn delay_us(us: u64) {
+    let ticks = (frequency() as u128 * us as u128 / 1_000_000) as u64;
+    let start = count();
+
+    while count().wrapping_sub(start) < ticks {
+        core::hint::spin_loop();
+    }
+}
+
+// Busy-wait for `ms` milliseconds
+pub fn delay_ms(ms: u64) {
+    delay_us(ms * 1000);
+}
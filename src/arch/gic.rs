@@ -1,9 +1,14 @@
 // S32G3 GIC-500 Interrupt Controller implementation
 // Based on ARM GICv3 Architecture
 
+use core::fmt;
+use core::ops::Range;
 use core::ptr::{read_volatile, write_volatile};
 use core::arch::asm;
-use crate::arch::s32g3::GIC_DIST_BASE;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use alloc::vec::Vec;
+use crate::arch::board::{BOARD, MAX_CORES};
+use crate::arch::s32g3::timer;
 
 // GIC Distributor register offsets
 const GICD_CTLR: usize = 0x0000;           // Distributor Control Register
@@ -21,18 +26,34 @@ const GICD_ITARGETSR: usize = 0x0800;      // Interrupt Processor Targets Regist
 const GICD_ICFGR: usize = 0x0C00;          // Interrupt Configuration Registers
 const GICD_IGRPMODR: usize = 0x0D00;       // Interrupt Group Modifier Registers
 const GICD_NSACR: usize = 0x0E00;          // Non-secure Access Control Registers
+const GICD_IROUTER: usize = 0x6000;        // Interrupt Routing Registers (64-bit, SPIs from 0x6100)
 
 // GIC Redistributor registers
 const GICR_CTLR: usize = 0x00000;          // Redistributor Control Register
 const GICR_TYPER: usize = 0x00008;         // Redistributor Type Register
 const GICR_WAKER: usize = 0x00014;         // Redistributor Wake Register
+const GICR_SGI_OFFSET: usize = 0x10000;    // SGI/PPI frame, second 64KB page
+const GICR_IGROUPR0: usize = 0x0080;      // SGI/PPI Group Register (in the SGI frame)
+const GICR_ISENABLER0: usize = 0x0100;     // SGI/PPI Set-Enable Register (in the SGI frame)
+const GICR_ICENABLER0: usize = 0x0180;     // SGI/PPI Clear-Enable Register (in the SGI frame)
+const GICR_ISPENDR0: usize = 0x0200;       // SGI/PPI Set-Pending Register (in the SGI frame)
+const GICR_ISACTIVER0: usize = 0x0300;     // SGI/PPI Set-Active Register (in the SGI frame)
+const GICR_IPRIORITYR: usize = 0x0400;     // SGI/PPI Priority Registers (in the SGI frame)
+const GICR_ICFGR0: usize = 0x0C00;         // SGI Configuration Register (in the SGI frame)
+const GICR_ICFGR1: usize = 0x0C04;         // PPI Configuration Register (in the SGI frame)
+const GICR_IGRPMODR0: usize = 0x0D00;      // SGI/PPI Group Modifier Register (in the SGI frame)
 
 // GIC register bit definitions
-const GICD_CTLR_ENABLE: u32 = 0x1;
+const GICD_CTLR_ENABLE_G1NS: u32 = 1 << 1; // EnableGrp1A: Non-secure Group 1 with ARE_NS set
 const GICD_CTLR_ARE_NS: u32 = 1 << 4;      // Affinity Routing Enable (Non-Secure)
+const GICD_CTLR_RWP: u32 = 1 << 31;        // Register Write Pending
+const GICR_CTLR_RWP: u32 = 1 << 3;         // Register Write Pending
 const GICR_WAKER_PROCESSORASLEEP: u32 = 1 << 1;
 const GICR_WAKER_CHILDRENASLEEP: u32 = 1 << 2;
 
+// Polls of the RWP bit before giving up (GIC init runs before the timer)
+const GIC_RWP_TIMEOUT_POLLS: u32 = 1_000_000;
+
 // Number of interrupt IDs supported by the GIC
 const GIC_MAX_INTID: u32 = 1020;
 const GIC_MAX_SPI: u32 = 988;              // Shared Peripheral Interrupts: 32-1019
@@ -45,69 +66,169 @@ const GIC_HIGHEST_PRIORITY: u32 = 0x0;     // Highest priority
 const GIC_LOWEST_PRIORITY: u32 = 0xF0;     // Lowest priority
 const GIC_DEFAULT_PRIORITY: u32 = 0xA0;    // Default priority
 
+// GIC-500 accepts byte accesses to the priority registers. Set to false for
+// implementations that only accept 32-bit accesses; priority writes then
+// become a read-modify-write of the containing word.
+const GIC_BYTE_ACCESS_OK: bool = true;
+
+// Split priority drop and deactivation (ICC_CTLR_EL1.EOImode = 1). When set,
+// handlers may call priority_drop early and deactivate later; end_of_interrupt
+// still does both. When clear, priority_drop alone also deactivates.
+const GIC_SPLIT_EOI: bool = false;
+const ICC_CTLR_EOIMODE: u64 = 1 << 1;
+const ICC_CTLR_PRIBITS_SHIFT: u64 = 8;     // Implemented priority bits - 1
+const ICC_BPR_MAX: u8 = 7;
+pub const GIC_IDLE_PRIORITY: u8 = 0xFF;    // Running priority when no interrupt is active
+
+// SGI used to park every other core (e.g. on panic)
+pub const SGI_STOP: u32 = 15;
+
+// SGI asking a core to run its scheduler (a task it may run became ready)
+pub const SGI_RESCHEDULE: u32 = 14;
+
+// GIC driver errors
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GicError {
+    InvalidInterrupt,   // Interrupt ID out of range for the operation
+    InvalidCore,        // Core position out of range
+    Timeout,            // The target did not respond in time
+    RouteNotApplied,    // GICD_IROUTER read back differently (is ARE enabled?)
+    InvalidBinaryPoint, // Binary point outside 0-7 or below the implemented minimum
+}
+
+// Registered interrupt handlers, indexed by INTID; null when none
+static HANDLERS: [AtomicPtr<()>; GIC_MAX_INTID as usize] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; GIC_MAX_INTID as usize];
+
+// Set once the distributor has been programmed, so only one core does it
+static GIC_DIST_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+// Per-core acknowledge flags for synchronous SGIs
+static SGI_ACK: [AtomicBool; MAX_CORES] = [const { AtomicBool::new(false) }; MAX_CORES];
+
+// Per-core bitmap of interrupts acknowledged but not yet EOI'd
+// Catches duplicate or unmatched EOIs, which corrupt the active-priority stack
+#[cfg(feature = "gic-debug")]
+mod debug {
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use crate::arch::board::MAX_CORES;
+    use super::GIC_MAX_INTID;
+
+    const WORDS: usize = (GIC_MAX_INTID as usize).div_ceil(32);
+
+    static ACTIVE: [[AtomicU32; WORDS]; MAX_CORES] =
+        [const { [const { AtomicU32::new(0) }; WORDS] }; MAX_CORES];
+
+    fn slot(irq_num: u32) -> Option<(&'static AtomicU32, u32)> {
+        let core = crate::arch::core_pos();
+        let word = ACTIVE.get(core)?.get(irq_num as usize / 32)?;
+        Some((word, 1 << (irq_num % 32)))
+    }
+
+    pub fn note_acknowledged(irq_num: u32) {
+        if let Some((word, bit)) = slot(irq_num) {
+            let prev = word.fetch_or(bit, Ordering::Relaxed);
+            assert!(prev & bit == 0, "GIC: interrupt {} acknowledged twice without EOI", irq_num);
+        }
+    }
+
+    pub fn note_eoi(irq_num: u32) {
+        if let Some((word, bit)) = slot(irq_num) {
+            let prev = word.fetch_and(!bit, Ordering::Relaxed);
+            assert!(prev & bit != 0, "GIC: EOI for interrupt {} which is not acknowledged", irq_num);
+        }
+    }
+}
+
 /**
- * Get the number of SPIs supported by the GIC
+ * Get the number of interrupt IDs (SGIs, PPIs and SPIs) the distributor implements
+ * GICD_TYPER.ITLinesNumber gives 32 * (N + 1), capped at the 1020 valid IDs
  */
-fn gic_num_spis() -> u32 {
+fn gic_num_intids() -> u32 {
     unsafe {
-        let typer = read_volatile((GIC_DIST_BASE + GICD_TYPER) as *const u32);
-        ((typer & 0x1F) + 1) * 32           // ITLinesNumber * 32
+        let typer = read_volatile((BOARD.gic_dist_base + GICD_TYPER) as *const u32);
+        (((typer & 0x1F) + 1) * 32).min(GIC_MAX_INTID)
     }
 }
 
+/**
+ * Wait for a GICD_CTLR or GICR_CTLR write to take effect
+ * `base` is the distributor or a redistributor base and `rwp` the matching
+ * Register Write Pending bit. Required after writes to CTLR and ICENABLER.
+ */
+fn wait_for_rwp(base: usize, rwp: u32) -> Result<(), GicError> {
+    for _ in 0..GIC_RWP_TIMEOUT_POLLS {
+        let ctlr = unsafe { read_volatile((base + GICD_CTLR) as *const u32) };
+        if ctlr & rwp == 0 {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    
+    crate::drivers::uart::puts("GIC: timed out waiting for RWP\r\n");
+    Err(GicError::Timeout)
+}
+
 /**
  * Initialize the GIC Distributor
+ * Programs SPIs only (IDs 32 and up); SGIs and PPIs are banked per core and
+ * configured in each redistributor by init_gicr. From the Non-secure side only
+ * ARE_NS and the Group 1 enables are writable; ARE_S is owned by the firmware.
  */
 pub fn init_gicd() {
+    let dist = BOARD.gic_dist_base;
+    let num_ints = gic_num_intids();
+    
+    // First register index covering SPIs, for 1-bit and 2-bit per-interrupt banks
+    let first_spi_word = 1;
+    let num_words = num_ints.div_ceil(32) as usize;
+    let first_spi_cfg = 2;
+    let num_cfg = num_ints.div_ceil(16) as usize;
+    
     unsafe {
         // Disable the distributor
-        write_volatile((GIC_DIST_BASE + GICD_CTLR) as *mut u32, 0);
+        write_volatile((dist + GICD_CTLR) as *mut u32, 0);
+        let _ = wait_for_rwp(dist, GICD_CTLR_RWP);
         
-        // Get number of SPIs
-        let num_spis = gic_num_spis();
-        let num_ints = num_spis + GIC_MAX_PPI;
+        // Affinity routing must be enabled while the groups are disabled
+        write_volatile((dist + GICD_CTLR) as *mut u32, GICD_CTLR_ARE_NS);
+        let _ = wait_for_rwp(dist, GICD_CTLR_RWP);
         
-        // Calculate number of register sets needed (32 interrupts per word)
-        let num_irq_regs = ((num_ints + 31) / 32) as usize;
-        
-        // Configure all SPIs as level-triggered, active high
-        for i in 0..num_irq_regs {
-            // SPIs start at ID 32
-            if i >= 1 {
-                write_volatile(((GIC_DIST_BASE + GICD_ICFGR) + (i * 4)) as *mut u32, 0);
-            }
+        // Disable, clear and deactivate all SPIs
+        for i in first_spi_word..num_words {
+            write_volatile((dist + GICD_ICENABLER + i * 4) as *mut u32, 0xFFFFFFFF);
         }
-
-        // Disable all interrupts
-        for i in 0..num_irq_regs {
-            write_volatile(((GIC_DIST_BASE + GICD_ICENABLER) + (i * 4)) as *mut u32, 0xFFFFFFFF);
+        let _ = wait_for_rwp(dist, GICD_CTLR_RWP);
+        for i in first_spi_word..num_words {
+            write_volatile((dist + GICD_ICPENDR + i * 4) as *mut u32, 0xFFFFFFFF);
+            write_volatile((dist + GICD_ICACTIVER + i * 4) as *mut u32, 0xFFFFFFFF);
         }
-
-        // Clear any pending interrupts
-        for i in 0..num_irq_regs {
-            write_volatile(((GIC_DIST_BASE + GICD_ICPENDR) + (i * 4)) as *mut u32, 0xFFFFFFFF);
-            write_volatile(((GIC_DIST_BASE + GICD_ICACTIVER) + (i * 4)) as *mut u32, 0xFFFFFFFF);
+        
+        // All SPIs are Group 1 Non-secure
+        for i in first_spi_word..num_words {
+            write_volatile((dist + GICD_IGROUPR + i * 4) as *mut u32, 0xFFFFFFFF);
         }
-
-        // Set priority for all interrupts
-        let num_prio_regs = num_ints as usize;
-        for i in 0..num_prio_regs {
-            write_volatile(((GIC_DIST_BASE + GICD_IPRIORITYR) + (i * 4)) as *mut u32, GIC_DEFAULT_PRIORITY);
+        
+        // Level-triggered, active high
+        for i in first_spi_cfg..num_cfg {
+            write_volatile((dist + GICD_ICFGR + i * 4) as *mut u32, 0);
         }
-
-        // Set interrupt targets to the primary core (legacy mode)
-        let num_target_regs = num_ints as usize;
-        for i in 32..num_target_regs {
-            write_volatile(((GIC_DIST_BASE + GICD_ITARGETSR) + (i * 4)) as *mut u32, 0x01010101);
+        
+        // Default priority, one byte per interrupt
+        for irq in 32..num_ints {
+            write_priority_field(dist + GICD_IPRIORITYR, irq, GIC_DEFAULT_PRIORITY as u8);
+            crate::maybe_yield!();
         }
-
-        // Set all interrupts as Group 1 Non-secure
-        for i in 0..num_irq_regs {
-            write_volatile(((GIC_DIST_BASE + GICD_IGROUPR) + (i * 4)) as *mut u32, 0xFFFFFFFF);
+        
+        // Route every SPI to the boot core (Aff 0.0.0.0, IRM = 0)
+        for irq in 32..num_ints {
+            write_volatile((dist + GICD_IROUTER + irq as usize * 8) as *mut u64, 0);
+            crate::maybe_yield!();
         }
-
-        // Enable the distributor with ARE_NS
-        write_volatile((GIC_DIST_BASE + GICD_CTLR) as *mut u32, GICD_CTLR_ENABLE | GICD_CTLR_ARE_NS);
+        
+        // Enable Group 1 with affinity routing
+        write_volatile((dist + GICD_CTLR) as *mut u32, GICD_CTLR_ENABLE_G1NS | GICD_CTLR_ARE_NS);
+        let _ = wait_for_rwp(dist, GICD_CTLR_RWP);
     }
 }
 
@@ -137,6 +258,25 @@ pub fn init_gicc() {
             options(nostack)
         );
         
+        // Select combined or split EOI
+        let mut ctlr: u64;
+        asm!(
+            "mrs {x}, S3_0_C12_C12_4",
+            x = out(reg) ctlr,
+            options(nostack)
+        );
+        if GIC_SPLIT_EOI {
+            ctlr |= ICC_CTLR_EOIMODE;
+        } else {
+            ctlr &= !ICC_CTLR_EOIMODE;
+        }
+        asm!(
+            "msr S3_0_C12_C12_4, {x}",
+            "isb",
+            x = in(reg) ctlr,
+            options(nostack)
+        );
+        
         // Enable Group 1 interrupts
         asm!(
             "msr S3_0_C12_C12_7, {x:x}",
@@ -146,14 +286,20 @@ pub fn init_gicc() {
     }
 }
 
+/**
+ * Base address of a core's redistributor
+ */
+fn gicr_base(core_id: u32) -> usize {
+    BOARD.gic_redist_base + (core_id as usize * BOARD.gic_redist_stride)
+}
+
 /**
  * Initialize GIC Redistributor for this core
  */
 pub fn init_gicr(core_id: u32) {
     unsafe {
         // Calculate base address for this core's redistributor
-        // S32G3 redistributor stride is 0x20000
-        let gicr_base = 0x50880000 + (core_id as usize * 0x20000);
+        let gicr_base = gicr_base(core_id);
         
         // Wake up the redistributor
         let waker = read_volatile((gicr_base + GICR_WAKER) as *const u32);
@@ -163,6 +309,13 @@ pub fn init_gicr(core_id: u32) {
         while (read_volatile((gicr_base + GICR_WAKER) as *const u32) & GICR_WAKER_CHILDRENASLEEP) != 0 {
             // Spin
         }
+        
+        // Set all SGIs and PPIs as Group 1 Non-secure
+        write_volatile((gicr_base + GICR_SGI_OFFSET + GICR_IGROUPR0) as *mut u32, 0xFFFFFFFF);
+        
+        // The stop SGI is always live so a panicking core can park this one
+        write_volatile((gicr_base + GICR_SGI_OFFSET + GICR_ISENABLER0) as *mut u32, 1 << SGI_STOP);
+        let _ = wait_for_rwp(gicr_base, GICR_CTLR_RWP);
     }
 }
 
@@ -171,11 +324,13 @@ pub fn init_gicr(core_id: u32) {
  */
 pub fn init() {
     // Get current core ID
-    let cpu_id = crate::arch::cpu_id() as u32;
+    let cpu_id = crate::arch::core_pos() as u32;
     
-    // Initialize GIC components
-    if cpu_id == 0 {
-        // Core 0 initializes the distributor
+    // The first core here initializes the (shared) distributor
+    if GIC_DIST_INITIALIZED
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+    {
         init_gicd();
     }
     
@@ -186,14 +341,39 @@ pub fn init() {
 
 /**
  * Enable a specific interrupt
+ * SGIs and PPIs are enabled in this core's redistributor: with affinity
+ * routing on, the distributor's banked enable registers for them are RAZ/WI
  */
 pub fn enable_interrupt(irq_num: u32) {
+    if irq_num < 32 {
+        let gicr = gicr_base(crate::arch::core_pos() as u32) + GICR_SGI_OFFSET;
+        unsafe {
+            write_volatile((gicr + GICR_ISENABLER0) as *mut u32, 1 << irq_num);
+        }
+        return;
+    }
+    if irq_num >= GIC_MAX_INTID {
+        return;
+    }
+    
     unsafe {
         let reg_offset = (irq_num / 32) as usize;
         let bit_offset = irq_num % 32;
         
+        // Enabling twice is harmless to the GIC but usually means two drivers
+        // both think they own the line
+        #[cfg(feature = "gic-debug")]
+        {
+            let enabled = read_volatile(
+                ((BOARD.gic_dist_base + GICD_ISENABLER) + (reg_offset * 4)) as *const u32
+            );
+            if enabled & (1 << bit_offset) != 0 {
+                crate::println!("GIC: interrupt {} enabled twice", irq_num);
+            }
+        }
+        
         write_volatile(
-            ((GIC_DIST_BASE + GICD_ISENABLER) + (reg_offset * 4)) as *mut u32,
+            ((BOARD.gic_dist_base + GICD_ISENABLER) + (reg_offset * 4)) as *mut u32,
             1 << bit_offset
         );
     }
@@ -203,15 +383,29 @@ pub fn enable_interrupt(irq_num: u32) {
  * Disable a specific interrupt
  */
 pub fn disable_interrupt(irq_num: u32) {
+    if irq_num < 32 {
+        let gicr = gicr_base(crate::arch::core_pos() as u32);
+        unsafe {
+            write_volatile((gicr + GICR_SGI_OFFSET + GICR_ICENABLER0) as *mut u32, 1 << irq_num);
+        }
+        // The interrupt may still be signalled until the write completes
+        let _ = wait_for_rwp(gicr, GICR_CTLR_RWP);
+        return;
+    }
+    if irq_num >= GIC_MAX_INTID {
+        return;
+    }
+    
     unsafe {
         let reg_offset = (irq_num / 32) as usize;
         let bit_offset = irq_num % 32;
         
         write_volatile(
-            ((GIC_DIST_BASE + GICD_ICENABLER) + (reg_offset * 4)) as *mut u32,
+            ((BOARD.gic_dist_base + GICD_ICENABLER) + (reg_offset * 4)) as *mut u32,
             1 << bit_offset
         );
     }
+    let _ = wait_for_rwp(BOARD.gic_dist_base, GICD_CTLR_RWP);
 }
 
 /**
@@ -226,13 +420,37 @@ pub fn get_interrupt_id() -> u32 {
             options(nostack)
         );
     }
-    (iar & 0x3FF) as u32
+    let irq_num = (iar & 0x3FF) as u32;
+    
+    // 1020-1023 are special (spurious) IDs and are never EOI'd
+    #[cfg(feature = "gic-debug")]
+    if irq_num < GIC_MAX_INTID {
+        debug::note_acknowledged(irq_num);
+    }
+    
+    irq_num
 }
 
 /**
- * Signal End Of Interrupt
+ * Signal End Of Interrupt (priority drop and deactivation)
  */
 pub fn end_of_interrupt(irq_num: u32) {
+    priority_drop(irq_num);
+    if GIC_SPLIT_EOI {
+        deactivate(irq_num);
+    }
+}
+
+/**
+ * Drop the running priority for an acknowledged interrupt (ICC_EOIR1_EL1)
+ * In split EOI mode the interrupt stays active, so it cannot fire again, but
+ * higher-priority interrupts can now preempt its handler. Drops must happen in
+ * the reverse order of acknowledgement.
+ */
+pub fn priority_drop(irq_num: u32) {
+    #[cfg(feature = "gic-debug")]
+    debug::note_eoi(irq_num);
+    
     unsafe {
         asm!(
             "msr S3_0_C12_C12_1, {x}",
@@ -243,35 +461,664 @@ pub fn end_of_interrupt(irq_num: u32) {
 }
 
 /**
- * Set interrupt priority
+ * Deactivate an interrupt whose priority was already dropped (ICC_DIR_EL1)
+ * Only meaningful in split EOI mode, and only after priority_drop for the
+ * same ID; it may be done from a different context than the drop.
  */
-pub fn set_priority(irq_num: u32, priority: u8) {
+pub fn deactivate(irq_num: u32) {
+    if !GIC_SPLIT_EOI {
+        return;
+    }
+    
     unsafe {
-        let reg_offset = irq_num as usize;
-        let priority_val = (priority as u32) << 4; // Higher 4 bits are used
-        
-        write_volatile(
-            ((GIC_DIST_BASE + GICD_IPRIORITYR) + (reg_offset * 4)) as *mut u32,
-            priority_val
+        asm!(
+            "msr S3_0_C12_C11_1, {x}",
+            x = in(reg) irq_num as u64,
+            options(nostack)
         );
     }
 }
 
+/**
+ * Register the handler called from the IRQ vector for an interrupt
+ * Replaces any previous handler. The handler runs before EOI and must clear
+ * the interrupt source if it is level-sensitive.
+ */
+pub fn register_handler(irq_num: u32, handler: fn()) -> Result<(), GicError> {
+    let slot = HANDLERS.get(irq_num as usize).ok_or(GicError::InvalidInterrupt)?;
+    slot.store(handler as *mut (), Ordering::Release);
+    Ok(())
+}
+
+/**
+ * Remove the handler for an interrupt
+ */
+pub fn unregister_handler(irq_num: u32) {
+    if let Some(slot) = HANDLERS.get(irq_num as usize) {
+        slot.store(core::ptr::null_mut(), Ordering::Release);
+    }
+}
+
+/**
+ * Look up the registered handler for an interrupt
+ */
+pub fn handler(irq_num: u32) -> Option<fn()> {
+    let handler = HANDLERS.get(irq_num as usize)?.load(Ordering::Acquire);
+    if handler.is_null() {
+        None
+    } else {
+        Some(unsafe { core::mem::transmute::<*mut (), fn()>(handler) })
+    }
+}
+
+/**
+ * Read the running priority of this CPU interface (ICC_RPR_EL1)
+ * Returns GIC_IDLE_PRIORITY (0xFF) when no interrupt is active
+ */
+pub fn running_priority() -> u8 {
+    let rpr: u64;
+    unsafe {
+        asm!(
+            "mrs {x}, S3_0_C12_C11_3",
+            x = out(reg) rpr,
+            options(nostack)
+        );
+    }
+    (rpr & 0xFF) as u8
+}
+
+/**
+ * Read this CPU interface's priority mask (ICC_PMR_EL1)
+ * Interrupts whose priority value is >= the mask are not signalled
+ */
+pub fn priority_mask() -> u8 {
+    let pmr: u64;
+    unsafe {
+        asm!(
+            "mrs {x}, S3_0_C4_C6_0",
+            x = out(reg) pmr,
+            options(nostack)
+        );
+    }
+    (pmr & 0xFF) as u8
+}
+
+/**
+ * Set this CPU interface's priority mask (ICC_PMR_EL1)
+ * Every value is accepted: priority bits the GIC does not implement (see
+ * priority_bits) read as zero, so e.g. 0xFF reads back as 0xF0 with 4 bits.
+ */
+pub fn set_priority_mask(mask: u8) {
+    unsafe {
+        asm!(
+            "msr S3_0_C4_C6_0, {x:x}",
+            "isb",
+            x = in(reg) mask as u64,
+            options(nostack)
+        );
+    }
+}
+
+/**
+ * Number of priority bits this CPU interface implements (ICC_CTLR_EL1.PRIbits + 1)
+ * Only the top this-many bits of each 8-bit priority value are significant.
+ */
+pub fn priority_bits() -> u8 {
+    let ctlr: u64;
+    unsafe {
+        asm!(
+            "mrs {x}, S3_0_C12_C12_4",
+            x = out(reg) ctlr,
+            options(nostack)
+        );
+    }
+    ((ctlr >> ICC_CTLR_PRIBITS_SHIFT) & 0x7) as u8 + 1
+}
+
+/**
+ * Read the Group 1 binary point (ICC_BPR1_EL1)
+ */
+pub fn binary_point() -> u8 {
+    let bpr: u64;
+    unsafe {
+        asm!(
+            "mrs {x}, S3_0_C12_C12_3",
+            x = out(reg) bpr,
+            options(nostack)
+        );
+    }
+    (bpr & 0x7) as u8
+}
+
+/**
+ * Set the Group 1 binary point (ICC_BPR1_EL1), which controls preemption
+ * The binary point splits each 8-bit priority into a group priority (bits
+ * [7:bpr+1]) and a subpriority (bits [bpr:0]). An interrupt only preempts a
+ * running handler if its group priority is higher; the subpriority only
+ * orders pending interrupts. With BPR 0 every implemented bit is group
+ * priority, so any more urgent interrupt preempts. With BPR 4 and 4
+ * implemented bits (set_priority(p) stores p << 4) all priorities share one
+ * group, so handlers never nest. Values the GIC does not support (below its
+ * minimum) are rejected rather than silently clamped.
+ */
+pub fn set_binary_point(bpr: u8) -> Result<(), GicError> {
+    if bpr > ICC_BPR_MAX {
+        return Err(GicError::InvalidBinaryPoint);
+    }
+    
+    unsafe {
+        asm!(
+            "msr S3_0_C12_C12_3, {x}",
+            "isb",
+            x = in(reg) bpr as u64,
+            options(nostack)
+        );
+    }
+    
+    // Writes below the minimum are raised to it by the hardware
+    if binary_point() != bpr {
+        return Err(GicError::InvalidBinaryPoint);
+    }
+    Ok(())
+}
+
+/**
+ * Write one interrupt's 8-bit priority field in a priority register bank
+ */
+fn write_priority_field(bank: usize, irq_num: u32, value: u8) {
+    unsafe {
+        if GIC_BYTE_ACCESS_OK {
+            write_volatile((bank + irq_num as usize) as *mut u8, value);
+        } else {
+            // Word-only access: read-modify-write the containing register
+            let word = (bank + (irq_num as usize & !3)) as *mut u32;
+            let shift = (irq_num % 4) * 8;
+            let val = (read_volatile(word) & !(0xFF << shift)) | ((value as u32) << shift);
+            write_volatile(word, val);
+        }
+    }
+}
+
+/**
+ * Read one interrupt's 8-bit priority field from a priority register bank
+ */
+fn read_priority_field(bank: usize, irq_num: u32) -> u8 {
+    unsafe {
+        if GIC_BYTE_ACCESS_OK {
+            read_volatile((bank + irq_num as usize) as *const u8)
+        } else {
+            let word = (bank + (irq_num as usize & !3)) as *const u32;
+            (read_volatile(word) >> ((irq_num % 4) * 8)) as u8
+        }
+    }
+}
+
+/**
+ * Set the priority of an SGI or PPI on this core (redistributor)
+ */
+pub fn set_sgi_priority(irq_num: u32, priority: u8) {
+    if irq_num >= 32 {
+        return;
+    }
+    let bank = gicr_base(crate::arch::core_pos() as u32) + GICR_SGI_OFFSET + GICR_IPRIORITYR;
+    write_priority_field(bank, irq_num, priority);
+}
+
+/**
+ * Set the priority of an SPI (distributor)
+ */
+pub fn set_spi_priority(irq_num: u32, priority: u8) {
+    if !(32..GIC_MAX_INTID).contains(&irq_num) {
+        return;
+    }
+    write_priority_field(BOARD.gic_dist_base + GICD_IPRIORITYR, irq_num, priority);
+}
+
+/**
+ * Read-modify-write one interrupt's 2-bit field in an ICFGR bank
+ * Bit 1 of the field selects edge-triggered (1) or level-sensitive (0)
+ */
+fn write_trigger_field(bank: usize, irq_num: u32, edge: bool) {
+    unsafe {
+        let reg = (bank + (irq_num as usize / 16) * 4) as *mut u32;
+        let bit = 1 << ((irq_num % 16) * 2 + 1);
+        let val = read_volatile(reg);
+        write_volatile(reg, if edge { val | bit } else { val & !bit });
+    }
+}
+
+/**
+ * Configure an SPI as edge-triggered (true) or level-sensitive (false)
+ * Should be done while the interrupt is disabled
+ */
+pub fn set_spi_trigger(irq_num: u32, edge: bool) -> Result<(), GicError> {
+    if !(32..GIC_MAX_INTID).contains(&irq_num) {
+        return Err(GicError::InvalidInterrupt);
+    }
+    write_trigger_field(BOARD.gic_dist_base + GICD_ICFGR, irq_num, edge);
+    Ok(())
+}
+
+/**
+ * Configure a PPI on this core as edge-triggered (true) or level-sensitive (false)
+ * SGIs are always edge-triggered, so only 16-31 are accepted
+ */
+pub fn set_ppi_trigger(irq_num: u32, edge: bool) -> Result<(), GicError> {
+    if !(GIC_MAX_SGI..GIC_MAX_PPI).contains(&irq_num) {
+        return Err(GicError::InvalidInterrupt);
+    }
+    // GICR_ICFGR1 covers PPIs 16-31, so index from 16
+    let bank = gicr_base(crate::arch::core_pos() as u32) + GICR_SGI_OFFSET + GICR_ICFGR1;
+    write_trigger_field(bank, irq_num - GIC_MAX_SGI, edge);
+    Ok(())
+}
+
+/**
+ * Set interrupt priority
+ */
+pub fn set_priority(irq_num: u32, priority: u8) {
+    let priority_val = (priority & 0x0F) << 4; // Higher 4 bits are used
+    
+    if irq_num < 32 {
+        set_sgi_priority(irq_num, priority_val);
+    } else {
+        set_spi_priority(irq_num, priority_val);
+    }
+}
+
 /**
  * Send a Software Generated Interrupt
+ * `target_list` is a GICv2-style bitmap of linear core positions (bit n =
+ * core n). With affinity routing, ICC_SGI1R_EL1 can only address cores in
+ * one cluster per write, so one write is issued per targeted cluster.
  */
 pub fn send_sgi(sgi_id: u32, target_list: u8, _filter: u8) {
     if sgi_id > 15 {
         return; // Invalid SGI ID
     }
     
+    let clusters = BOARD.num_cores.div_ceil(BOARD.cores_per_cluster);
+    for cluster in 0..clusters {
+        // Aff0 bitmap of the targeted cores in this cluster
+        let mut aff0_list = 0u64;
+        for aff0 in 0..BOARD.cores_per_cluster {
+            let core = cluster * BOARD.cores_per_cluster + aff0;
+            if core < BOARD.num_cores && target_list & (1 << core) != 0 {
+                aff0_list |= 1 << aff0;
+            }
+        }
+        if aff0_list == 0 {
+            continue;
+        }
+        
+        // ICC_SGI1R_EL1: TargetList[15:0] (Aff0 bitmap), Aff1[23:16], INTID[27:24]
+        let sgi_value = aff0_list | ((cluster as u64) << 16) | ((sgi_id as u64) << 24);
+        unsafe {
+            asm!(
+                "msr S3_0_C12_C11_5, {x}",
+                "isb",
+                x = in(reg) sgi_value,
+                options(nostack)
+            );
+        }
+    }
+}
+
+/**
+ * Send an SGI to a single core, addressed by its linear core position
+ */
+pub fn send_sgi_to_core(target_core: usize, sgi_id: u32) -> Result<(), GicError> {
+    if sgi_id > 15 {
+        return Err(GicError::InvalidInterrupt);
+    }
+    if target_core >= BOARD.num_cores {
+        return Err(GicError::InvalidCore);
+    }
+    
+    // ICC_SGI1R_EL1: TargetList[15:0] (Aff0 bitmap), Aff1[23:16], INTID[27:24]
+    let aff1 = (target_core / BOARD.cores_per_cluster) as u64;
+    let aff0 = (target_core % BOARD.cores_per_cluster) as u64;
+    let sgi_value = (1 << aff0) | (aff1 << 16) | ((sgi_id as u64) << 24);
+    
     unsafe {
-        // In GICv3, SGIs are sent using system registers
-        let sgi_value = (sgi_id as u64) | ((target_list as u64) << 16);
         asm!(
             "msr S3_0_C12_C11_5, {x}",
+            "isb",
             x = in(reg) sgi_value,
             options(nostack)
         );
     }
-}
\ No newline at end of file
+    
+    Ok(())
+}
+
+/**
+ * Send an SGI and block until the target core acknowledges it with ack_sgi()
+ * Only one synchronous request per target core may be outstanding at a time
+ */
+pub fn send_sgi_sync(target_core: usize, sgi_id: u32, timeout_us: u32) -> Result<(), GicError> {
+    let ack = SGI_ACK.get(target_core).ok_or(GicError::InvalidCore)?;
+    
+    ack.store(false, Ordering::Release);
+    send_sgi_to_core(target_core, sgi_id)?;
+    
+    let start = timer::now_ns();
+    let timeout_ns = timeout_us as u64 * 1000;
+    while !ack.load(Ordering::Acquire) {
+        if timer::now_ns() - start >= timeout_ns {
+            return Err(GicError::Timeout);
+        }
+        core::hint::spin_loop();
+    }
+    
+    Ok(())
+}
+
+/**
+ * Acknowledge a synchronous SGI on this core
+ * Called by the receiving SGI handler once the request has been serviced
+ */
+pub fn ack_sgi() {
+    if let Some(ack) = SGI_ACK.get(crate::arch::core_pos()) {
+        ack.store(true, Ordering::Release);
+    }
+}
+
+/**
+ * Route an SPI to a single core, addressed by its linear core position
+ * In debug builds GICD_IROUTER is read back, since the write is silently
+ * ignored when affinity routing is not enabled or the value is malformed
+ */
+pub fn route_spi_to_core(irq_num: u32, target_core: usize) -> Result<(), GicError> {
+    if !(32..GIC_MAX_INTID).contains(&irq_num) {
+        return Err(GicError::InvalidInterrupt);
+    }
+    if target_core >= BOARD.num_cores {
+        return Err(GicError::InvalidCore);
+    }
+    
+    // GICD_IROUTER: Aff0[7:0], Aff1[15:8], Interrupt_Routing_Mode[31] = 0
+    let aff1 = (target_core / BOARD.cores_per_cluster) as u64;
+    let aff0 = (target_core % BOARD.cores_per_cluster) as u64;
+    let route = (aff1 << 8) | aff0;
+    
+    set_spi_target(irq_num, route)
+}
+
+/**
+ * Write the raw GICD_IROUTER value for an SPI
+ */
+pub fn set_spi_target(irq_num: u32, route: u64) -> Result<(), GicError> {
+    if !(32..GIC_MAX_INTID).contains(&irq_num) {
+        return Err(GicError::InvalidInterrupt);
+    }
+    
+    let irouter = (BOARD.gic_dist_base + GICD_IROUTER + irq_num as usize * 8) as *mut u64;
+    unsafe {
+        write_volatile(irouter, route);
+    }
+    
+    #[cfg(debug_assertions)]
+    {
+        let readback = unsafe { read_volatile(irouter) };
+        if readback != route {
+            return Err(GicError::RouteNotApplied);
+        }
+    }
+    
+    Ok(())
+}
+
+/**
+ * Send the stop SGI to every core except this one
+ * Each receiving core parks itself in park_core()
+ */
+pub fn stop_all_other_cores() {
+    // ICC_SGI1R_EL1: INTID[27:24], IRM[40] = 1 routes to all PEs but self
+    let sgi_value = ((SGI_STOP as u64) << 24) | (1 << 40);
+    
+    unsafe {
+        asm!(
+            "msr S3_0_C12_C11_5, {x}",
+            "isb",
+            x = in(reg) sgi_value,
+            options(nostack)
+        );
+    }
+}
+
+/**
+ * Stop SGI handler: mask interrupts and wait forever
+ */
+pub fn park_core() -> ! {
+    unsafe {
+        crate::arch::aarch64::disable_irq();
+    }
+    crate::arch::CORE_STATES.mark_offline(crate::arch::percpu::this_cpu().core_pos);
+    
+    loop {
+        crate::arch::aarch64::wfe();
+    }
+}
+
+/**
+ * Enable, pending and active bits of one interrupt, as seen by this core
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct IrqBits {
+    pub intid: u32,
+    pub enabled: bool,
+    pub pending: bool,
+    pub active: bool,
+}
+
+/**
+ * Snapshot of the GIC configuration seen from this core, from dump_state()
+ */
+#[derive(Clone, Debug)]
+pub struct GicState {
+    pub gicd_ctlr: u32,
+    pub gicd_typer: u32,
+    pub gicr_typer: u64,        // This core's redistributor
+    pub icc_ctlr: u64,          // ICC_CTLR_EL1
+    pub icc_pmr: u8,            // ICC_PMR_EL1
+    pub icc_bpr1: u8,           // ICC_BPR1_EL1
+    pub irqs: Vec<IrqBits>,     // The range passed to dump_state
+}
+
+impl GicState {
+    /**
+     * Number of SPIs the distributor implements
+     */
+    pub fn num_spis(&self) -> u32 {
+        (((self.gicd_typer & 0x1F) + 1) * 32).min(GIC_MAX_INTID) - 32
+    }
+    
+    /**
+     * Number of cores the GIC reports without affinity routing (CPUNumber + 1)
+     */
+    pub fn num_cpus(&self) -> u32 {
+        ((self.gicd_typer >> 5) & 0x7) + 1
+    }
+}
+
+impl fmt::Display for GicState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |set: bool| if set { "yes" } else { "-" };
+        
+        writeln!(f, "GICD_CTLR    {:#010x} (Grp1 {}, ARE_NS {})", self.gicd_ctlr,
+            flag(self.gicd_ctlr & GICD_CTLR_ENABLE_G1NS != 0), flag(self.gicd_ctlr & GICD_CTLR_ARE_NS != 0))?;
+        writeln!(f, "GICD_TYPER   {:#010x} ({} SPIs, {} CPUs)", self.gicd_typer, self.num_spis(), self.num_cpus())?;
+        writeln!(f, "GICR_TYPER   {:#018x} (affinity {:#x})", self.gicr_typer, self.gicr_typer >> 32)?;
+        writeln!(f, "ICC_CTLR_EL1 {:#x}", self.icc_ctlr)?;
+        writeln!(f, "ICC_PMR_EL1  {:#04x}", self.icc_pmr)?;
+        writeln!(f, "ICC_BPR1_EL1 {}", self.icc_bpr1)?;
+        writeln!(f, "{:>6} {:>8} {:>8} {:>8}", "INTID", "ENABLED", "PENDING", "ACTIVE")?;
+        for irq in &self.irqs {
+            writeln!(f, "{:>6} {:>8} {:>8} {:>8}", irq.intid,
+                flag(irq.enabled), flag(irq.pending), flag(irq.active))?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Read one interrupt's bit from a 1-bit-per-interrupt register bank
+ */
+fn read_bit(bank: usize, irq_num: u32) -> bool {
+    unsafe {
+        let reg = (bank + (irq_num as usize / 32) * 4) as *const u32;
+        read_volatile(reg) & (1 << (irq_num % 32)) != 0
+    }
+}
+
+/**
+ * Enable, pending and active bits of one interrupt
+ * SGIs and PPIs come from this core's redistributor, SPIs from the distributor
+ */
+fn irq_bits(irq_num: u32) -> IrqBits {
+    let (base, enable, pend, active) = if irq_num < 32 {
+        let sgi = gicr_base(crate::arch::core_pos() as u32) + GICR_SGI_OFFSET;
+        (sgi, GICR_ISENABLER0, GICR_ISPENDR0, GICR_ISACTIVER0)
+    } else {
+        (BOARD.gic_dist_base, GICD_ISENABLER, GICD_ISPENDR, GICD_ISACTIVER)
+    };
+    
+    IrqBits {
+        intid: irq_num,
+        enabled: read_bit(base + enable, irq_num),
+        pending: read_bit(base + pend, irq_num),
+        active: read_bit(base + active, irq_num),
+    }
+}
+
+/**
+ * Read the distributor, this core's redistributor and CPU interface state,
+ * plus the enable/pending/active bits of the interrupts in `irqs`
+ * Nothing is modified, so it is safe to call while chasing an interrupt that
+ * does not fire. IDs the distributor does not implement are skipped.
+ */
+pub fn dump_state(irqs: Range<u32>) -> GicState {
+    let icc_ctlr: u64;
+    unsafe {
+        asm!(
+            "mrs {x}, S3_0_C12_C12_4",
+            x = out(reg) icc_ctlr,
+            options(nostack)
+        );
+    }
+    
+    let num_ints = gic_num_intids();
+    let gicr = gicr_base(crate::arch::core_pos() as u32);
+    unsafe {
+        GicState {
+            gicd_ctlr: read_volatile((BOARD.gic_dist_base + GICD_CTLR) as *const u32),
+            gicd_typer: read_volatile((BOARD.gic_dist_base + GICD_TYPER) as *const u32),
+            gicr_typer: read_volatile((gicr + GICR_TYPER) as *const u64),
+            icc_ctlr,
+            icc_pmr: priority_mask(),
+            icc_bpr1: binary_point(),
+            irqs: irqs.filter(|&irq| irq < num_ints).map(irq_bits).collect(),
+        }
+    }
+}
+
+/**
+ * Interrupt group, from IGROUPR and IGRPMODR
+ * Secure groups read as Group 0 from the Non-secure side
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum IrqGroup {
+    Group0,
+    Group1Secure,
+    Group1NonSecure,
+}
+
+/**
+ * Trigger mode, from ICFGR
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum IrqTrigger {
+    Level,
+    Edge,
+}
+
+/**
+ * Full configuration and state of one interrupt, from interrupt_state()
+ */
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct InterruptState {
+    pub intid: u32,
+    pub enabled: bool,
+    pub pending: bool,
+    pub active: bool,
+    pub group: IrqGroup,
+    pub priority: u8,           // Raw IPRIORITYR value (set_priority(p) stores p << 4)
+    pub trigger: IrqTrigger,
+    pub route: Option<u64>,     // GICD_IROUTER for SPIs; SGIs and PPIs are local to this core
+}
+
+impl fmt::Display for InterruptState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "INTID {}: enabled={} pending={} active={} {:?} priority={:#04x} {:?}",
+            self.intid, self.enabled, self.pending, self.active,
+            self.group, self.priority, self.trigger)?;
+        match self.route {
+            Some(route) if route & (1 << 31) != 0 => write!(f, " routed to any core"),
+            Some(route) => write!(f, " routed to {}.{}", (route >> 8) & 0xFF, route & 0xFF),
+            None => write!(f, " (this core)"),
+        }
+    }
+}
+
+/**
+ * Read the configuration and state of a single interrupt
+ * SGIs and PPIs (0-31) are read from this core's redistributor, SPIs from the
+ * distributor. Nothing is modified.
+ */
+pub fn interrupt_state(irq_num: u32) -> Result<InterruptState, GicError> {
+    if irq_num >= gic_num_intids() {
+        return Err(GicError::InvalidInterrupt);
+    }
+    
+    let bits = irq_bits(irq_num);
+    let (base, igroupr, igrpmodr, ipriorityr, icfgr) = if irq_num < 32 {
+        let sgi = gicr_base(crate::arch::core_pos() as u32) + GICR_SGI_OFFSET;
+        (sgi, GICR_IGROUPR0, GICR_IGRPMODR0, GICR_IPRIORITYR, GICR_ICFGR0)
+    } else {
+        (BOARD.gic_dist_base, GICD_IGROUPR, GICD_IGRPMODR, GICD_IPRIORITYR, GICD_ICFGR)
+    };
+    
+    let group = match (read_bit(base + igroupr, irq_num), read_bit(base + igrpmodr, irq_num)) {
+        (true, _) => IrqGroup::Group1NonSecure,
+        (false, true) => IrqGroup::Group1Secure,
+        (false, false) => IrqGroup::Group0,
+    };
+    
+    let priority = read_priority_field(base + ipriorityr, irq_num);
+    let (icfg, route) = unsafe {
+        let icfg = read_volatile((base + icfgr + (irq_num as usize / 16) * 4) as *const u32);
+        let route = if irq_num < 32 {
+            None
+        } else {
+            Some(read_volatile((BOARD.gic_dist_base + GICD_IROUTER + irq_num as usize * 8) as *const u64))
+        };
+        (icfg, route)
+    };
+    let trigger = if icfg & (1 << ((irq_num % 16) * 2 + 1)) != 0 {
+        IrqTrigger::Edge
+    } else {
+        IrqTrigger::Level
+    };
+    
+    Ok(InterruptState {
+        intid: irq_num,
+        enabled: bits.enabled,
+        pending: bits.pending,
+        active: bits.active,
+        group,
+        priority,
+        trigger,
+        route,
+    })
+}
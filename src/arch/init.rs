@@ -0,0 +1,63 @@
+// Ordered system initialization
+// Each subsystem belongs to a phase and phases must run in order, exactly once.
+// Running a phase twice is a no-op; running one before its predecessor panics.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+// Initialization phases, in the order they must complete
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+#[repr(u8)]
+pub enum InitPhase {
+    Reset = 0,          // Nothing initialized yet
+    EarlyConsole = 1,   // Exception vectors and UART console
+    Gic = 2,            // Interrupt controller
+    Timer = 3,          // System timer
+    Interrupts = 4,     // IRQs unmasked on the boot core
+    Kernel = 5,         // FreeRTOS subsystems
+}
+
+impl InitPhase {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => InitPhase::EarlyConsole,
+            2 => InitPhase::Gic,
+            3 => InitPhase::Timer,
+            4 => InitPhase::Interrupts,
+            5 => InitPhase::Kernel,
+            _ => InitPhase::Reset,
+        }
+    }
+}
+
+// Last completed phase
+static COMPLETED_PHASE: AtomicU8 = AtomicU8::new(InitPhase::Reset as u8);
+
+// Get the last completed phase
+pub fn completed_phase() -> InitPhase {
+    InitPhase::from_u8(COMPLETED_PHASE.load(Ordering::Acquire))
+}
+
+// Check if a phase has completed
+pub fn is_complete(phase: InitPhase) -> bool {
+    completed_phase() >= phase
+}
+
+// Run the initializer for a phase
+// Returns false if the phase had already completed
+pub fn run_phase(phase: InitPhase, init: fn()) -> bool {
+    let completed = completed_phase();
+
+    if completed >= phase {
+        return false;
+    }
+
+    if phase as u8 != completed as u8 + 1 {
+        panic!("Init phase {:?} requires {:?} to complete first (completed: {:?})",
+               phase, InitPhase::from_u8(phase as u8 - 1), completed);
+    }
+
+    init();
+    COMPLETED_PHASE.store(phase as u8, Ordering::Release);
+
+    true
+}
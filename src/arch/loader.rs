@@ -0,0 +1,52 @@
+// Minimal flat-binary loader for secondary-core payloads
+// Copies a separately built image into RAM and makes it visible to instruction
+// fetch, returning the entry address to hand to the secondary boot path.
+
+use core::arch::asm;
+use super::aarch64::dcache_line_size;
+
+// Smallest instruction cache line size in bytes, from CTR_EL0.IminLine
+fn icache_line_size() -> usize {
+    let ctr: u64;
+    unsafe {
+        asm!("mrs {}, ctr_el0", out(reg) ctr);
+    }
+    4 << (ctr & 0xF)
+}
+
+// Make a freshly written code region coherent with instruction fetch
+pub fn sync_icache_range(start: usize, len: usize) {
+    let end = start + len;
+
+    // Clean data cache to the point of unification
+    let dline = dcache_line_size();
+    let mut addr = start & !(dline - 1);
+    while addr < end {
+        unsafe { asm!("dc cvau, {}", in(reg) addr, options(nostack)); }
+        addr += dline;
+    }
+    unsafe { asm!("dsb ish", options(nostack)); }
+
+    // Invalidate instruction cache to the point of unification
+    let iline = icache_line_size();
+    let mut addr = start & !(iline - 1);
+    while addr < end {
+        unsafe { asm!("ic ivau, {}", in(reg) addr, options(nostack)); }
+        addr += iline;
+    }
+    unsafe {
+        asm!("dsb ish", options(nostack));
+        asm!("isb", options(nostack));
+    }
+}
+
+// Copy a flat binary to `dest` and return its entry address
+// The image is assumed to start executing at its first byte.
+//
+// Safety: `dest..dest + image.len()` must be RAM not used by anything else,
+// including the running kernel's image, stacks and heap.
+pub unsafe fn load_flat(dest: usize, image: &[u8]) -> usize {
+    core::ptr::copy_nonoverlapping(image.as_ptr(), dest as *mut u8, image.len());
+    sync_icache_range(dest, image.len());
+    dest
+}
@@ -0,0 +1,228 @@
+// Memory layout checks
+// The stack and heap regions are carved out of RAM by link.ld; a mistake there
+// (or in memory.x) shows up much later as silent corruption, so the layout is
+// checked against the linker symbols once at boot.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use linked_list_allocator::{Heap, LockedHeap};
+use super::aarch64;
+
+extern "C" {
+    static __bss_start: u8;
+    static __bss_end: u8;
+    static __stack_start: u8;
+    static __stack_end: u8;
+    static __secondary_stacks_start: u8;
+    static __secondary_stacks_end: u8;
+    static _heap_start: u8;
+    static _heap_end: u8;
+}
+
+// Half-open address range [start, end)
+#[derive(Clone, Copy)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Region {
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn overlaps(&self, other: &Region) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    // Check if [addr, addr + len) lies inside the region
+    pub fn contains(&self, addr: usize, len: usize) -> bool {
+        addr >= self.start && addr.checked_add(len).is_some_and(|end| end <= self.end)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#010x}..{:#010x} ({} KiB)", self.start, self.end, self.len() / 1024)
+    }
+}
+
+// Zero-initialized data
+pub fn bss_region() -> Region {
+    unsafe {
+        Region {
+            start: &__bss_start as *const u8 as usize,
+            end: &__bss_end as *const u8 as usize,
+        }
+    }
+}
+
+// Boot stack (grows down from end)
+pub fn stack_region() -> Region {
+    unsafe {
+        Region {
+            start: &__stack_start as *const u8 as usize,
+            end: &__stack_end as *const u8 as usize,
+        }
+    }
+}
+
+// Stacks for secondary cores, one slot per core position
+pub fn secondary_stack_region() -> Region {
+    unsafe {
+        Region {
+            start: &__secondary_stacks_start as *const u8 as usize,
+            end: &__secondary_stacks_end as *const u8 as usize,
+        }
+    }
+}
+
+// Global allocator arena
+pub fn heap_region() -> Region {
+    unsafe {
+        Region {
+            start: &_heap_start as *const u8 as usize,
+            end: &_heap_end as *const u8 as usize,
+        }
+    }
+}
+
+// Print the linker-defined regions and panic if any of them overlap
+// Called once the console is up, early in arch::init
+pub fn validate_layout() {
+    let bss = bss_region();
+    let stack = stack_region();
+    let heap = heap_region();
+    let secondary = secondary_stack_region();
+
+    crate::println!("Memory layout:");
+    crate::println!("  bss:   {}", bss);
+    crate::println!("  stack: {}", stack);
+    crate::println!("  heap:  {}", heap);
+    crate::println!("  secondary stacks: {}", secondary);
+
+    assert!(!stack.is_empty() && !heap.is_empty(), "empty stack or heap region, check link.ld");
+    assert!(!stack.overlaps(&heap), "stack {} overlaps heap {}", stack, heap);
+    assert!(!stack.overlaps(&bss), "stack {} overlaps bss {}", stack, bss);
+    assert!(!heap.overlaps(&bss), "heap {} overlaps bss {}", heap, bss);
+    assert!(!secondary.overlaps(&stack) && !secondary.overlaps(&heap),
+            "secondary stacks {} overlap the boot stack or heap", secondary);
+}
+
+// Global allocator that can be used from interrupt handlers
+// The heap's spinlock is only ever taken with IRQs masked, so an ISR that
+// allocates cannot deadlock against the task it interrupted
+// It also records the least free memory seen since boot.
+pub struct IrqSafeHeap {
+    heap: LockedHeap,
+    min_free: AtomicUsize,      // Low-water mark of free bytes
+}
+
+impl IrqSafeHeap {
+    pub const fn empty() -> Self {
+        IrqSafeHeap {
+            heap: LockedHeap::empty(),
+            min_free: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    // Hand the heap its memory; must be called once, before any allocation
+    pub unsafe fn init(&self, start: usize, len: usize) {
+        self.with_heap(|heap| heap.init(start as *mut u8, len));
+    }
+
+    // Run `f` on the locked heap with IRQs masked
+    pub fn with_heap<R>(&self, f: impl FnOnce(&mut Heap) -> R) -> R {
+        aarch64::with_irqs_masked(|| f(&mut self.heap.lock()))
+    }
+
+    // Least free memory seen since boot, in bytes
+    pub fn min_free(&self) -> usize {
+        self.with_heap(|heap| self.min_free.load(Ordering::Relaxed).min(heap.free()))
+    }
+}
+
+unsafe impl GlobalAlloc for IrqSafeHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with_heap(|heap| {
+            let ptr = heap.allocate_first_fit(layout).map_or(ptr::null_mut(), |ptr| ptr.as_ptr());
+            self.min_free.fetch_min(heap.free(), Ordering::Relaxed);
+            ptr
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        aarch64::with_irqs_masked(|| self.heap.dealloc(ptr, layout))
+    }
+}
+
+// Snapshot of the global allocator, in bytes
+#[derive(Clone, Copy, Debug)]
+pub struct HeapStats {
+    pub total: usize,
+    pub used: usize,
+    pub free: usize,
+    pub largest_free_block: usize,
+    pub min_free: usize,        // Least free memory seen since boot
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "heap: {} used, {} free of {} (largest free block {}, low water {})",
+               self.used, self.free, self.total, self.largest_free_block, self.min_free)
+    }
+}
+
+// Query the global allocator
+// linked_list_allocator does not expose its free list, so the largest free
+// block is found by binary-searching first-fit probe allocations, each freed
+// again immediately. The heap lock is held throughout so the result is
+// consistent with the totals.
+pub fn heap_stats() -> HeapStats {
+    let min_free = crate::ALLOCATOR.min_free();
+    crate::ALLOCATOR.with_heap(|heap| {
+        let total = heap.size();
+        let used = heap.used();
+        let free = heap.free();
+
+        // Largest size known to fit, and smallest known not to
+        let mut fits = 0;
+        let mut too_big = free + 1;
+        while too_big - fits > 1 {
+            let probe = fits + (too_big - fits) / 2;
+            let layout = match Layout::from_size_align(probe, 8) {
+                Ok(layout) => layout,
+                Err(_) => break,
+            };
+            match heap.allocate_first_fit(layout) {
+                Ok(ptr) => {
+                    unsafe { heap.deallocate(ptr, layout) };
+                    fits = probe;
+                }
+                Err(_) => too_big = probe,
+            }
+        }
+
+        HeapStats {
+            total,
+            used,
+            free,
+            largest_free_block: fits,
+            min_free: min_free.min(free),
+        }
+    })
+}
+
+// Print heap usage, the output of a `free` console command
+pub fn print_heap_stats() {
+    let stats = heap_stats();
+    crate::println!("   total    used    free  largest  lowfree");
+    crate::println!("{:>8}{:>8}{:>8}{:>9}{:>9}",
+                    stats.total, stats.used, stats.free, stats.largest_free_block, stats.min_free);
+}
@@ -0,0 +1,373 @@
+// Identity-mapped translation tables for EL1
+// 4 KiB granule, 39-bit VA (TTBR0 only). The low 4 GiB are mapped 1:1:
+//   0x0000_0000 - 0x3FFF_FFFF  2 MiB blocks: SRAM normal, the rest device
+//   0x4000_0000 - 0x7FFF_FFFF  device (peripherals, GIC)
+//   0x8000_0000 - 0xFFFF_FFFF  normal cacheable (DRAM)
+// Everything above 4 GiB faults until map() adds 4 KiB pages there.
+
+use core::arch::asm;
+use super::aarch64::{MairEl1, SctlrEl1, SysReg, SysRegWrite, TcrEl1, Ttbr0El1};
+use super::sync::SpinLock;
+
+// MAIR_EL1 attribute indices
+const MAIR_IDX_DEVICE: u64 = 0;     // Device-nGnRnE
+const MAIR_IDX_NORMAL: u64 = 1;     // Normal, inner/outer write-back RA/WA
+const MAIR_VALUE: u64 = (0x00 << (8 * MAIR_IDX_DEVICE)) | (0xFF << (8 * MAIR_IDX_NORMAL));
+
+// Descriptor bits
+const DESC_VALID: u64 = 0b01;
+const DESC_TYPE_MASK: u64 = 0b11;
+const DESC_BLOCK: u64 = 0b01;
+const DESC_TABLE: u64 = 0b11;
+const DESC_PAGE: u64 = 0b11;        // Level 3 descriptors use the table encoding
+const DESC_ATTR_SHIFT: u64 = 2;
+const DESC_AP_RO: u64 = 1 << 7;     // AP[2]: read-only at EL1
+const DESC_SH_INNER: u64 = 0b11 << 8;
+const DESC_ADDR_MASK: u64 = 0x0000_FFFF_FFFF_F000;
+const DESC_AF: u64 = 1 << 10;
+const DESC_PXN: u64 = 1 << 53;
+const DESC_UXN: u64 = 1 << 54;
+
+// TCR_EL1: T0SZ = 25 (39-bit VA), non-cacheable walks (the tables are
+// written before the caches are on), inner shareable, 4 KiB granule,
+// TTBR1 walks disabled, 36-bit physical addresses
+const TCR_T0SZ: u64 = 25;
+const TCR_SH0_INNER: u64 = 0b11 << 12;
+const TCR_EPD1: u64 = 1 << 23;
+const TCR_IPS_36BIT: u64 = 0b001 << 32;
+const TCR_VALUE: u64 = TCR_T0SZ | TCR_SH0_INNER | TCR_EPD1 | TCR_IPS_36BIT;
+
+const SCTLR_M: u64 = 1 << 0;
+
+const BLOCK_1G: u64 = 1 << 30;
+const BLOCK_2M: u64 = 1 << 21;
+const PAGE_SIZE: usize = 4096;
+
+// Top of the 39-bit VA space
+const VA_LIMIT: usize = 1 << (64 - TCR_T0SZ);
+
+// Tables map() can take for levels 2 and 3; they are never given back
+const POOL_TABLES: usize = 16;
+
+// Above this many pages a range flush invalidates the whole TLB instead
+const FLUSH_PAGES_MAX: usize = 64;
+
+// On-chip SRAM in the first GiB
+const SRAM_START: u64 = 0x3400_0000;
+const SRAM_END: u64 = 0x4000_0000;
+
+#[repr(C, align(4096))]
+struct Table([u64; 512]);
+
+static mut L1_TABLE: Table = Table([0; 512]);
+static mut L2_TABLE_GB0: Table = Table([0; 512]);
+
+// Tables for runtime mappings; the lock also serializes all changes to L1
+struct TablePool {
+    tables: [Table; POOL_TABLES],
+    used: usize,
+}
+
+static POOL: SpinLock<TablePool> = SpinLock::new(TablePool {
+    tables: [const { Table([0; 512]) }; POOL_TABLES],
+    used: 0,
+});
+
+// Memory type of a runtime mapping
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MemType {
+    Device,     // Device-nGnRnE, never executable
+    Normal,     // Normal, write-back cacheable, inner shareable
+}
+
+// Attributes of a runtime mapping
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MemAttr {
+    pub mem_type: MemType,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+impl MemAttr {
+    pub const DEVICE: MemAttr = MemAttr { mem_type: MemType::Device, writable: true, executable: false };
+    pub const NORMAL_RW: MemAttr = MemAttr { mem_type: MemType::Normal, writable: true, executable: false };
+    pub const NORMAL_RO: MemAttr = MemAttr { mem_type: MemType::Normal, writable: false, executable: false };
+    pub const NORMAL_RX: MemAttr = MemAttr { mem_type: MemType::Normal, writable: false, executable: true };
+
+    // Level 3 page descriptor for `pa`
+    fn page_descriptor(&self, pa: u64) -> u64 {
+        let mut desc = pa | DESC_PAGE | DESC_AF;
+        desc |= match self.mem_type {
+            MemType::Device => (MAIR_IDX_DEVICE << DESC_ATTR_SHIFT) | DESC_PXN | DESC_UXN,
+            MemType::Normal => (MAIR_IDX_NORMAL << DESC_ATTR_SHIFT) | DESC_SH_INNER,
+        };
+        if !self.writable {
+            desc |= DESC_AP_RO;
+        }
+        if !self.executable {
+            desc |= DESC_PXN | DESC_UXN;
+        }
+        desc
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MmuError {
+    Misaligned,     // Address or size not a multiple of 4 KiB, or size zero
+    OutOfRange,     // Range runs past the 39-bit VA space
+    Overlap,        // Part of the range is already mapped
+    NotMapped,      // Part of the range is not a map() page
+    NoTables,       // Table pool exhausted
+}
+
+fn device_block(addr: u64) -> u64 {
+    addr | DESC_BLOCK | (MAIR_IDX_DEVICE << DESC_ATTR_SHIFT) | DESC_AF | DESC_PXN | DESC_UXN
+}
+
+fn normal_block(addr: u64) -> u64 {
+    addr | DESC_BLOCK | (MAIR_IDX_NORMAL << DESC_ATTR_SHIFT) | DESC_SH_INNER | DESC_AF
+}
+
+// Clean a table to the point of coherency so the (non-cacheable) walker sees it
+fn clean_table(table: *const Table) {
+    let start = table as usize;
+    for line in (start..start + core::mem::size_of::<Table>()).step_by(64) {
+        unsafe { asm!("dc cvac, {}", in(reg) line, options(nostack)); }
+    }
+}
+
+// Build the tables and turn on the MMU
+// Must run before the data and instruction caches are enabled
+pub fn init() {
+    unsafe {
+        let l1 = &mut *core::ptr::addr_of_mut!(L1_TABLE);
+        let l2 = &mut *core::ptr::addr_of_mut!(L2_TABLE_GB0);
+
+        for (i, entry) in l2.0.iter_mut().enumerate() {
+            let addr = i as u64 * BLOCK_2M;
+            *entry = if (SRAM_START..SRAM_END).contains(&addr) {
+                normal_block(addr)
+            } else {
+                device_block(addr)
+            };
+        }
+
+        l1.0[0] = (l2 as *const Table as u64) | DESC_TABLE;
+        l1.0[1] = device_block(BLOCK_1G);
+        l1.0[2] = normal_block(2 * BLOCK_1G);
+        l1.0[3] = normal_block(3 * BLOCK_1G);
+
+        clean_table(l1);
+        clean_table(l2);
+        asm!("dsb ish", options(nostack));
+    }
+
+    enable();
+}
+
+// Turn on the MMU on a secondary core, using the tables the boot core built
+pub fn init_secondary() {
+    enable();
+}
+
+// Program the translation registers for the shared tables and set SCTLR.M
+fn enable() {
+    unsafe {
+        let l1 = core::ptr::addr_of!(L1_TABLE);
+
+        MairEl1::write(MAIR_VALUE);
+        TcrEl1::write(TCR_VALUE);
+        Ttbr0El1::write(l1 as u64);
+        asm!("isb", options(nostack));
+
+        // Drop any translations left over from firmware
+        asm!("tlbi vmalle1", "dsb ish", "isb", options(nostack));
+
+        let sctlr = SctlrEl1::read();
+        SctlrEl1::write(sctlr | SCTLR_M);
+        asm!("isb", options(nostack));
+    }
+}
+
+// Invalidate every EL1 translation on all cores in the inner shareable domain
+// Call after changing descriptors (and cleaning them, as walks do not snoop
+// the caches): the leading dsb makes the table writes visible to the walkers
+// before the invalidate, the trailing dsb waits for it to finish on every
+// core, and the isb refetches with the new translations.
+pub fn flush_tlb_all() {
+    unsafe {
+        asm!("dsb ishst", "tlbi vmalle1is", "dsb ish", "isb", options(nostack));
+    }
+}
+
+// Invalidate the translations for one page on all cores, any ASID
+// Same ordering as flush_tlb_all; use it when a single mapping changed.
+pub fn flush_tlb_page(va: usize) {
+    // VA[55:12] goes in bits [43:0] of the operand
+    let operand = (va as u64 >> 12) & ((1 << 44) - 1);
+    unsafe {
+        asm!(
+            "dsb ishst",
+            "tlbi vaae1is, {}",
+            "dsb ish",
+            "isb",
+            in(reg) operand,
+            options(nostack)
+        );
+    }
+}
+
+// Table index of `va` at level 1, 2 or 3
+fn table_index(va: usize, level: u32) -> usize {
+    (va >> (12 + 9 * (3 - level))) & 0x1FF
+}
+
+// Table a descriptor points to, if it is a table descriptor
+fn next_table(desc: u64) -> Option<*mut Table> {
+    if desc & DESC_TYPE_MASK == DESC_TABLE {
+        Some((desc & DESC_ADDR_MASK) as *mut Table)
+    } else {
+        None
+    }
+}
+
+// Clean one descriptor to the point of coherency for the walker
+fn clean_entry(entry: *const u64) {
+    unsafe { asm!("dc cvac, {}", in(reg) entry, options(nostack)); }
+}
+
+fn check_range(va: usize, size: usize) -> Result<(), MmuError> {
+    if size == 0 || va % PAGE_SIZE != 0 || size % PAGE_SIZE != 0 {
+        return Err(MmuError::Misaligned);
+    }
+    match va.checked_add(size) {
+        Some(end) if end <= VA_LIMIT => Ok(()),
+        _ => Err(MmuError::OutOfRange),
+    }
+}
+
+// Level 3 entry for `va`, if levels 1 and 2 lead to a level 3 table
+// Ok(None) means a table is still missing; Err means a block maps `va`.
+// Must be called with the pool locked
+unsafe fn page_entry(va: usize) -> Result<Option<*mut u64>, MmuError> {
+    let mut table = core::ptr::addr_of_mut!(L1_TABLE);
+    for level in 1..=2 {
+        let desc = (*table).0[table_index(va, level)];
+        if desc & DESC_VALID == 0 {
+            return Ok(None);
+        }
+        table = next_table(desc).ok_or(MmuError::Overlap)?;
+    }
+    Ok(Some(&mut (*table).0[table_index(va, 3)] as *mut u64))
+}
+
+// Invalidate the translations for [va, va + size) on all cores
+fn flush_range(va: usize, size: usize) {
+    let pages = size / PAGE_SIZE;
+    if pages > FLUSH_PAGES_MAX {
+        flush_tlb_all();
+    } else {
+        for page in 0..pages {
+            flush_tlb_page(va + page * PAGE_SIZE);
+        }
+    }
+}
+
+// Map [va, va + size) to [pa, pa + size) in 4 KiB pages with `attrs`
+// Missing level 2 and 3 tables come from a fixed pool. The range must be
+// unmapped: the boot blocks are never split, so only VAs outside them (above
+// 4 GiB) can be mapped. Nothing is changed if the request fails.
+pub fn map(va: usize, pa: usize, size: usize, attrs: MemAttr) -> Result<(), MmuError> {
+    check_range(va, size)?;
+    if pa % PAGE_SIZE != 0 {
+        return Err(MmuError::Misaligned);
+    }
+
+    let mut pool = POOL.lock();
+    unsafe {
+        // Check the whole range and count the tables it needs before writing
+        let mut needed = 0;
+        let mut last_missing = [usize::MAX; 2];
+        for page in (va..va + size).step_by(PAGE_SIZE) {
+            match page_entry(page)? {
+                Some(entry) if *entry & DESC_VALID != 0 => return Err(MmuError::Overlap),
+                Some(_) => {}
+                None => {
+                    // One table per missing 1 GiB and 2 MiB region
+                    let l1 = &*core::ptr::addr_of!(L1_TABLE);
+                    let gb = page / BLOCK_1G as usize;
+                    if l1.0[table_index(page, 1)] & DESC_VALID == 0 && last_missing[0] != gb {
+                        last_missing[0] = gb;
+                        needed += 1;
+                    }
+                    let mb = page / BLOCK_2M as usize;
+                    if last_missing[1] != mb {
+                        last_missing[1] = mb;
+                        needed += 1;
+                    }
+                }
+            }
+        }
+        if pool.used + needed > POOL_TABLES {
+            return Err(MmuError::NoTables);
+        }
+
+        for offset in (0..size).step_by(PAGE_SIZE) {
+            let page = va + offset;
+            let mut table = core::ptr::addr_of_mut!(L1_TABLE);
+            for level in 1..=2 {
+                let entry = &mut (*table).0[table_index(page, level)];
+                if *entry & DESC_VALID == 0 {
+                    let index = pool.used;
+                    pool.used += 1;
+                    let new = &mut pool.tables[index] as *mut Table;
+                    (*new).0 = [0; 512];
+                    clean_table(new);
+                    asm!("dsb ishst", options(nostack));
+                    *entry = new as u64 | DESC_TABLE;
+                    clean_entry(entry);
+                }
+                table = next_table(*entry).ok_or(MmuError::Overlap)?;
+            }
+            let entry = &mut (*table).0[table_index(page, 3)];
+            *entry = attrs.page_descriptor((pa + offset) as u64);
+            clean_entry(entry);
+        }
+    }
+    drop(pool);
+
+    flush_range(va, size);
+    Ok(())
+}
+
+// Remove the pages map() installed for [va, va + size)
+// The tables stay in place for later mappings. Nothing is changed if part of
+// the range is not a mapped page.
+pub fn unmap(va: usize, size: usize) -> Result<(), MmuError> {
+    check_range(va, size)?;
+
+    let pool = POOL.lock();
+    unsafe {
+        for page in (va..va + size).step_by(PAGE_SIZE) {
+            match page_entry(page) {
+                Ok(Some(entry)) if *entry & DESC_VALID != 0 => {}
+                _ => return Err(MmuError::NotMapped),
+            }
+        }
+        for page in (va..va + size).step_by(PAGE_SIZE) {
+            if let Ok(Some(entry)) = page_entry(page) {
+                *entry = 0;
+                clean_entry(entry);
+            }
+        }
+    }
+    drop(pool);
+
+    flush_range(va, size);
+    Ok(())
+}
+
+// Check if the MMU is on
+pub fn is_enabled() -> bool {
+    unsafe { SctlrEl1::read() & SCTLR_M != 0 }
+}
@@ -1,7 +1,34 @@
 pub mod aarch64;
+pub mod backtrace;
+pub mod board;
+pub mod clocks;
+pub mod core_set;
+pub mod dma;
 pub mod s32g3;
 pub mod gic;
+pub mod esr;
 pub mod exceptions;
+pub mod generic_timer;
+pub mod init;
+pub mod loader;
+pub mod mem;
+pub mod mmu;
+pub mod percpu;
+pub mod profile;
+pub mod psci;
+pub mod semihosting;
+pub mod smp;
+pub mod spin_table;
+pub mod sync;
+pub mod trace;
+
+pub use core_set::CoreSet;
+
+use init::InitPhase;
+use crate::drivers::uart;
+
+// Cores that have completed their bring-up
+pub static CORE_STATES: CoreSet = CoreSet::new();
 
 // Interrupt related functions
 pub fn enable_interrupt(irq_num: u32) {
@@ -28,6 +55,11 @@ pub fn send_sgi(sgi_id: u32, target_list: u8) {
     gic::send_sgi(sgi_id, target_list, 0);
 } 
 
+// Exception functions
+pub fn set_fault_handler(handler: exceptions::FaultHandler) {
+    exceptions::set_fault_handler(handler);
+}
+
 // CPU core functions
 pub fn enable_interrupts() {
     unsafe {
@@ -45,8 +77,27 @@ pub fn wait_for_interrupt() {
     aarch64::wfi();
 }
 
-pub fn cpu_id() -> u8 {
-    aarch64::cpu_id()
+// Sleep in wfe until `done` returns true or `us` microseconds have passed
+// `done` is checked before sleeping and after every wake (a sev, an interrupt,
+// or the event stream), so it should be cheap. Returns true if `done` was
+// satisfied, false on timeout. The event stream bounds each wfe, so the
+// timeout may overrun by up to generic_timer::EVENT_STREAM_US.
+pub fn wait_for_event_timeout(us: u32, mut done: impl FnMut() -> bool) -> bool {
+    let ticks = generic_timer::frequency() * us as u64 / 1_000_000;
+    let start = generic_timer::count();
+    loop {
+        if done() {
+            return true;
+        }
+        if generic_timer::count().wrapping_sub(start) >= ticks {
+            return false;
+        }
+        aarch64::wfe();
+    }
+}
+
+pub fn core_pos() -> usize {
+    aarch64::core_pos()
 }
 
 pub fn current_el() -> u8 {
@@ -75,8 +126,22 @@ pub fn delay_ms(ms: u32) {
     s32g3::timer::delay_ms(ms);
 }
 
-// Hardware initialization
+// Hardware initialization for the boot core
+// Runs each hardware phase once, in order (see init.rs)
 pub fn init() {
-    s32g3::init();
-    gic::init();  // Initialize GIC for this core
+    percpu::init();
+    CORE_STATES.mark_online(percpu::this_cpu().core_pos);
+    generic_timer::enable_event_stream();
+
+    init::run_phase(InitPhase::EarlyConsole, || {
+        s32g3::init();
+        aarch64::enable_fpu();
+        exceptions::init_vectors();
+        mmu::init();
+        uart::init();
+        mem::validate_layout();
+    });
+    init::run_phase(InitPhase::Gic, gic::init);
+    init::run_phase(InitPhase::Timer, s32g3::timer::init);
+    init::run_phase(InitPhase::Interrupts, enable_interrupts);
 }
\ No newline at end of file
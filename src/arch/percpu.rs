@@ -0,0 +1,74 @@
+// Per-core data, reached through TPIDR_EL1
+// Each core points TPIDR_EL1 at its own PerCpu block during init, so
+// this_cpu() is a single register read rather than an MPIDR decode.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use super::board::MAX_CORES;
+
+pub struct PerCpu {
+    pub core_pos: usize,            // Linear core position (cluster * cores_per_cluster + core)
+    pub current_task: AtomicUsize,  // Task running on this core
+    pub isr_nesting: AtomicU32,     // ISR nesting depth, 0 in task context
+    pub critical_nesting: AtomicU32,    // Critical section depth of the running task
+    pub critical_daif: AtomicU64,       // DAIF to restore when the outermost section exits
+    pub switched_in_at: AtomicU64,      // Generic timer count when current_task was switched in
+    pub need_resched: AtomicBool,       // Switch tasks on the way out of the next IRQ/SVC
+    pub switch_locked: AtomicBool,      // KERNEL_LOCK held across a switch until off the old stack
+}
+
+impl PerCpu {
+    const fn new(core_pos: usize) -> Self {
+        PerCpu {
+            core_pos,
+            current_task: AtomicUsize::new(0),
+            isr_nesting: AtomicU32::new(0),
+            critical_nesting: AtomicU32::new(0),
+            critical_daif: AtomicU64::new(0),
+            switched_in_at: AtomicU64::new(0),
+            need_resched: AtomicBool::new(false),
+            switch_locked: AtomicBool::new(false),
+        }
+    }
+}
+
+static PER_CPU: [PerCpu; MAX_CORES] = {
+    let mut blocks = [const { PerCpu::new(0) }; MAX_CORES];
+    let mut i = 0;
+    while i < MAX_CORES {
+        blocks[i] = PerCpu::new(i);
+        i += 1;
+    }
+    blocks
+};
+
+// Point TPIDR_EL1 at this core's block; called early on every core
+pub fn init() {
+    let block = &PER_CPU[super::aarch64::core_pos()] as *const PerCpu as u64;
+    unsafe {
+        asm!("msr tpidr_el1, {}", in(reg) block, options(nostack));
+    }
+}
+
+// This core's per-CPU block
+pub fn this_cpu() -> &'static PerCpu {
+    let block: u64;
+    unsafe {
+        asm!("mrs {}, tpidr_el1", out(reg) block, options(nomem, nostack));
+    }
+    if block == 0 {
+        // Before init on this core
+        return &PER_CPU[super::aarch64::core_pos()];
+    }
+    unsafe { &*(block as *const PerCpu) }
+}
+
+// Per-CPU block of another core, by linear position
+pub fn cpu(core_pos: usize) -> Option<&'static PerCpu> {
+    PER_CPU.get(core_pos)
+}
+
+// Check if this core is inside an ISR
+pub fn in_isr() -> bool {
+    this_cpu().isr_nesting.load(Ordering::Relaxed) != 0
+}
@@ -0,0 +1,123 @@
+// Lightweight on-target profiling
+// profile::start("name") returns a Span; when the span is dropped the elapsed
+// time is accumulated into a global bucket for that name. Buckets are claimed
+// lock-free so spans can be used from interrupt handlers.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use crate::arch::s32g3::timer;
+
+// Maximum number of distinct span names
+const MAX_BUCKETS: usize = 16;
+
+// A name is packed into one word as its length above its address, so a
+// bucket's name is claimed and published by a single compare-exchange
+const NAME_LEN_SHIFT: u32 = 48;
+const NAME_PTR_MASK: u64 = (1 << NAME_LEN_SHIFT) - 1;
+
+// Accumulated time for one span name
+struct Bucket {
+    name: AtomicU64,        // Packed name, 0 while the bucket is free
+    count: AtomicU64,
+    total_ns: AtomicU64,
+}
+
+// Pack a name for Bucket::name
+// Names are string literals, so they fit in the low 48 bits of the address
+// space and are far shorter than 64 KiB
+fn pack_name(name: &'static str) -> u64 {
+    let ptr = name.as_ptr() as u64;
+    assert!(ptr & !NAME_PTR_MASK == 0 && name.len() >> (64 - NAME_LEN_SHIFT) == 0,
+            "profile: span name {:?} cannot be packed", name);
+    ((name.len() as u64) << NAME_LEN_SHIFT) | ptr
+}
+
+impl Bucket {
+    const fn new() -> Self {
+        Bucket {
+            name: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+            total_ns: AtomicU64::new(0),
+        }
+    }
+
+    // Name of the bucket, None if free
+    fn name(&self) -> Option<&'static str> {
+        let packed = self.name.load(Ordering::Acquire);
+        if packed == 0 {
+            return None;
+        }
+        let ptr = (packed & NAME_PTR_MASK) as *const u8;
+        let len = (packed >> NAME_LEN_SHIFT) as usize;
+        unsafe { Some(core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len))) }
+    }
+}
+
+static BUCKETS: [Bucket; MAX_BUCKETS] = [const { Bucket::new() }; MAX_BUCKETS];
+
+// Find the bucket for a name, claiming a free one if needed
+fn bucket_for(name: &'static str) -> Option<&'static Bucket> {
+    let packed = pack_name(name);
+    for bucket in BUCKETS.iter() {
+        match bucket.name() {
+            Some(existing) if existing == name => return Some(bucket),
+            Some(_) => continue,
+            None => {},
+        }
+
+        // Lost the race if someone claimed the slot first, possibly for the
+        // same name; the name is complete either way
+        match bucket.name.compare_exchange(0, packed, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => return Some(bucket),
+            Err(_) if bucket.name() == Some(name) => return Some(bucket),
+            Err(_) => {},
+        }
+    }
+
+    None
+}
+
+// A timed section of code, accumulated into its bucket when dropped
+pub struct Span {
+    bucket: Option<&'static Bucket>,
+    start_ns: u64,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(bucket) = self.bucket {
+            let elapsed = timer::now_ns().saturating_sub(self.start_ns);
+            bucket.count.fetch_add(1, Ordering::Relaxed);
+            bucket.total_ns.fetch_add(elapsed, Ordering::Relaxed);
+        }
+    }
+}
+
+// Start timing a named span
+// If all buckets are in use the span is not recorded
+pub fn start(name: &'static str) -> Span {
+    Span {
+        bucket: bucket_for(name),
+        start_ns: timer::now_ns(),
+    }
+}
+
+// Clear all accumulated times (bucket names are kept)
+pub fn reset() {
+    for bucket in BUCKETS.iter() {
+        bucket.count.store(0, Ordering::Relaxed);
+        bucket.total_ns.store(0, Ordering::Relaxed);
+    }
+}
+
+// Print call count, total and average time for every bucket
+pub fn report() {
+    crate::println!("{:<24} {:>10} {:>14} {:>10}", "SPAN", "COUNT", "TOTAL (ns)", "AVG (ns)");
+    for bucket in BUCKETS.iter() {
+        if let Some(name) = bucket.name() {
+            let count = bucket.count.load(Ordering::Relaxed);
+            let total = bucket.total_ns.load(Ordering::Relaxed);
+            let avg = total.checked_div(count).unwrap_or(0);
+            crate::println!("{:<24} {:>10} {:>14} {:>10}", name, count, total, avg);
+        }
+    }
+}
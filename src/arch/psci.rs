@@ -0,0 +1,135 @@
+// PSCI client
+// Power management calls into the secure monitor (TF-A), which owns core and
+// system power on S32G. Return values follow PSCI: zero or positive on
+// success, negative error codes otherwise.
+
+use core::arch::asm;
+use super::aarch64;
+
+// PSCI 0.2+ function IDs
+pub const PSCI_VERSION: u32 = 0x8400_0000;
+pub const CPU_SUSPEND: u32 = 0xC400_0001;
+pub const CPU_OFF: u32 = 0x8400_0002;
+pub const CPU_ON: u32 = 0xC400_0003;
+pub const SYSTEM_OFF: u32 = 0x8400_0008;
+pub const SYSTEM_RESET: u32 = 0x8400_0009;
+pub const PSCI_FEATURES: u32 = 0x8400_000A;
+
+// PSCI error codes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PsciError {
+    NotSupported,       // -1
+    InvalidParameters,  // -2
+    Denied,             // -3
+    AlreadyOn,          // -4
+    OnPending,          // -5
+    InternalFailure,    // -6
+    NotPresent,         // -7
+    Disabled,           // -8
+    InvalidAddress,     // -9
+    Unknown(i32),
+}
+
+impl PsciError {
+    fn from_code(code: i32) -> Self {
+        match code {
+            -1 => PsciError::NotSupported,
+            -2 => PsciError::InvalidParameters,
+            -3 => PsciError::Denied,
+            -4 => PsciError::AlreadyOn,
+            -5 => PsciError::OnPending,
+            -6 => PsciError::InternalFailure,
+            -7 => PsciError::NotPresent,
+            -8 => PsciError::Disabled,
+            -9 => PsciError::InvalidAddress,
+            other => PsciError::Unknown(other),
+        }
+    }
+}
+
+// Issue a PSCI call with up to three arguments and return the raw result
+pub fn call(function: u32, arg0: u64, arg1: u64, arg2: u64) -> i64 {
+    let mut ret = function as u64;
+    unsafe {
+        asm!(
+            "smc #0",
+            inout("x0") ret,
+            inout("x1") arg0 => _,
+            inout("x2") arg1 => _,
+            inout("x3") arg2 => _,
+            options(nostack)
+        );
+    }
+    ret as i64
+}
+
+// Map a raw PSCI return value to a Result
+fn check(ret: i64) -> Result<u32, PsciError> {
+    // Results are 32-bit; errors are sign-extended negative values
+    let ret = ret as i32;
+    if ret < 0 {
+        Err(PsciError::from_code(ret))
+    } else {
+        Ok(ret as u32)
+    }
+}
+
+// PSCI version implemented by the monitor, as (major, minor)
+pub fn version() -> (u16, u16) {
+    let version = call(PSCI_VERSION, 0, 0, 0) as u32;
+    ((version >> 16) as u16, version as u16)
+}
+
+// Check whether the monitor implements a function
+// Returns the function's feature flags on success, NotSupported otherwise
+pub fn features(function: u32) -> Result<u32, PsciError> {
+    check(call(PSCI_FEATURES, function as u64, 0, 0))
+}
+
+// Power on a core at `entry`, which receives `context` in x0
+pub fn cpu_on(mpidr: u64, entry: u64, context: u64) -> Result<(), PsciError> {
+    check(call(CPU_ON, mpidr, entry, context)).map(|_| ())
+}
+
+// Power state requested from CPU_SUSPEND (original power_state format)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PowerState {
+    Standby,    // Core stays powered and returns from the call on wakeup
+    Powerdown,  // Core loses context and restarts at the entry point
+}
+
+impl PowerState {
+    // power_state argument: StateType in bit 16, core-level StateID 0
+    fn encode(self) -> u64 {
+        match self {
+            PowerState::Standby => 0,
+            PowerState::Powerdown => 1 << 16,
+        }
+    }
+}
+
+// Suspend the calling core
+// Standby returns on wakeup; powerdown only returns if the monitor declined
+// it (or a wakeup was already pending), otherwise the core restarts at
+// `entry` with `context` in x0
+pub fn cpu_suspend(state: PowerState, entry: u64, context: u64) -> Result<(), PsciError> {
+    check(call(CPU_SUSPEND, state.encode(), entry, context)).map(|_| ())
+}
+
+// Power the whole system off
+pub fn system_off() -> ! {
+    call(SYSTEM_OFF, 0, 0, 0);
+    // The monitor should not return; park the core if it does
+    loop {
+        aarch64::wfe();
+    }
+}
+
+// Reset the whole system
+pub fn system_reset() -> ! {
+    call(SYSTEM_RESET, 0, 0, 0);
+    // The monitor should not return; park the core if it does
+    loop {
+        aarch64::wfe();
+    }
+}
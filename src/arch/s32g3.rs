@@ -2,57 +2,236 @@
 // Based on S32G3 Reference Manual
 
 use core::{arch, ptr::{read_volatile, write_volatile}};
+#[cfg(feature = "stm-delay")]
 use cortex_a::asm;
 
-use crate::drivers::uart;
+use super::board::BOARD;
+use super::clocks;
 
-use super::{enable_interrupts, exceptions::init_vectors, gic};
+// Peripheral base addresses come from the board configuration (see
+// arch/board.rs) and clock rates from arch/clocks.rs
 
-// S32G3 base addresses for key peripherals
-pub const UART_BASE: usize = 0x401C8000;  // LinFLEX UART0 base address
-pub const GIC_DIST_BASE: usize = 0x50800000;  // GIC-500 Distributor
-pub const GIC_CPU_BASE: usize = 0x50880000;   // GIC-500 CPU Interface
+// MC_CGM clock mux registers (mux n at n * MC_CGM_MUX_STRIDE)
+pub const MC_CGM_MUX_CSS: usize = 0x304;    // Mux Clock Select Status
+pub const MC_CGM_MUX_DC0: usize = 0x308;    // Mux Divider 0 Control
+pub const MC_CGM_MUX_STRIDE: usize = 0x40;
+pub const MC_CGM_MUX_PER: usize = 3;        // PER_CLK (STM)
+pub const MC_CGM_MUX_CAN_PE: usize = 7;     // CAN_PE_CLK (FlexCAN)
+pub const MC_CGM_MUX_LIN_BAUD: usize = 8;   // LIN_BAUD_CLK (LinFLEX)
+pub const MUX_CSS_SELSTAT_SHIFT: u32 = 24;  // Selected source
+pub const MUX_CSS_SELSTAT_MASK: u32 = 0x3F;
+pub const MUX_DC_DE: u32 = 1 << 31;         // Divider Enable
+pub const MUX_DC_DIV_SHIFT: u32 = 16;       // Divide by DIV + 1
+pub const MUX_DC_DIV_MASK: u32 = 0xFF;
+
+// MC_CGM clock source IDs
+pub const CGM_SRC_FIRC: u32 = 0;
+pub const CGM_SRC_FXOSC: u32 = 2;
+pub const CGM_SRC_PERIPH_PLL_PHI0: u32 = 18;    // PHI0-PHI7 are consecutive
+pub const PERIPH_PLL_NUM_PHI: u32 = 8;
+pub const FIRC_HZ: u64 = 48_000_000;        // Internal RC oscillator
+
+// PLLDIG register offsets
+pub const PLLDIG_PLLCR: usize = 0x00;       // PLL Control Register
+pub const PLLDIG_PLLDV: usize = 0x08;       // PLL Divider Register
+pub const PLLDIG_PLLFD: usize = 0x10;       // PLL Frequency Modulation Register
+pub const PLLDIG_PLLCLKMUX: usize = 0x20;   // PLL Reference Clock Select
+pub const PLLDIG_PLLODIV0: usize = 0x80;    // Output divider for PHI0, one word per PHI
+
+// PLLDIG register bit definitions
+pub const PLLCR_PLLPD: u32 = 1 << 31;       // PLL Power Down
+pub const PLLDV_MFI_MASK: u32 = 0xFF;       // Multiplication Factor Integer
+pub const PLLDV_RDIV_SHIFT: u32 = 12;       // Reference divider (0 means 1)
+pub const PLLDV_RDIV_MASK: u32 = 0x7;
+pub const PLLFD_MFN_MASK: u32 = 0x7FFF;     // Multiplication Factor Numerator
+pub const PLLFD_MFN_DEN: u64 = 18432;       // Fixed MFN denominator
+pub const PLLCLKMUX_FXOSC: u32 = 1;         // Reference is FXOSC (FIRC otherwise)
+pub const PLLODIV_DE: u32 = 1 << 31;        // Output divider enable
+pub const PLLODIV_DIV_SHIFT: u32 = 16;      // Divide by DIV + 1
+pub const PLLODIV_DIV_MASK: u32 = 0xFF;
 
 // LinFLEX UART register offsets
 pub const LINFLEX_LINCR1: usize = 0x00;     // LIN Control Register 1
+pub const LINFLEX_LINIER: usize = 0x04;     // LIN Interrupt Enable Register
 pub const LINFLEX_LINSR: usize = 0x08;      // LIN Status Register
 pub const LINFLEX_UARTCR: usize = 0x10;     // UART Mode Control Register
 pub const LINFLEX_UARTSR: usize = 0x14;     // UART Mode Status Register
 pub const LINFLEX_LINIBRR: usize = 0x40;    // LIN Integer Baud Rate Register
 pub const LINFLEX_LINFBRR: usize = 0x44;    // LIN Fractional Baud Rate Register
 pub const LINFLEX_BDRL: usize = 0x38;       // Buffer Data Register Least Significant
+pub const LINFLEX_BDRM: usize = 0x3C;       // Buffer Data Register Most Significant (RX data)
+pub const LINFLEX_GCR: usize = 0x4C;        // Global Control Register
 pub const LINFLEX_UARTPTO: usize = 0x50;    // UART Preset Timeout Register
 
 // LinFLEX UART register bit definitions
 pub const LINCR1_INIT: u32 = 1 << 0;        // Initialization Mode
 pub const LINCR1_MME: u32 = 1 << 4;         // Master Mode Enable
+pub const LINIER_DRIE: u32 = 1 << 2;        // Data Reception Complete Interrupt Enable
 pub const LINSR_LINS_MASK: u32 = 0xF;       // LIN State Field Mask
 pub const LINSR_LINS_INITMODE: u32 = 0x1;   // Initialization Mode
 pub const UARTCR_UART: u32 = 1 << 0;        // UART Mode
 pub const UARTCR_WL0: u32 = 1 << 1;         // Word Length bit 0 (8-bit)
+pub const UARTCR_PCE: u32 = 1 << 2;         // Parity Control Enable
 pub const UARTCR_PC0: u32 = 1 << 3;         // Parity Control bit 0
 pub const UARTCR_PC1: u32 = 1 << 4;         // Parity Control bit 1
+pub const UARTCR_WL1: u32 = 1 << 7;         // Word Length bit 1 (15/16-bit)
 pub const UARTCR_TXEN: u32 = 1 << 10;       // Transmitter Enable
 pub const UARTCR_RXEN: u32 = 1 << 11;       // Receiver Enable
 pub const UARTCR_TFBM: u32 = 1 << 8;        // Tx FIFO Buffer Mode
 pub const UARTCR_RFBM: u32 = 1 << 9;        // Rx FIFO Buffer Mode
 pub const UARTCR_ROSE: u32 = 1 << 23;       // Reduced Oversampling Enable
 pub const UARTCR_TFC: u32 = 0xF800;         // Tx FIFO Counter mask
+pub const GCR_STOP: u32 = 1 << 1;           // Two stop bits when set
+pub const LINIBRR_MAX: u32 = 0xF_FFFF;      // Integer baud divider field width
 pub const UARTSR_DTF: u32 = 1 << 1;         // Data Transmission Completed Flag
+pub const UARTSR_DRFRFE: u32 = 1 << 2;      // Data Reception Completed (buffer) / Rx FIFO Empty (FIFO)
+pub const UARTSR_RMB: u32 = 1 << 9;         // Release Message Buffer (buffer mode byte ready)
 
 // LinFLEX UART configuration values
-pub const UART_CLOCK_HZ: u32 = 80_000_000;  // 80 MHz UART clock
 pub const UART_BAUD_RATE: u32 = 115200;     // Default baud rate
 pub const LDIV_MULTIPLIER: u32 = 16;        // Default LIN divider multiplier
 
+// SWT register offsets
+pub const SWT_CR: usize = 0x00;             // Control Register
+pub const SWT_TO: usize = 0x08;             // Timeout Register
+pub const SWT_SR: usize = 0x10;             // Service Register
+
+// SWT register bit definitions and keys
+pub const SWT_CR_WEN: u32 = 1 << 0;         // Watchdog Enable
+pub const SWT_CR_FRZ: u32 = 1 << 1;         // Stop counting in debug mode
+pub const SWT_CR_SLK: u32 = 1 << 4;         // Soft Lock
+pub const SWT_CR_HLK: u32 = 1 << 5;         // Hard Lock (until reset)
+pub const SWT_UNLOCK_KEY1: u32 = 0xC520;    // Written to SR to clear the soft lock
+pub const SWT_UNLOCK_KEY2: u32 = 0xD928;
+pub const SWT_SERVICE_KEY1: u32 = 0xA602;   // Written to SR to restart the timeout
+pub const SWT_SERVICE_KEY2: u32 = 0xB480;
+pub const SWT_TO_MIN: u32 = 0x100;          // Smallest timeout the SWT accepts
+
+// SPI (DSPI) register offsets
+pub const SPI_MCR: usize = 0x00;            // Module Configuration Register
+pub const SPI_CTAR0: usize = 0x0C;          // Clock and Transfer Attributes Register 0
+pub const SPI_SR: usize = 0x2C;             // Status Register
+pub const SPI_PUSHR: usize = 0x34;          // TX FIFO Push Register
+pub const SPI_POPR: usize = 0x38;           // RX FIFO Pop Register
+
+// SPI register bit definitions
+pub const SPI_MCR_MSTR: u32 = 1 << 31;      // Master mode
+pub const SPI_MCR_PCSIS_MASK: u32 = 0xFF << 16; // Chip select inactive states (1 = high)
+pub const SPI_MCR_MDIS: u32 = 1 << 14;      // Module Disable
+pub const SPI_MCR_CLR_TXF: u32 = 1 << 11;   // Flush TX FIFO
+pub const SPI_MCR_CLR_RXF: u32 = 1 << 10;   // Flush RX FIFO
+pub const SPI_MCR_HALT: u32 = 1 << 0;       // Stop transfers
+pub const SPI_CTAR_FMSZ_SHIFT: u32 = 27;    // Frame size minus one
+pub const SPI_CTAR_CPOL: u32 = 1 << 26;     // Clock idles high
+pub const SPI_CTAR_CPHA: u32 = 1 << 25;     // Data captured on the second edge
+pub const SPI_CTAR_PBR_SHIFT: u32 = 16;     // Baud rate prescaler (2, 3, 5, 7)
+pub const SPI_SR_TCF: u32 = 1 << 31;        // Transfer Complete Flag
+pub const SPI_SR_TFUF: u32 = 1 << 27;       // TX FIFO Underflow Flag
+pub const SPI_SR_TFFF: u32 = 1 << 25;       // TX FIFO Fill Flag (room for a frame)
+pub const SPI_SR_RFOF: u32 = 1 << 19;       // RX FIFO Overflow Flag
+pub const SPI_SR_RFDF: u32 = 1 << 17;       // RX FIFO Drain Flag (frame waiting)
+pub const SPI_PUSHR_CONT: u32 = 1 << 31;    // Keep chip select asserted after this frame
+pub const SPI_PUSHR_PCS_SHIFT: u32 = 16;    // Chip select lines to assert
+pub const SPI_FIFO_DEPTH: usize = 4;        // Entries in each of the TX and RX FIFOs
+
+// I2C register offsets (8-bit registers)
+pub const I2C_IBFD: usize = 0x01;           // Bus Frequency Divider Register
+pub const I2C_IBCR: usize = 0x02;           // Bus Control Register
+pub const I2C_IBSR: usize = 0x03;           // Bus Status Register
+pub const I2C_IBDR: usize = 0x04;           // Data I/O Register
+
+// I2C register bit definitions
+pub const IBCR_MDIS: u8 = 1 << 7;           // Module Disable
+pub const IBCR_MSSL: u8 = 1 << 5;           // Master mode (setting it sends START, clearing it STOP)
+pub const IBCR_TXRX: u8 = 1 << 4;           // Transmit when set, receive when clear
+pub const IBCR_NOACK: u8 = 1 << 3;          // Do not acknowledge the next received byte
+pub const IBCR_RSTA: u8 = 1 << 2;           // Send a repeated START
+pub const IBSR_IBB: u8 = 1 << 5;            // Bus Busy
+pub const IBSR_IBAL: u8 = 1 << 4;           // Arbitration Lost (write 1 to clear)
+pub const IBSR_IBIF: u8 = 1 << 1;           // Byte transfer done (write 1 to clear)
+pub const IBSR_RXAK: u8 = 1 << 0;           // No acknowledge received
+
+// FlexCAN register offsets
+pub const FLEXCAN_MCR: usize = 0x00;        // Module Configuration Register
+pub const FLEXCAN_CTRL1: usize = 0x04;      // Control 1 Register
+pub const FLEXCAN_TIMER: usize = 0x08;      // Free Running Timer (read to unlock an MB)
+pub const FLEXCAN_RXMGMASK: usize = 0x10;   // Rx Mailboxes Global Mask
+pub const FLEXCAN_IMASK1: usize = 0x28;     // Interrupt Masks 1 (MB 0-31)
+pub const FLEXCAN_IFLAG1: usize = 0x30;     // Interrupt Flags 1 (MB 0-31, write 1 to clear)
+pub const FLEXCAN_MB0: usize = 0x80;        // Message buffer 0
+pub const FLEXCAN_MB_SIZE: usize = 0x10;    // CS, ID and two data words per 8-byte MB
+
+// FlexCAN MCR bit definitions
+pub const FLEXCAN_MCR_MDIS: u32 = 1 << 31;      // Module Disable
+pub const FLEXCAN_MCR_FRZ: u32 = 1 << 30;       // Freeze Enable
+pub const FLEXCAN_MCR_HALT: u32 = 1 << 28;      // Enter freeze mode
+pub const FLEXCAN_MCR_NOTRDY: u32 = 1 << 27;    // Not ready (disabled, frozen or stopped)
+pub const FLEXCAN_MCR_SOFTRST: u32 = 1 << 25;   // Soft Reset
+pub const FLEXCAN_MCR_FRZACK: u32 = 1 << 24;    // Freeze Mode Acknowledge
+pub const FLEXCAN_MCR_SUPV: u32 = 1 << 23;      // Supervisor-only register access
+pub const FLEXCAN_MCR_LPMACK: u32 = 1 << 20;    // Low-Power Mode Acknowledge
+pub const FLEXCAN_MCR_SRXDIS: u32 = 1 << 17;    // Do not receive own frames
+pub const FLEXCAN_MCR_MAXMB_MASK: u32 = 0x7F;   // Number of the last MB in use
+
+// FlexCAN CTRL1 bit timing fields (each segment field holds its length - 1)
+pub const FLEXCAN_CTRL1_PRESDIV_SHIFT: u32 = 24;
+pub const FLEXCAN_CTRL1_RJW_SHIFT: u32 = 22;
+pub const FLEXCAN_CTRL1_PSEG1_SHIFT: u32 = 19;
+pub const FLEXCAN_CTRL1_PSEG2_SHIFT: u32 = 16;
+pub const FLEXCAN_CTRL1_CLKSRC: u32 = 1 << 13;  // Clock from the peripheral clock (CAN_PE_CLK)
+pub const FLEXCAN_CTRL1_PROPSEG_SHIFT: u32 = 0;
+
+// FlexCAN message buffer CS word
+pub const FLEXCAN_CS_CODE_SHIFT: u32 = 24;
+pub const FLEXCAN_CS_CODE_MASK: u32 = 0xF;
+pub const FLEXCAN_CS_SRR: u32 = 1 << 22;        // Substitute remote request (extended frames)
+pub const FLEXCAN_CS_IDE: u32 = 1 << 21;        // Extended ID
+pub const FLEXCAN_CS_DLC_SHIFT: u32 = 16;
+pub const FLEXCAN_CS_DLC_MASK: u32 = 0xF;
+pub const FLEXCAN_ID_STD_SHIFT: u32 = 18;       // Standard IDs sit in bits 28-18
+
+// FlexCAN message buffer codes
+pub const FLEXCAN_RX_EMPTY: u32 = 0x4;          // Armed for reception
+pub const FLEXCAN_RX_FULL: u32 = 0x2;           // Holds a received frame
+pub const FLEXCAN_RX_OVERRUN: u32 = 0x6;        // Holds a frame; an earlier one was lost
+pub const FLEXCAN_TX_INACTIVE: u32 = 0x8;       // Idle
+pub const FLEXCAN_TX_DATA: u32 = 0xC;           // Transmit once
+
+// SIUL2 register offsets
+pub const SIUL2_MSCR0: usize = 0x240;       // Multiplexed Signal Config Register 0
+pub const SIUL2_GPDO0: usize = 0x1300;      // GPIO Pad Data Out (one byte per pad)
+pub const SIUL2_GPDI0: usize = 0x1500;      // GPIO Pad Data In (one byte per pad)
+pub const SIUL2_NUM_PADS: u32 = 102;        // Pads served by SIUL2_0
+
+// SIUL2 MSCR bit definitions
+pub const MSCR_OBE: u32 = 1 << 21;          // Output Buffer Enable
+pub const MSCR_IBE: u32 = 1 << 19;          // Input Buffer Enable
+pub const MSCR_SSS_MASK: u32 = 0x7;         // Source Signal Select (0 = GPIO)
+
 // Memory-mapped timer constants
-pub const S32G_STM0_BASE: usize = 0x40054000;  // System Timer Module 0
 pub const S32G_STM_CR: usize = 0x00;      // Control Register offset
 pub const S32G_STM_CNT: usize = 0x04;     // Count Register offset
-pub const S32G_STM_CMP0: usize = 0x10;    // Compare Register 0 offset
+pub const S32G_STM_CCR0: usize = 0x10;    // Channel 0 Control Register offset
+pub const S32G_STM_CIR0: usize = 0x14;    // Channel 0 Interrupt Register offset
+pub const S32G_STM_CMP0: usize = 0x18;    // Channel 0 Compare Register offset
+pub const S32G_STM_CHANNEL_STRIDE: usize = 0x10;  // Distance between channel register blocks
+pub const S32G_STM_NUM_CHANNELS: u32 = 4;
 
-// Clock configuration
-pub const S32G_CLOCK_FREQ: u64 = 80_000_000;  // 80 MHz system clock (approximate)
+// STM register bit definitions
+pub const STM_CCR_CEN: u32 = 1 << 0;      // Channel Enable
+pub const STM_CIR_CIF: u32 = 1 << 0;      // Channel Interrupt Flag (write 1 to clear)
+
+// STM0 interrupt (SPI 24)
+pub const S32G_STM0_IRQ: u32 = 56;
+
+// LinFLEX0 interrupt (SPI 82)
+pub const S32G_LINFLEX0_IRQ: u32 = 114;
+
+// LinFLEX1 interrupt (SPI 83)
+pub const S32G_LINFLEX1_IRQ: u32 = 115;
+
+// FlexCAN0 message buffer 0-7 interrupt (SPI 39)
+pub const S32G_FLEXCAN0_MB_IRQ: u32 = 71;
 
 pub mod timer {
     use core::sync::atomic::{AtomicU64, Ordering};
@@ -65,15 +244,49 @@ pub mod timer {
     pub fn init() {
         unsafe {
             // Access STM0 registers
-            let stm_base = S32G_STM0_BASE as *mut u32;
+            let stm_base = BOARD.stm_base as *mut u32;
             
             // Configure STM0 with a 1ms tick rate
             // Enable timer, set to free-running mode
             write_volatile(stm_base.add(S32G_STM_CR / 4), 0x1);
             
-            // Set initial compare value
-            write_volatile(stm_base.add(S32G_STM_CMP0 / 4), S32G_CLOCK_FREQ as u32 / 1000);
+            // Set initial compare value and enable the channel's interrupt
+            let now = read_volatile(stm_base.add(S32G_STM_CNT / 4));
+            write_volatile(stm_base.add(S32G_STM_CMP0 / 4), now.wrapping_add(tick_period()));
+            write_volatile(stm_base.add(S32G_STM_CCR0 / 4), STM_CCR_CEN);
+        }
+    }
+
+    // STM counts per 1ms tick
+    fn tick_period() -> u32 {
+        (clocks::stm_clock_hz() / 1000) as u32
+    }
+
+    // Service the channel 0 tick interrupt
+    // Acknowledges it and schedules the next compare one period after this one
+    // (so late handling does not drift the tick). If the handler ran more than
+    // a period late, the missed ticks are counted too rather than waiting for
+    // the counter to wrap. Returns the number of ticks that elapsed.
+    pub fn handle_tick() -> u32 {
+        clear_channel_interrupt(0);
+
+        let period = tick_period();
+        let mut ticks = 0;
+        unsafe {
+            let cmp0 = (BOARD.stm_base + S32G_STM_CMP0) as *mut u32;
+            let mut next = read_volatile(cmp0);
+            loop {
+                next = next.wrapping_add(period);
+                ticks += 1;
+                // Stop once the next compare is still in the future
+                if (next.wrapping_sub(get_raw_counter()) as i32) > 0 {
+                    break;
+                }
+            }
+            write_volatile(cmp0, next);
         }
+        SYSTEM_TICKS.fetch_add(ticks as u64, Ordering::Relaxed);
+        ticks
     }
 
     // Read the system timer counter
@@ -91,19 +304,56 @@ pub mod timer {
         SYSTEM_TICKS.fetch_add(1, Ordering::Relaxed);
     }
 
+    // Check if a channel's compare interrupt flag is set
+    pub fn channel_interrupt_pending(ch: u32) -> bool {
+        if ch >= S32G_STM_NUM_CHANNELS {
+            return false;
+        }
+        unsafe {
+            let cir = BOARD.stm_base + S32G_STM_CIR0 + ch as usize * S32G_STM_CHANNEL_STRIDE;
+            read_volatile(cir as *const u32) & STM_CIR_CIF != 0
+        }
+    }
+
+    // Clear a channel's compare interrupt flag
+    // Must be called from the STM interrupt handler, otherwise the flag stays
+    // asserted and the interrupt re-fires as soon as it is EOI'd
+    pub fn clear_channel_interrupt(ch: u32) {
+        if ch >= S32G_STM_NUM_CHANNELS {
+            return;
+        }
+        unsafe {
+            let cir = BOARD.stm_base + S32G_STM_CIR0 + ch as usize * S32G_STM_CHANNEL_STRIDE;
+            write_volatile(cir as *mut u32, STM_CIR_CIF);
+        }
+    }
+
     // Read raw STM counter value
     pub fn get_raw_counter() -> u32 {
         unsafe {
-            let stm_base = S32G_STM0_BASE as *const u32;
+            let stm_base = BOARD.stm_base as *const u32;
             read_volatile(stm_base.add(S32G_STM_CNT / 4))
         }
     }
 
+    // Nanoseconds since boot from the 64-bit ARM generic counter
+    // Unlike the 32-bit STM counter this never wraps in practice
+    pub fn now_ns() -> u64 {
+        crate::arch::generic_timer::now_ns()
+    }
+
     // Delay for a specified number of microseconds
+    #[cfg(not(feature = "stm-delay"))]
+    pub fn delay_us(us: u32) {
+        crate::arch::generic_timer::delay_us(us as u64);
+    }
+
+    // Delay for a specified number of microseconds, on the STM counter
+    // Waits must stay below one STM wrap (~53 s at 80 MHz)
+    #[cfg(feature = "stm-delay")]
     pub fn delay_us(us: u32) {
-        // More accurate delay based on STM counter
         let start = get_raw_counter();
-        let ticks_to_wait = (S32G_CLOCK_FREQ as u32 / 1_000_000) * us;
+        let ticks_to_wait = (clocks::stm_clock_hz() as u32 / 1_000_000) * us;
         
         while get_raw_counter().wrapping_sub(start) < ticks_to_wait {
             asm::nop();
@@ -114,17 +364,14 @@ pub mod timer {
     pub fn delay_ms(ms: u32) {
         for _ in 0..ms {
             delay_us(1000);
+            crate::maybe_yield!(1);
         }
     }
 }
 
 // Initialize S32G3 peripheral clocks and basic hardware
+// Called first in the EarlyConsole phase, before the UART is touched
 pub fn init() {
-    // Initialize system timer
-    init_vectors();
-    gic::init();
-    uart::init();
-    enable_interrupts();
     // In a full implementation, would initialize other S32G3-specific
     // hardware like clocks, GPIOs, etc.
 }
\ No newline at end of file
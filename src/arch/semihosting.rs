@@ -0,0 +1,95 @@
+// ARM semihosting
+// Console I/O and exit through a debugger or QEMU (-semihosting), for runs
+// where the LinFLEX is not wired up. Each call is an `hlt #0xf000` trapped
+// by the host; without one attached it raises an undefined instruction
+// exception, so only select this console when a host is known to be present.
+
+use core::arch::asm;
+use crate::drivers::uart::SerialOps;
+
+// Semihosting operation numbers
+const SYS_WRITEC: u64 = 0x03;
+const SYS_WRITE0: u64 = 0x04;
+const SYS_READC: u64 = 0x07;
+const SYS_EXIT: u64 = 0x18;
+
+// SYS_EXIT reason for a normal application exit
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+
+// Issue a semihosting call; `param` is the operation's argument in x1
+unsafe fn call(op: u64, param: u64) -> u64 {
+    let mut ret = op;
+    asm!(
+        "hlt #0xf000",
+        inout("x0") ret,
+        in("x1") param,
+        options(nostack)
+    );
+    ret
+}
+
+// Write one character to the host console
+pub fn writec(c: u8) {
+    unsafe {
+        call(SYS_WRITEC, &c as *const u8 as u64);
+    }
+}
+
+// Write a string to the host console
+// Sent in chunks through SYS_WRITE0, which needs a NUL-terminated buffer
+pub fn write0(s: &str) {
+    let mut buf = [0u8; 64];
+    for chunk in s.as_bytes().chunks(buf.len() - 1) {
+        buf[..chunk.len()].copy_from_slice(chunk);
+        buf[chunk.len()] = 0;
+        unsafe {
+            call(SYS_WRITE0, buf.as_ptr() as u64);
+        }
+    }
+}
+
+// Read one character from the host console, waiting for it
+pub fn readc() -> u8 {
+    unsafe { call(SYS_READC, 0) as u8 }
+}
+
+// End the session with an exit code
+// QEMU exits with `code`; a debugger reports the stop
+pub fn exit(code: u32) -> ! {
+    let block: [u64; 2] = [ADP_STOPPED_APPLICATION_EXIT, code as u64];
+    unsafe {
+        call(SYS_EXIT, block.as_ptr() as u64);
+    }
+    // The host should not resume us; park the core if it does
+    loop {
+        super::aarch64::wfe();
+    }
+}
+
+// The semihosting console as a console device
+pub struct Semihosting;
+
+impl SerialOps for Semihosting {
+    fn putc(&self, c: u8) {
+        if c == b'\n' {
+            writec(b'\r');
+        }
+        writec(c);
+    }
+
+    fn flush(&self) {
+        // The host completes each write before returning
+    }
+
+    fn have_rx_data(&self) -> bool {
+        // Semihosting has no non-blocking poll
+        false
+    }
+
+    fn getchar(&self) -> u8 {
+        readc()
+    }
+}
+
+// Select with uart::set_console(&SEMIHOSTING)
+pub static SEMIHOSTING: Semihosting = Semihosting;
@@ -0,0 +1,187 @@
+// Secondary core bring-up
+// The boot core gives each secondary its stack from link.ld, publishes its entry point in the
+// spin table and asks the secure monitor to power it on (PSCI CPU_ON). Cores
+// that firmware already released are parked in _start and are woken by the
+// spin table's sev instead. Either way the secondary runs secondary_main,
+// marks itself online and jumps to the published entry point.
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicU64, Ordering};
+use super::board::{BOARD, MAX_CORES};
+use super::psci::{self, PowerState, PsciError};
+use super::{aarch64, exceptions, generic_timer, gic, mem, mmu, percpu, spin_table, CORE_STATES};
+
+// Stack handed to each secondary core; link.ld reserves MAX_CORES of these
+const SECONDARY_STACK_SIZE: usize = 16 * 1024;
+
+// Polls of CORE_STATES before giving up on a core (~1 ms per 1_000_000 cycles)
+const CPU_ON_TIMEOUT_POLLS: u32 = 1000;
+const CPU_ON_POLL_CYCLES: u64 = 100_000;
+
+// Entry point for a released secondary: gets the context value in x0
+pub type SecondaryEntry = extern "C" fn(u64) -> !;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SmpError {
+    InvalidCore,        // Core position out of range or the boot core
+    NotOnline,          // Core has not completed bring-up
+    AlreadyOnline,      // Core already completed bring-up
+    NoStack,            // link.ld reserved no stack for the core
+    Psci(PsciError),    // Secure monitor refused CPU_ON
+    Timeout,            // Core never reported online
+}
+
+// Top of each secondary's stack, read by the parked loop in _start
+// Zero until the boot core releases that core.
+#[no_mangle]
+static SECONDARY_STACK_TOP: [AtomicU64; MAX_CORES] = [const { AtomicU64::new(0) }; MAX_CORES];
+
+// Secondary entry from PSCI CPU_ON or the parked loop in _start
+// x0 holds the stack top; MMU and caches are still off.
+global_asm!(
+    ".section .text",
+    ".global secondary_entry",
+    "secondary_entry:",
+    "   msr daifset, #0xf",
+    "   mov sp, x0",
+    "   bl secondary_main",
+    "1: wfe",
+    "   b 1b",
+);
+
+extern "C" {
+    fn secondary_entry();
+}
+
+// MPIDR affinity value for a linear core position
+fn mpidr_of(core: usize) -> u64 {
+    let cluster = core / BOARD.cores_per_cluster;
+    let cpu = core % BOARD.cores_per_cluster;
+    ((cluster as u64) << 8) | cpu as u64
+}
+
+// Clean a value to the point of coherency for a core running with caches off
+fn clean_to_poc<T>(value: &T) {
+    unsafe {
+        asm!(
+            "dc cvac, {x}",
+            "dsb sy",
+            x = in(reg) value as *const T as usize,
+            options(nostack)
+        );
+    }
+}
+
+// Stack top for a secondary: its slot in the linker's secondary stack region
+fn secondary_stack(core: usize) -> Result<u64, SmpError> {
+    let region = mem::secondary_stack_region();
+    let top = region.start + (core + 1) * SECONDARY_STACK_SIZE;
+    if top > region.end {
+        return Err(SmpError::NoStack);
+    }
+
+    SECONDARY_STACK_TOP[core].store(top as u64, Ordering::Release);
+    Ok(top as u64)
+}
+
+// Bring a secondary core online and run `entry(context)` on it
+// Returns once the core has reported itself online.
+pub fn cpu_on(core: usize, entry: SecondaryEntry, context: u64) -> Result<(), SmpError> {
+    if core >= BOARD.num_cores || core == percpu::this_cpu().core_pos {
+        return Err(SmpError::InvalidCore);
+    }
+    if CORE_STATES.is_online(core) {
+        return Err(SmpError::AlreadyOnline);
+    }
+
+    let stack_top = secondary_stack(core)?;
+    clean_to_poc(&SECONDARY_STACK_TOP[core]);
+
+    if !spin_table::set_boot_params(core, entry as *const () as u64, context) {
+        return Err(SmpError::InvalidCore);
+    }
+
+    match psci::cpu_on(mpidr_of(core), secondary_entry as *const () as u64, stack_top) {
+        // Already on means firmware released it into _start, where the
+        // spin table's sev wakes it
+        Ok(()) | Err(PsciError::AlreadyOn) => {}
+        Err(err) => return Err(SmpError::Psci(err)),
+    }
+
+    for _ in 0..CPU_ON_TIMEOUT_POLLS {
+        if CORE_STATES.is_online(core) {
+            return Ok(());
+        }
+        aarch64::delay_cycles(CPU_ON_POLL_CYCLES);
+    }
+    Err(SmpError::Timeout)
+}
+
+// Bring every other core online running `entry(context)`
+// Returns the number of cores that came up; cores that fail are left off.
+pub fn start_secondaries(entry: SecondaryEntry, context: u64) -> usize {
+    let boot = percpu::this_cpu().core_pos;
+    (0..BOARD.num_cores)
+        .filter(|&core| core != boot && cpu_on(core, entry, context).is_ok())
+        .count()
+}
+
+// Suspend the calling secondary core
+// Standby returns once the core is woken by an interrupt. Powerdown discards
+// the core's context: on wakeup it comes back through secondary_entry on a
+// fresh stack and runs `resume(context)`. Returns only if the core did not
+// power down.
+pub fn cpu_suspend(state: PowerState, resume: SecondaryEntry, context: u64) -> Result<(), SmpError> {
+    let core = percpu::this_cpu().core_pos;
+    if !CORE_STATES.is_online(core) {
+        return Err(SmpError::NotOnline);
+    }
+
+    if state == PowerState::Standby {
+        return psci::cpu_suspend(state, 0, 0).map_err(SmpError::Psci);
+    }
+
+    // Only secondaries have a stack to restart on
+    let stack_top = SECONDARY_STACK_TOP[core].load(Ordering::Acquire);
+    if stack_top == 0 {
+        return Err(SmpError::InvalidCore);
+    }
+    if !spin_table::set_boot_params(core, resume as *const () as u64, context) {
+        return Err(SmpError::InvalidCore);
+    }
+
+    CORE_STATES.mark_offline(core);
+    let result = psci::cpu_suspend(state, secondary_entry as *const () as u64, stack_top);
+
+    // Still here: the monitor declined or a wakeup was pending, so the core
+    // kept its context
+    spin_table::take_boot_params(core);
+    CORE_STATES.mark_online(core);
+    result.map_err(SmpError::Psci)
+}
+
+// Rust side of secondary_entry
+// Brings up the core-local state, reports online, then waits for its entry.
+#[no_mangle]
+extern "C" fn secondary_main() -> ! {
+    percpu::init();
+    aarch64::enable_fpu();
+    exceptions::init_vectors();
+    
+    // The boot core already set up the distributor; this wakes our
+    // redistributor and CPU interface
+    gic::init();
+    mmu::init_secondary();
+    generic_timer::enable_event_stream();
+
+    let core = percpu::this_cpu().core_pos;
+    CORE_STATES.mark_online(core);
+
+    loop {
+        if let Some((entry, context)) = spin_table::take_boot_params(core) {
+            let entry: SecondaryEntry = unsafe { core::mem::transmute(entry as usize) };
+            entry(context);
+        }
+        aarch64::wfe();
+    }
+}
@@ -0,0 +1,80 @@
+// Secondary-core spin table
+// The boot core publishes an entry point and context value per core; a parked
+// secondary consumes (and clears) its slot when it wakes from wfe.
+// Each slot occupies its own cache line and is cleaned/invalidated to the
+// point of coherency, so cores running with caches off still see the update.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+use crate::arch::board::MAX_CORES;
+
+// Boot parameters for a single core
+#[repr(C, align(64))]
+struct BootParams {
+    entry: AtomicU64,       // 0 when no entry point is pending
+    context: AtomicU64,
+}
+
+impl BootParams {
+    const fn new() -> Self {
+        BootParams {
+            entry: AtomicU64::new(0),
+            context: AtomicU64::new(0),
+        }
+    }
+}
+
+static CORE_BOOT_PARAMS: [BootParams; MAX_CORES] = [const { BootParams::new() }; MAX_CORES];
+
+// Clean and invalidate the cache line holding a slot
+fn clean_invalidate(params: &BootParams) {
+    unsafe {
+        asm!(
+            "dc civac, {x}",
+            "dsb sy",
+            x = in(reg) params as *const BootParams as usize,
+            options(nostack)
+        );
+    }
+}
+
+// Publish the entry point and context for a secondary core and wake it
+// Returns false if the core position is out of range or the entry is null
+pub fn set_boot_params(core: usize, entry: u64, context: u64) -> bool {
+    let params = match CORE_BOOT_PARAMS.get(core) {
+        Some(params) => params,
+        None => return false,
+    };
+
+    if entry == 0 {
+        return false;
+    }
+
+    // Context first, so the entry's release store publishes both
+    params.context.store(context, Ordering::Relaxed);
+    params.entry.store(entry, Ordering::Release);
+    clean_invalidate(params);
+
+    // Wake any core parked in wfe
+    unsafe {
+        asm!("sev", options(nostack));
+    }
+
+    true
+}
+
+// Consume and clear this core's boot parameters
+// Returns (entry, context) if an entry point was published
+pub fn take_boot_params(core: usize) -> Option<(u64, u64)> {
+    let params = CORE_BOOT_PARAMS.get(core)?;
+
+    clean_invalidate(params);
+    let entry = params.entry.swap(0, Ordering::Acquire);
+    if entry == 0 {
+        return None;
+    }
+    let context = params.context.load(Ordering::Relaxed);
+    clean_invalidate(params);
+
+    Some((entry, context))
+}
@@ -0,0 +1,123 @@
+// Spinlocks for state shared between cores
+// Critical sections only mask interrupts on the calling core; state another
+// core can touch at the same time also needs one of these. Waiters sleep in
+// wfe and the unlock sends an event, so a contended lock does not keep
+// hammering the interconnect.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+use super::aarch64;
+
+// Ticket lock without data or interrupt masking
+// Cores get the lock in the order they asked for it. The caller is
+// responsible for masking interrupts if an ISR on the same core may take it.
+pub struct RawSpinLock {
+    next_ticket: AtomicU32,
+    now_serving: AtomicU32,
+}
+
+impl RawSpinLock {
+    pub const fn new() -> Self {
+        RawSpinLock {
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+        }
+    }
+
+    pub fn lock(&self) {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            // A sev between the load and the wfe leaves the event register
+            // set, so the wfe returns at once rather than missing the unlock
+            aarch64::wfe();
+        }
+    }
+
+    pub fn try_lock(&self) -> bool {
+        let serving = self.now_serving.load(Ordering::Relaxed);
+        self.next_ticket
+            .compare_exchange(serving, serving.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    // Must only be called by the holder
+    pub fn unlock(&self) {
+        self.now_serving.fetch_add(1, Ordering::Release);
+        aarch64::dsb();
+        aarch64::sev();
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.next_ticket.load(Ordering::Relaxed) != self.now_serving.load(Ordering::Relaxed)
+    }
+}
+
+// Spinlock protecting a value
+// IRQs are masked on this core while the lock is held, so an ISR can never
+// spin on a lock its own core holds.
+pub struct SpinLock<T> {
+    raw: RawSpinLock,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(data: T) -> Self {
+        SpinLock {
+            raw: RawSpinLock::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    // Mask IRQs and spin until the lock is ours
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let daif = aarch64::save_and_disable_irqs();
+        self.raw.lock();
+        SpinLockGuard { lock: self, daif }
+    }
+
+    // Take the lock only if it is free
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        let daif = aarch64::save_and_disable_irqs();
+        if self.raw.try_lock() {
+            Some(SpinLockGuard { lock: self, daif })
+        } else {
+            aarch64::restore_irqs(daif);
+            None
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.raw.is_locked()
+    }
+}
+
+// Access to the value of a held SpinLock; unlocks and restores IRQs on drop
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+    daif: u64,              // IRQ mask from before lock()
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.raw.unlock();
+        aarch64::restore_irqs(self.daif);
+    }
+}
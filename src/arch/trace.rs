@@ -0,0 +1,167 @@
+// In-memory trace log
+// trace! formats into a fixed-size record in a lock-free ring instead of the
+// UART, so it never blocks and is safe in interrupt handlers and the
+// scheduler. When the ring is full the oldest records are overwritten;
+// dump() later drains what is left to the console and reports how many were
+// lost.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+use super::{generic_timer, percpu};
+
+// Records in the ring; a power of two so the index wraps cleanly
+const RING_SIZE: usize = 256;
+
+// Bytes of formatted message kept per record; longer messages are cut
+pub const MESSAGE_LEN: usize = 48;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
+pub enum TraceLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+// One trace entry as read back by drain
+#[derive(Copy, Clone)]
+pub struct TraceRecord {
+    pub timestamp: u64,         // Generic timer count
+    pub core: u8,
+    pub level: TraceLevel,
+    len: u8,
+    message: [u8; MESSAGE_LEN],
+}
+
+impl TraceRecord {
+    const fn empty() -> Self {
+        TraceRecord {
+            timestamp: 0,
+            core: 0,
+            level: TraceLevel::Debug,
+            len: 0,
+            message: [0; MESSAGE_LEN],
+        }
+    }
+
+    // Message text, up to the last whole character if it was cut
+    pub fn message(&self) -> &str {
+        let bytes = &self.message[..self.len as usize];
+        match core::str::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(err) => unsafe { core::str::from_utf8_unchecked(&bytes[..err.valid_up_to()]) },
+        }
+    }
+}
+
+// Ring slot guarded by a sequence number, as in a seqlock
+// `seq` is index + 1 of the record it holds, or 0 while a writer fills it.
+struct Slot {
+    seq: AtomicU64,
+    record: UnsafeCell<TraceRecord>,
+}
+
+unsafe impl Sync for Slot {}
+
+static RING: [Slot; RING_SIZE] = [const {
+    Slot { seq: AtomicU64::new(0), record: UnsafeCell::new(TraceRecord::empty()) }
+}; RING_SIZE];
+
+// Next index to write, and the next index drain will read
+static HEAD: AtomicU64 = AtomicU64::new(0);
+static TAIL: AtomicU64 = AtomicU64::new(0);
+
+// Formats into a record's message, dropping what does not fit
+struct MessageWriter<'a> {
+    record: &'a mut TraceRecord,
+}
+
+impl fmt::Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = self.record.len as usize;
+        let count = s.len().min(MESSAGE_LEN - len);
+        self.record.message[len..len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.record.len = (len + count) as u8;
+        Ok(())
+    }
+}
+
+// Append a record; used by the trace! macros
+// Never blocks: a writer claims its slot with one atomic add.
+pub fn record(level: TraceLevel, args: fmt::Arguments) {
+    let mut entry = TraceRecord::empty();
+    entry.timestamp = generic_timer::count();
+    entry.core = percpu::this_cpu().core_pos as u8;
+    entry.level = level;
+    let _ = fmt::write(&mut MessageWriter { record: &mut entry }, args);
+
+    let index = HEAD.fetch_add(1, Ordering::Relaxed);
+    let slot = &RING[index as usize % RING_SIZE];
+    slot.seq.store(0, Ordering::Relaxed);
+    fence(Ordering::Release);
+    unsafe { core::ptr::write_volatile(slot.record.get(), entry) };
+    slot.seq.store(index + 1, Ordering::Release);
+}
+
+// Hand every record written since the last drain to `sink`, oldest first
+// Returns the number of records that were overwritten before they could be
+// read. Only one core may drain at a time.
+pub fn drain(mut sink: impl FnMut(&TraceRecord)) -> u64 {
+    let head = HEAD.load(Ordering::Acquire);
+    let mut tail = TAIL.load(Ordering::Relaxed);
+    let mut lost = 0;
+
+    // Anything more than a ring behind has been overwritten
+    if head - tail > RING_SIZE as u64 {
+        lost += head - tail - RING_SIZE as u64;
+        tail = head - RING_SIZE as u64;
+    }
+
+    while tail < head {
+        let slot = &RING[tail as usize % RING_SIZE];
+        let seq = slot.seq.load(Ordering::Acquire);
+        if seq == 0 || seq < tail + 1 {
+            // Claimed but not yet written (the slot may still hold the
+            // record from a ring ago); leave it for the next drain
+            break;
+        }
+        let entry = unsafe { core::ptr::read_volatile(slot.record.get()) };
+        fence(Ordering::Acquire);
+        if seq == tail + 1 && slot.seq.load(Ordering::Relaxed) == seq {
+            sink(&entry);
+        } else {
+            // A writer lapped the reader while we copied
+            lost += 1;
+        }
+        tail += 1;
+    }
+
+    TAIL.store(tail, Ordering::Relaxed);
+    lost
+}
+
+// Print and remove all pending records
+pub fn dump() {
+    let lost = drain(|entry| {
+        let us = generic_timer::ticks_to_ns(entry.timestamp) / 1000;
+        crate::println!("[{:>6}.{:06}] cpu{} {:?}: {}",
+                        us / 1_000_000, us % 1_000_000, entry.core, entry.level, entry.message());
+    });
+    if lost > 0 {
+        crate::println!("trace: {} records lost", lost);
+    }
+}
+
+// Record a trace message at Info level without blocking
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => ($crate::arch::trace::record($crate::arch::trace::TraceLevel::Info, format_args!($($arg)*)));
+}
+
+// Record a trace message at the given TraceLevel without blocking
+#[macro_export]
+macro_rules! trace_at {
+    ($level:expr, $($arg:tt)*) => ($crate::arch::trace::record($level, format_args!($($arg)*)));
+}
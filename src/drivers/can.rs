@@ -0,0 +1,340 @@
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Once;
+use crate::arch::{self, clocks, gic};
+use crate::arch::board::BOARD;
+use crate::arch::gic::GicError;
+use crate::arch::s32g3::{
+    S32G_FLEXCAN0_MB_IRQ, FLEXCAN_MCR, FLEXCAN_CTRL1, FLEXCAN_TIMER, FLEXCAN_RXMGMASK,
+    FLEXCAN_IMASK1, FLEXCAN_IFLAG1, FLEXCAN_MB0, FLEXCAN_MB_SIZE,
+    FLEXCAN_MCR_MDIS, FLEXCAN_MCR_FRZ, FLEXCAN_MCR_HALT, FLEXCAN_MCR_NOTRDY, FLEXCAN_MCR_SOFTRST,
+    FLEXCAN_MCR_FRZACK, FLEXCAN_MCR_SUPV, FLEXCAN_MCR_LPMACK, FLEXCAN_MCR_SRXDIS, FLEXCAN_MCR_MAXMB_MASK,
+    FLEXCAN_CTRL1_PRESDIV_SHIFT, FLEXCAN_CTRL1_RJW_SHIFT, FLEXCAN_CTRL1_PSEG1_SHIFT,
+    FLEXCAN_CTRL1_PSEG2_SHIFT, FLEXCAN_CTRL1_CLKSRC, FLEXCAN_CTRL1_PROPSEG_SHIFT,
+    FLEXCAN_CS_CODE_SHIFT, FLEXCAN_CS_CODE_MASK, FLEXCAN_CS_SRR, FLEXCAN_CS_IDE,
+    FLEXCAN_CS_DLC_SHIFT, FLEXCAN_CS_DLC_MASK, FLEXCAN_ID_STD_SHIFT,
+    FLEXCAN_RX_EMPTY, FLEXCAN_RX_FULL, FLEXCAN_RX_OVERRUN, FLEXCAN_TX_INACTIVE, FLEXCAN_TX_DATA
+};
+use crate::freertos::port;
+use crate::freertos::queue::Queue;
+
+/**
+ * Set in an ID to mark it as a 29-bit extended ID (as in Linux SocketCAN)
+ * IDs without it are 11-bit standard IDs.
+ */
+pub const CAN_EFF_FLAG: u32 = 1 << 31;
+pub const CAN_SFF_MASK: u32 = 0x7FF;
+pub const CAN_EFF_MASK: u32 = 0x1FFF_FFFF;
+
+// Message buffer roles: a receive MB per ID type (the IDE bit always has to
+// match) and one transmit MB
+const RX_STD_MB: usize = 0;
+const RX_EXT_MB: usize = 1;
+const TX_MB: usize = 8;
+const LAST_MB: usize = TX_MB;
+
+// Frames buffered between the RX interrupt and the consuming tasks
+const RX_QUEUE_LEN: usize = 32;
+
+// Status polls before a mode change or transmit wait is abandoned
+const CAN_POLL_LIMIT: u32 = 1_000_000;
+
+// Bit time in time quanta: 1 sync + PROPSEG + PSEG1 + PSEG2, each segment
+// 1-8 quanta (PSEG2 at least 2). Longer bits than 20 quanta would push the
+// sample point early, since PROPSEG + PSEG1 top out at 16.
+const TQ_MIN: u32 = 8;
+const TQ_MAX: u32 = 20;
+const PRESDIV_MAX: u32 = 256;
+
+// Classic CAN tops out at 1 Mbit/s
+const CAN_MAX_BITRATE: u32 = 1_000_000;
+
+/**
+ * Errors reported by the CAN driver
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CanError {
+    InvalidBitrate,     // No prescaler/quanta split gives the bitrate exactly
+    InvalidId,          // ID does not fit in 11 (or with CAN_EFF_FLAG, 29) bits
+    InvalidLength,      // More than 8 data bytes
+    NotInitialized,     // init() has not run
+    Busy,               // Previous frame still waiting for the bus
+    Timeout,            // Controller did not enter or leave freeze mode
+    Gic(GicError),      // RX interrupt could not be hooked up
+}
+
+/**
+ * A received classic CAN frame
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanFrame {
+    pub id: u32,            // With CAN_EFF_FLAG set for extended IDs
+    len: u8,
+    bytes: [u8; 8],
+}
+
+impl CanFrame {
+    /**
+     * The frame's data bytes (0 to 8)
+     */
+    pub fn data(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+// Frames received by can_isr, created by init()
+static RX_QUEUE: Once<Queue<CanFrame>> = Once::new();
+
+// Frames dropped because RX_QUEUE was full or an MB was overwritten
+static RX_DROPPED: AtomicU32 = AtomicU32::new(0);
+
+fn reg(offset: usize) -> *mut u32 {
+    (BOARD.can0_base + offset) as *mut u32
+}
+
+fn mb_reg(mb: usize, word: usize) -> *mut u32 {
+    reg(FLEXCAN_MB0 + mb * FLEXCAN_MB_SIZE + word * 4)
+}
+
+/**
+ * Wait for the MCR bits in `mask` to read as `set`
+ */
+fn wait_mcr(mask: u32, set: bool) -> Result<(), CanError> {
+    for _ in 0..CAN_POLL_LIMIT {
+        let mcr = unsafe { read_volatile(reg(FLEXCAN_MCR)) };
+        if (mcr & mask != 0) == set {
+            return Ok(());
+        }
+    }
+    Err(CanError::Timeout)
+}
+
+/**
+ * CTRL1 timing fields for `bitrate` on a `clock` protocol engine clock
+ * Prefers the most quanta per bit, with the sample point near 87.5%.
+ */
+fn bit_timing(clock: u32, bitrate: u32) -> Result<u32, CanError> {
+    if bitrate == 0 || bitrate > CAN_MAX_BITRATE {
+        return Err(CanError::InvalidBitrate);
+    }
+    
+    let tq = (TQ_MIN..=TQ_MAX).rev()
+        .find(|tq| {
+            let quanta_rate = bitrate * tq;
+            clock % quanta_rate == 0 && clock / quanta_rate <= PRESDIV_MAX
+        })
+        .ok_or(CanError::InvalidBitrate)?;
+    let presdiv = clock / (bitrate * tq);
+    
+    // Phase segment 2 covers the time after the sample point
+    let pseg2 = ((tq + 4) / 8).max(2);
+    let tseg1 = tq - 1 - pseg2;
+    let propseg = (tseg1 / 2).min(8);
+    let pseg1 = tseg1 - propseg;
+    let rjw = pseg2.min(4);
+    
+    Ok(((presdiv - 1) << FLEXCAN_CTRL1_PRESDIV_SHIFT)
+        | ((rjw - 1) << FLEXCAN_CTRL1_RJW_SHIFT)
+        | ((pseg1 - 1) << FLEXCAN_CTRL1_PSEG1_SHIFT)
+        | ((pseg2 - 1) << FLEXCAN_CTRL1_PSEG2_SHIFT)
+        | ((propseg - 1) << FLEXCAN_CTRL1_PROPSEG_SHIFT))
+}
+
+fn mb_code(mb: usize) -> u32 {
+    unsafe { (read_volatile(mb_reg(mb, 0)) >> FLEXCAN_CS_CODE_SHIFT) & FLEXCAN_CS_CODE_MASK }
+}
+
+/**
+ * Arm a receive MB for standard or extended frames
+ */
+fn arm_rx(mb: usize, extended: bool) {
+    let ide = if extended { FLEXCAN_CS_IDE } else { 0 };
+    unsafe {
+        write_volatile(mb_reg(mb, 1), 0);
+        write_volatile(mb_reg(mb, 0), (FLEXCAN_RX_EMPTY << FLEXCAN_CS_CODE_SHIFT) | ide);
+    }
+}
+
+/**
+ * Bring FlexCAN0 up at `bitrate` bits/s and start receiving
+ * Bit timing is derived from CAN_PE_CLK as reported by
+ * arch::clocks::can_clock_hz(); the controller runs from that clock
+ * (CTRL1.CLKSRC), so the bootloader must have enabled the CAN_PE mux. Both
+ * standard and extended frames are accepted, without filtering.
+ */
+pub fn init(bitrate: u32) -> Result<(), CanError> {
+    let timing = bit_timing(clocks::can_clock_hz(), bitrate)?;
+    RX_QUEUE.call_once(|| Queue::new(RX_QUEUE_LEN));
+    
+    unsafe {
+        // Enable the module and reset it
+        let mcr = read_volatile(reg(FLEXCAN_MCR));
+        write_volatile(reg(FLEXCAN_MCR), mcr & !FLEXCAN_MCR_MDIS);
+        wait_mcr(FLEXCAN_MCR_LPMACK, false)?;
+        write_volatile(reg(FLEXCAN_MCR), read_volatile(reg(FLEXCAN_MCR)) | FLEXCAN_MCR_SOFTRST);
+        wait_mcr(FLEXCAN_MCR_SOFTRST, false)?;
+        
+        // Configuration is only writable in freeze mode
+        let mcr = read_volatile(reg(FLEXCAN_MCR)) | FLEXCAN_MCR_FRZ | FLEXCAN_MCR_HALT;
+        write_volatile(reg(FLEXCAN_MCR), mcr);
+        wait_mcr(FLEXCAN_MCR_FRZACK, true)?;
+        
+        let mcr = (mcr & !(FLEXCAN_MCR_SUPV | FLEXCAN_MCR_MAXMB_MASK))
+            | FLEXCAN_MCR_SRXDIS | LAST_MB as u32;
+        write_volatile(reg(FLEXCAN_MCR), mcr);
+        write_volatile(reg(FLEXCAN_CTRL1), timing | FLEXCAN_CTRL1_CLKSRC);
+        
+        // Accept every ID into the receive MBs
+        write_volatile(reg(FLEXCAN_RXMGMASK), 0);
+        for mb in 0..=LAST_MB {
+            write_volatile(mb_reg(mb, 0), FLEXCAN_TX_INACTIVE << FLEXCAN_CS_CODE_SHIFT);
+        }
+        arm_rx(RX_STD_MB, false);
+        arm_rx(RX_EXT_MB, true);
+        
+        write_volatile(reg(FLEXCAN_IFLAG1), u32::MAX);
+        write_volatile(reg(FLEXCAN_IMASK1), (1 << RX_STD_MB) | (1 << RX_EXT_MB));
+    }
+    
+    gic::register_handler(S32G_FLEXCAN0_MB_IRQ, can_isr).map_err(CanError::Gic)?;
+    arch::enable_interrupt(S32G_FLEXCAN0_MB_IRQ);
+    
+    // Leave freeze mode and wait until the controller has synchronized
+    unsafe {
+        let mcr = read_volatile(reg(FLEXCAN_MCR));
+        write_volatile(reg(FLEXCAN_MCR), mcr & !FLEXCAN_MCR_HALT);
+    }
+    wait_mcr(FLEXCAN_MCR_FRZACK, false)?;
+    wait_mcr(FLEXCAN_MCR_NOTRDY, false)
+}
+
+/**
+ * Queue a data frame for transmission
+ * Waits (bounded) for the previous frame to leave the transmit MB, and
+ * returns once this one is loaded, not when it is on the bus.
+ */
+pub fn send(id: u32, data: &[u8]) -> Result<(), CanError> {
+    if RX_QUEUE.get().is_none() {
+        return Err(CanError::NotInitialized);
+    }
+    if data.len() > 8 {
+        return Err(CanError::InvalidLength);
+    }
+    
+    let (id_word, flags) = if id & CAN_EFF_FLAG != 0 {
+        let id = id & !CAN_EFF_FLAG;
+        if id > CAN_EFF_MASK {
+            return Err(CanError::InvalidId);
+        }
+        (id, FLEXCAN_CS_IDE | FLEXCAN_CS_SRR)
+    } else {
+        if id > CAN_SFF_MASK {
+            return Err(CanError::InvalidId);
+        }
+        (id << FLEXCAN_ID_STD_SHIFT, 0)
+    };
+    
+    let mut polls = 0;
+    while mb_code(TX_MB) == FLEXCAN_TX_DATA {
+        polls += 1;
+        if polls > CAN_POLL_LIMIT {
+            return Err(CanError::Busy);
+        }
+    }
+    
+    // Data words are big-endian: byte 0 is the top byte of the first word
+    let mut bytes = [0u8; 8];
+    bytes[..data.len()].copy_from_slice(data);
+    unsafe {
+        write_volatile(reg(FLEXCAN_IFLAG1), 1 << TX_MB);
+        write_volatile(mb_reg(TX_MB, 0), FLEXCAN_TX_INACTIVE << FLEXCAN_CS_CODE_SHIFT);
+        write_volatile(mb_reg(TX_MB, 1), id_word);
+        write_volatile(mb_reg(TX_MB, 2), u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+        write_volatile(mb_reg(TX_MB, 3), u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]));
+        write_volatile(mb_reg(TX_MB, 0), (FLEXCAN_TX_DATA << FLEXCAN_CS_CODE_SHIFT)
+            | flags | ((data.len() as u32) << FLEXCAN_CS_DLC_SHIFT));
+    }
+    
+    Ok(())
+}
+
+/**
+ * Take the oldest received frame, if any
+ */
+pub fn recv() -> Option<CanFrame> {
+    recv_timeout(Some(0))
+}
+
+/**
+ * Take the oldest received frame, blocking for at most `max_wait` ticks
+ * (None waits forever)
+ */
+pub fn recv_timeout(max_wait: Option<u64>) -> Option<CanFrame> {
+    RX_QUEUE.get()?.receive(max_wait)
+}
+
+/**
+ * Frames lost since the last call, to an overwritten MB or a full queue
+ */
+pub fn take_rx_dropped() -> u32 {
+    RX_DROPPED.swap(0, Ordering::Relaxed)
+}
+
+/**
+ * Copy a full receive MB into a frame and re-arm it
+ * Reading CS locks the MB against updates; reading TIMER releases it.
+ */
+fn read_rx_mb(mb: usize, extended: bool) -> Option<CanFrame> {
+    let frame = unsafe {
+        let cs = read_volatile(mb_reg(mb, 0));
+        let code = (cs >> FLEXCAN_CS_CODE_SHIFT) & FLEXCAN_CS_CODE_MASK;
+        if code != FLEXCAN_RX_FULL && code != FLEXCAN_RX_OVERRUN {
+            let _ = read_volatile(reg(FLEXCAN_TIMER));
+            return None;
+        }
+        if code == FLEXCAN_RX_OVERRUN {
+            RX_DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+        
+        let id_word = read_volatile(mb_reg(mb, 1));
+        let id = if cs & FLEXCAN_CS_IDE != 0 {
+            (id_word & CAN_EFF_MASK) | CAN_EFF_FLAG
+        } else {
+            (id_word >> FLEXCAN_ID_STD_SHIFT) & CAN_SFF_MASK
+        };
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&read_volatile(mb_reg(mb, 2)).to_be_bytes());
+        bytes[4..].copy_from_slice(&read_volatile(mb_reg(mb, 3)).to_be_bytes());
+        let len = ((cs >> FLEXCAN_CS_DLC_SHIFT) & FLEXCAN_CS_DLC_MASK).min(8) as u8;
+        
+        let _ = read_volatile(reg(FLEXCAN_TIMER));
+        CanFrame { id, len, bytes }
+    };
+    
+    arm_rx(mb, extended);
+    Some(frame)
+}
+
+/**
+ * FlexCAN0 MB 0-7 interrupt handler
+ * Moves received frames into RX_QUEUE and wakes a waiting task.
+ */
+fn can_isr() {
+    let Some(queue) = RX_QUEUE.get() else { return };
+    let flags = unsafe { read_volatile(reg(FLEXCAN_IFLAG1)) };
+    let mut woken = false;
+    
+    for (mb, extended) in [(RX_STD_MB, false), (RX_EXT_MB, true)] {
+        if flags & (1 << mb) == 0 {
+            continue;
+        }
+        if let Some(frame) = read_rx_mb(mb, extended) {
+            if !queue.send_from_isr(frame, &mut woken) {
+                RX_DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        unsafe { write_volatile(reg(FLEXCAN_IFLAG1), 1 << mb) };
+    }
+    
+    port::yield_from_isr(woken);
+}
@@ -0,0 +1,153 @@
+use core::ptr::{read_volatile, write_volatile};
+use crate::arch::board::BOARD;
+use crate::arch::s32g3::timer;
+use crate::arch::s32g3::{
+    SIUL2_MSCR0, SIUL2_GPDO0, SIUL2_GPDI0, SIUL2_NUM_PADS,
+    MSCR_OBE, MSCR_IBE, MSCR_SSS_MASK
+};
+
+/**
+ * Errors reported by the GPIO driver
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpioError {
+    InvalidPin,
+}
+
+/**
+ * Byte offset of a pad in the GPDO/GPDI arrays
+ * The pad data registers are big-endian words, so the byte lanes are swapped
+ */
+fn pad_byte(pin: u32) -> usize {
+    (pin ^ 3) as usize
+}
+
+fn check_pin(pin: u32) -> Result<(), GpioError> {
+    if pin < SIUL2_NUM_PADS {
+        Ok(())
+    } else {
+        Err(GpioError::InvalidPin)
+    }
+}
+
+/**
+ * Set a pad's MSCR to GPIO with the given buffers enabled
+ */
+fn configure(pin: u32, mscr_bits: u32) -> Result<(), GpioError> {
+    check_pin(pin)?;
+    
+    unsafe {
+        let mscr = (BOARD.siul2_base + SIUL2_MSCR0 + pin as usize * 4) as *mut u32;
+        let val = read_volatile(mscr) & !(MSCR_OBE | MSCR_IBE | MSCR_SSS_MASK);
+        write_volatile(mscr, val | mscr_bits);
+    }
+    
+    Ok(())
+}
+
+/**
+ * Configure a pad as a GPIO output
+ * The input buffer stays enabled so the driven level can be read back
+ */
+pub fn configure_output(pin: u32) -> Result<(), GpioError> {
+    configure(pin, MSCR_OBE | MSCR_IBE)
+}
+
+/**
+ * Configure a pad as a GPIO input
+ */
+pub fn configure_input(pin: u32) -> Result<(), GpioError> {
+    configure(pin, MSCR_IBE)
+}
+
+/**
+ * Drive an output pad high (true) or low (false)
+ * Out-of-range pins are ignored; they were rejected at configure time
+ */
+pub fn set(pin: u32, high: bool) {
+    if check_pin(pin).is_err() {
+        return;
+    }
+    
+    unsafe {
+        let gpdo = (BOARD.siul2_base + SIUL2_GPDO0 + pad_byte(pin)) as *mut u8;
+        write_volatile(gpdo, high as u8);
+    }
+}
+
+/**
+ * Read the level of a pad
+ */
+pub fn get(pin: u32) -> bool {
+    if check_pin(pin).is_err() {
+        return false;
+    }
+    
+    unsafe {
+        let gpdi = (BOARD.siul2_base + SIUL2_GPDI0 + pad_byte(pin)) as *const u8;
+        read_volatile(gpdi) & 1 != 0
+    }
+}
+
+/**
+ * Pad direction for set_direction
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/**
+ * Configure a pad as a GPIO input or output
+ */
+pub fn set_direction(pin: u32, direction: Direction) -> Result<(), GpioError> {
+    match direction {
+        Direction::Input => configure_input(pin),
+        Direction::Output => configure_output(pin),
+    }
+}
+
+/**
+ * Drive an output pad high (true) or low (false)
+ */
+pub fn write(pin: u32, high: bool) {
+    set(pin, high);
+}
+
+/**
+ * Read the level of a pad
+ */
+pub fn read(pin: u32) -> bool {
+    get(pin)
+}
+
+/**
+ * Invert the level an output pad is driving
+ * Works from the GPDO latch, so it does not depend on the input buffer
+ */
+pub fn toggle(pin: u32) {
+    if check_pin(pin).is_err() {
+        return;
+    }
+    
+    unsafe {
+        let gpdo = (BOARD.siul2_base + SIUL2_GPDO0 + pad_byte(pin)) as *mut u8;
+        write_volatile(gpdo, (read_volatile(gpdo) & 1) ^ 1);
+    }
+}
+
+/**
+ * Blink a pad `times` times, e.g. an LED during bring-up
+ * Each half period is `half_period_ms` (500 gives a 1 Hz blink)
+ */
+pub fn blink(pin: u32, times: u32, half_period_ms: u32) -> Result<(), GpioError> {
+    set_direction(pin, Direction::Output)?;
+    
+    for _ in 0..times * 2 {
+        toggle(pin);
+        timer::delay_ms(half_period_ms);
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use core::ptr::{read_volatile, write_volatile};
+use crate::arch::board::BOARD;
+use crate::arch::s32g3::{
+    I2C_IBFD, I2C_IBCR, I2C_IBSR, I2C_IBDR,
+    IBCR_MDIS, IBCR_MSSL, IBCR_TXRX, IBCR_NOACK, IBCR_RSTA,
+    IBSR_IBB, IBSR_IBAL, IBSR_IBIF, IBSR_RXAK
+};
+
+// Status polls before a bus or byte wait is abandoned
+const I2C_POLL_LIMIT: u32 = 1_000_000;
+
+// Range of 7-bit addresses scanned by scan(); the rest are reserved
+const I2C_SCAN_FIRST: u8 = 0x08;
+const I2C_SCAN_LAST: u8 = 0x77;
+
+// SCL divider and the IBFD value selecting it, in increasing divider order
+const IBFD_DIVIDERS: [(u16, u8); 60] = [
+    (20, 0x00), (22, 0x01), (24, 0x02), (26, 0x03), (28, 0x04), (30, 0x05),
+    (32, 0x09), (34, 0x06), (36, 0x0A), (40, 0x07), (44, 0x0C), (48, 0x0D),
+    (52, 0x43), (56, 0x0E), (60, 0x45), (64, 0x12), (68, 0x0F), (72, 0x13),
+    (80, 0x14), (88, 0x15), (96, 0x19), (104, 0x16), (112, 0x1A), (128, 0x17),
+    (136, 0x4F), (144, 0x1C), (160, 0x1D), (176, 0x55), (192, 0x1E), (208, 0x56),
+    (224, 0x22), (228, 0x24), (240, 0x1F), (256, 0x23), (288, 0x5C), (320, 0x25),
+    (384, 0x26), (448, 0x2A), (480, 0x27), (512, 0x2B), (576, 0x2C), (640, 0x2D),
+    (768, 0x31), (896, 0x32), (960, 0x2F), (1024, 0x33), (1152, 0x34), (1280, 0x35),
+    (1536, 0x36), (1792, 0x3A), (1920, 0x37), (2048, 0x3B), (2304, 0x3C), (2560, 0x3D),
+    (3072, 0x3E), (3584, 0x7A), (3840, 0x3F), (4096, 0x7B), (5120, 0x7D), (6144, 0x7E),
+];
+
+/**
+ * Errors reported by the I2C driver
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I2cError {
+    InvalidClock,       // Requested SCL rate is below what the dividers can reach
+    InvalidAddress,     // Address does not fit in 7 bits
+    Nack,               // Device did not acknowledge its address or a data byte
+    ArbitrationLost,    // Another master won the bus
+    BusBusy,            // Bus stayed busy, e.g. a device holding SDA low
+    Timeout,            // A byte transfer never completed
+}
+
+/**
+ * One I2C controller in master mode, 7-bit addressing
+ */
+pub struct I2c {
+    base: usize,            // I2C register block
+    clock: u32,             // I2C module clock
+}
+
+impl I2c {
+    /**
+     * Create a driver for the controller at `base`
+     * Nothing is touched until set_clock() is called
+     */
+    pub const fn new(base: usize) -> Self {
+        I2c {
+            base,
+            clock: BOARD.i2c_clock_hz,
+        }
+    }
+    
+    fn read_reg(&self, offset: usize) -> u8 {
+        unsafe { read_volatile((self.base + offset) as *const u8) }
+    }
+    
+    fn write_reg(&self, offset: usize, val: u8) {
+        unsafe { write_volatile((self.base + offset) as *mut u8, val) }
+    }
+    
+    /**
+     * Program the SCL rate and enable the controller
+     * The rate is rounded down to the nearest one the divider table offers.
+     */
+    pub fn set_clock(&mut self, freq_hz: u32) -> Result<(), I2cError> {
+        let ibfd = IBFD_DIVIDERS.iter()
+            .find(|(div, _)| freq_hz != 0 && self.clock / *div as u32 <= freq_hz)
+            .map(|(_, ibfd)| *ibfd)
+            .ok_or(I2cError::InvalidClock)?;
+        
+        self.write_reg(I2C_IBCR, IBCR_MDIS);
+        self.write_reg(I2C_IBFD, ibfd);
+        self.write_reg(I2C_IBSR, IBSR_IBAL | IBSR_IBIF);
+        self.write_reg(I2C_IBCR, 0);
+        
+        Ok(())
+    }
+    
+    /**
+     * Wait for the bus busy flag to reach `busy`
+     */
+    fn wait_bus(&self, busy: bool) -> bool {
+        for _ in 0..I2C_POLL_LIMIT {
+            if (self.read_reg(I2C_IBSR) & IBSR_IBB != 0) == busy {
+                return true;
+            }
+        }
+        false
+    }
+    
+    /**
+     * Wait for the current byte to finish and acknowledge the interrupt flag
+     */
+    fn wait_transfer(&self) -> Result<(), I2cError> {
+        for _ in 0..I2C_POLL_LIMIT {
+            let sr = self.read_reg(I2C_IBSR);
+            if sr & IBSR_IBAL != 0 {
+                self.write_reg(I2C_IBSR, IBSR_IBAL | IBSR_IBIF);
+                return Err(I2cError::ArbitrationLost);
+            }
+            if sr & IBSR_IBIF != 0 {
+                self.write_reg(I2C_IBSR, IBSR_IBIF);
+                return Ok(());
+            }
+        }
+        Err(I2cError::Timeout)
+    }
+    
+    /**
+     * Shift out one byte and check that it was acknowledged
+     */
+    fn send(&self, byte: u8) -> Result<(), I2cError> {
+        self.write_reg(I2C_IBDR, byte);
+        self.wait_transfer()?;
+        if self.read_reg(I2C_IBSR) & IBSR_RXAK != 0 {
+            return Err(I2cError::Nack);
+        }
+        Ok(())
+    }
+    
+    fn address_byte(addr: u8, read: bool) -> Result<u8, I2cError> {
+        if addr > 0x7F {
+            return Err(I2cError::InvalidAddress);
+        }
+        Ok((addr << 1) | read as u8)
+    }
+    
+    /**
+     * Take the bus with a START and address a device
+     */
+    fn start(&self, addr: u8, read: bool) -> Result<(), I2cError> {
+        let addr = Self::address_byte(addr, read)?;
+        if !self.wait_bus(false) {
+            return Err(I2cError::BusBusy);
+        }
+        
+        self.write_reg(I2C_IBSR, IBSR_IBAL | IBSR_IBIF);
+        let cr = self.read_reg(I2C_IBCR);
+        self.write_reg(I2C_IBCR, cr | IBCR_MSSL | IBCR_TXRX);
+        if !self.wait_bus(true) {
+            return Err(I2cError::BusBusy);
+        }
+        
+        self.send(addr)
+    }
+    
+    /**
+     * Address a device again with a repeated START, keeping the bus
+     */
+    fn restart(&self, addr: u8, read: bool) -> Result<(), I2cError> {
+        let addr = Self::address_byte(addr, read)?;
+        let cr = self.read_reg(I2C_IBCR);
+        self.write_reg(I2C_IBCR, cr | IBCR_RSTA | IBCR_TXRX);
+        self.send(addr)
+    }
+    
+    /**
+     * Release the bus with a STOP (a no-op if arbitration was lost)
+     */
+    fn stop(&self) -> Result<(), I2cError> {
+        let cr = self.read_reg(I2C_IBCR);
+        self.write_reg(I2C_IBCR, cr & !(IBCR_MSSL | IBCR_TXRX | IBCR_NOACK | IBCR_RSTA));
+        if !self.wait_bus(false) {
+            return Err(I2cError::BusBusy);
+        }
+        Ok(())
+    }
+    
+    fn write_bytes(&self, data: &[u8]) -> Result<(), I2cError> {
+        for &byte in data {
+            self.send(byte)?;
+        }
+        Ok(())
+    }
+    
+    /**
+     * Receive into `buf` after a read address, NACKing the last byte
+     * The STOP is issued before the last byte is read out of IBDR, because
+     * reading IBDR in master receive mode clocks in the next byte.
+     */
+    fn read_bytes(&self, buf: &mut [u8]) -> Result<(), I2cError> {
+        let last = buf.len() - 1;
+        let mut cr = self.read_reg(I2C_IBCR) & !(IBCR_TXRX | IBCR_NOACK);
+        if last == 0 {
+            cr |= IBCR_NOACK;
+        }
+        self.write_reg(I2C_IBCR, cr);
+        
+        // Dummy read starts the first byte
+        let _ = self.read_reg(I2C_IBDR);
+        
+        for (i, byte) in buf.iter_mut().enumerate() {
+            self.wait_transfer()?;
+            let cr = self.read_reg(I2C_IBCR);
+            if i == last {
+                self.write_reg(I2C_IBCR, cr & !(IBCR_MSSL | IBCR_NOACK));
+            } else if i + 1 == last {
+                self.write_reg(I2C_IBCR, cr | IBCR_NOACK);
+            }
+            *byte = self.read_reg(I2C_IBDR);
+        }
+        
+        Ok(())
+    }
+    
+    /**
+     * Write `data` to the device at 7-bit address `addr`
+     */
+    pub fn write(&mut self, addr: u8, data: &[u8]) -> Result<(), I2cError> {
+        let result = self.start(addr, false).and_then(|_| self.write_bytes(data));
+        let stop = self.stop();
+        result.and(stop)
+    }
+    
+    /**
+     * Read `buf.len()` bytes from the device at 7-bit address `addr`
+     */
+    pub fn read(&mut self, addr: u8, buf: &mut [u8]) -> Result<(), I2cError> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        
+        let result = self.start(addr, true).and_then(|_| self.read_bytes(buf));
+        let stop = self.stop();
+        result.and(stop)
+    }
+    
+    /**
+     * Write `data` then read into `buf` with a repeated START in between
+     * The usual register read: `data` holds the register address.
+     */
+    pub fn write_read(&mut self, addr: u8, data: &[u8], buf: &mut [u8]) -> Result<(), I2cError> {
+        if buf.is_empty() {
+            return self.write(addr, data);
+        }
+        
+        let result = self.start(addr, false)
+            .and_then(|_| self.write_bytes(data))
+            .and_then(|_| self.restart(addr, true))
+            .and_then(|_| self.read_bytes(buf));
+        let stop = self.stop();
+        result.and(stop)
+    }
+    
+    /**
+     * Check whether a device acknowledges `addr` (an address-only write)
+     */
+    pub fn probe(&mut self, addr: u8) -> bool {
+        self.write(addr, &[]).is_ok()
+    }
+    
+    /**
+     * Probe every non-reserved address and print the ones that respond
+     */
+    pub fn scan(&mut self) -> usize {
+        let mut found = 0;
+        for addr in I2C_SCAN_FIRST..=I2C_SCAN_LAST {
+            if self.probe(addr) {
+                crate::println!("I2C: device at 0x{:02x}", addr);
+                found += 1;
+            }
+        }
+        crate::println!("I2C: {} device(s) found", found);
+        found
+    }
+}
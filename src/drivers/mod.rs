@@ -1,4 +1,10 @@
+pub mod can;
+pub mod gpio;
+pub mod i2c;
+pub mod soft_uart;
+pub mod spi;
 pub mod uart;
+pub mod watchdog;
 
 // Initialize all drivers
 pub fn init() {
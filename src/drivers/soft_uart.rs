@@ -0,0 +1,135 @@
+// Bit-banged UART over two GPIO pads
+// Last-resort console for bring-up when the LinFLEX is misconfigured or its
+// clock is wrong. Bit timing comes from timer::delay_us, which has 1us
+// resolution, so keep the baud rate at 19200 or below.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::arch::aarch64;
+use crate::arch::s32g3::timer;
+use super::gpio::{self, GpioError};
+use super::uart::SerialOps;
+
+// Pads and bit period; BIT_US == 0 means not initialized
+static TX_PIN: AtomicU32 = AtomicU32::new(0);
+static RX_PIN: AtomicU32 = AtomicU32::new(0);
+static BIT_US: AtomicU32 = AtomicU32::new(0);
+
+/**
+ * The software UART as a console device
+ */
+pub struct SoftUart;
+
+impl SerialOps for SoftUart {
+    fn putc(&self, c: u8) {
+        putc(c);
+    }
+    
+    fn flush(&self) {
+        // Every byte is fully shifted out before putc returns
+    }
+    
+    fn have_rx_data(&self) -> bool {
+        // A low line is the start bit of an incoming byte
+        BIT_US.load(Ordering::Acquire) != 0 && !gpio::get(RX_PIN.load(Ordering::Relaxed))
+    }
+    
+    fn getchar(&self) -> u8 {
+        // Without pads configured there is nothing to wait for
+        getc().unwrap_or(0)
+    }
+}
+
+pub static SOFT_UART_CONSOLE: SoftUart = SoftUart;
+
+/**
+ * Configure the pads and bit period
+ * Select it as the console with uart::set_console(&SOFT_UART_CONSOLE)
+ */
+pub fn init(tx_pin: u32, rx_pin: u32, baud: u32) -> Result<(), GpioError> {
+    gpio::configure_output(tx_pin)?;
+    gpio::configure_input(rx_pin)?;
+    
+    // Idle line is high
+    gpio::set(tx_pin, true);
+    
+    TX_PIN.store(tx_pin, Ordering::Relaxed);
+    RX_PIN.store(rx_pin, Ordering::Relaxed);
+    BIT_US.store(((1_000_000 + baud / 2) / baud.max(1)).max(1), Ordering::Release);
+    
+    Ok(())
+}
+
+/**
+ * Send one byte: start bit, 8 data bits LSB first, one stop bit
+ */
+pub fn putc(c: u8) {
+    let bit_us = BIT_US.load(Ordering::Acquire);
+    if bit_us == 0 {
+        return;
+    }
+    
+    // If it's a newline, send carriage return first
+    if c == b'\n' {
+        putc(b'\r');
+    }
+    
+    let tx = TX_PIN.load(Ordering::Relaxed);
+    
+    // Mask IRQs for the whole frame so bit timing is not stretched
+    aarch64::with_irqs_masked(|| {
+        gpio::set(tx, false);
+        timer::delay_us(bit_us);
+        
+        for i in 0..8 {
+            gpio::set(tx, (c >> i) & 1 != 0);
+            timer::delay_us(bit_us);
+        }
+        
+        gpio::set(tx, true);
+        timer::delay_us(bit_us);
+    });
+}
+
+/**
+ * Receive one byte, waiting for a start bit
+ * Returns None if not initialized
+ */
+pub fn getc() -> Option<u8> {
+    let bit_us = BIT_US.load(Ordering::Acquire);
+    if bit_us == 0 {
+        return None;
+    }
+    
+    let rx = RX_PIN.load(Ordering::Relaxed);
+    
+    // Wait for the falling edge of the start bit
+    while gpio::get(rx) {
+        core::hint::spin_loop();
+    }
+    
+    Some(aarch64::with_irqs_masked(|| {
+        // Move to the middle of the start bit, then sample each data bit
+        timer::delay_us(bit_us / 2);
+        
+        let mut c = 0u8;
+        for i in 0..8 {
+            timer::delay_us(bit_us);
+            if gpio::get(rx) {
+                c |= 1 << i;
+            }
+        }
+        
+        // Skip the stop bit
+        timer::delay_us(bit_us);
+        c
+    }))
+}
+
+/**
+ * Send a string
+ */
+pub fn puts(s: &str) {
+    for c in s.bytes() {
+        putc(c);
+    }
+}
@@ -0,0 +1,260 @@
+use core::ptr::{read_volatile, write_volatile};
+use crate::arch::board::BOARD;
+use crate::arch::s32g3::{
+    SPI_MCR, SPI_CTAR0, SPI_SR, SPI_PUSHR, SPI_POPR,
+    SPI_MCR_MSTR, SPI_MCR_PCSIS_MASK, SPI_MCR_MDIS, SPI_MCR_CLR_TXF, SPI_MCR_CLR_RXF, SPI_MCR_HALT,
+    SPI_CTAR_FMSZ_SHIFT, SPI_CTAR_CPOL, SPI_CTAR_CPHA, SPI_CTAR_PBR_SHIFT,
+    SPI_SR_TCF, SPI_SR_TFUF, SPI_SR_TFFF, SPI_SR_RFOF, SPI_SR_RFDF,
+    SPI_PUSHR_CONT, SPI_PUSHR_PCS_SHIFT, SPI_FIFO_DEPTH
+};
+use super::gpio;
+
+// Status polls without progress before a transfer is abandoned
+const SPI_POLL_LIMIT: u32 = 1_000_000;
+
+// Prescaler values selected by CTAR.PBR
+const PBR_VALUES: [u32; 4] = [2, 3, 5, 7];
+
+// Scaler values selected by CTAR.BR
+const BR_VALUES: [u32; 16] = [
+    2, 4, 6, 8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768
+];
+
+/**
+ * Errors reported by the SPI driver
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiError {
+    InvalidFrequency,   // Requested clock is slower than the controller can divide to
+    InvalidFrameSize,   // Frame size outside 4-16 bits
+    InvalidLength,      // Buffer is not a whole number of frames
+    InvalidChipSelect,  // Hardware CS line or GPIO pin out of range
+    Overrun,            // RX FIFO overflowed and a received frame was lost
+    Underrun,           // TX FIFO ran empty mid-transfer
+    Timeout,            // Controller stopped making progress
+}
+
+/**
+ * Clock polarity and phase, numbered as in the usual SPI mode convention
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiMode {
+    Mode0,  // CPOL 0, CPHA 0
+    Mode1,  // CPOL 0, CPHA 1
+    Mode2,  // CPOL 1, CPHA 0
+    Mode3,  // CPOL 1, CPHA 1
+}
+
+impl SpiMode {
+    fn ctar_bits(self) -> u32 {
+        match self {
+            SpiMode::Mode0 => 0,
+            SpiMode::Mode1 => SPI_CTAR_CPHA,
+            SpiMode::Mode2 => SPI_CTAR_CPOL,
+            SpiMode::Mode3 => SPI_CTAR_CPOL | SPI_CTAR_CPHA,
+        }
+    }
+}
+
+/**
+ * How the device on the bus is selected for a transfer
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChipSelect {
+    Hardware(u8),   // Controller PCS line 0-7, asserted low by the controller
+    Gpio(u32),      // GPIO pad driven low by the driver around each transfer
+    None,           // Caller manages selection itself
+}
+
+/**
+ * One SPI controller in master mode
+ */
+pub struct Spi {
+    base: usize,            // SPI register block
+    clock: u32,             // SPI module clock
+    bits: u32,              // Frame size programmed by configure
+    cs: ChipSelect,
+}
+
+impl Spi {
+    /**
+     * Create a driver for the controller at `base`
+     * Nothing is touched until configure() is called
+     */
+    pub const fn new(base: usize) -> Self {
+        Spi {
+            base,
+            clock: BOARD.spi_clock_hz,
+            bits: 8,
+            cs: ChipSelect::Hardware(0),
+        }
+    }
+    
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+    
+    fn write_reg(&self, offset: usize, val: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, val) }
+    }
+    
+    /**
+     * Pick the PBR/BR pair giving the fastest clock not above `freq_hz`
+     */
+    fn baud_divider(&self, freq_hz: u32) -> Result<u32, SpiError> {
+        if freq_hz == 0 {
+            return Err(SpiError::InvalidFrequency);
+        }
+        
+        let mut best: Option<(u32, u32)> = None;
+        for (pbr, pbr_val) in PBR_VALUES.iter().enumerate() {
+            for (br, br_val) in BR_VALUES.iter().enumerate() {
+                let sck = self.clock / (pbr_val * br_val);
+                if sck <= freq_hz && best.is_none_or(|(best_sck, _)| sck > best_sck) {
+                    best = Some((sck, ((pbr as u32) << SPI_CTAR_PBR_SHIFT) | br as u32));
+                }
+            }
+        }
+        
+        best.map(|(_, ctar)| ctar).ok_or(SpiError::InvalidFrequency)
+    }
+    
+    /**
+     * Set up the controller as master with the given mode, clock and frame size
+     * The clock is rounded down to the nearest rate the dividers can produce.
+     * Frames wider than 8 bits take two bytes of the transfer buffer, most
+     * significant byte first.
+     */
+    pub fn configure(&mut self, mode: SpiMode, freq_hz: u32, bits: u32) -> Result<(), SpiError> {
+        if !(4..=16).contains(&bits) {
+            return Err(SpiError::InvalidFrameSize);
+        }
+        let divider = self.baud_divider(freq_hz)?;
+        
+        // Halt while the attributes change; chip selects idle high
+        self.write_reg(SPI_MCR, SPI_MCR_MSTR | SPI_MCR_PCSIS_MASK | SPI_MCR_HALT);
+        
+        // DBR stays clear so SCK keeps a 50% duty cycle with odd prescalers
+        let ctar = ((bits - 1) << SPI_CTAR_FMSZ_SHIFT) | mode.ctar_bits() | divider;
+        self.write_reg(SPI_CTAR0, ctar);
+        
+        self.bits = bits;
+        Ok(())
+    }
+    
+    /**
+     * Choose how the device is selected for subsequent transfers
+     * A GPIO chip select is configured as an output and deasserted (high).
+     */
+    pub fn set_chip_select(&mut self, cs: ChipSelect) -> Result<(), SpiError> {
+        match cs {
+            ChipSelect::Hardware(line) if line >= 8 => return Err(SpiError::InvalidChipSelect),
+            ChipSelect::Gpio(pin) => {
+                gpio::configure_output(pin).map_err(|_| SpiError::InvalidChipSelect)?;
+                gpio::set(pin, true);
+            }
+            _ => {}
+        }
+        
+        self.cs = cs;
+        Ok(())
+    }
+    
+    fn frame_bytes(&self) -> usize {
+        if self.bits > 8 { 2 } else { 1 }
+    }
+    
+    /**
+     * Shift `buf` out and replace it with the bytes shifted in
+     * The chip select stays asserted for the whole buffer. Blocks until the
+     * last frame is received.
+     */
+    pub fn transfer(&mut self, buf: &mut [u8]) -> Result<(), SpiError> {
+        let width = self.frame_bytes();
+        if buf.len() % width != 0 {
+            return Err(SpiError::InvalidLength);
+        }
+        if buf.is_empty() {
+            return Ok(());
+        }
+        
+        // Start from empty FIFOs and clear stale flags (write 1 to clear)
+        let mcr = self.read_reg(SPI_MCR) & !SPI_MCR_MDIS;
+        self.write_reg(SPI_MCR, mcr | SPI_MCR_CLR_TXF | SPI_MCR_CLR_RXF);
+        self.write_reg(SPI_SR, SPI_SR_TCF | SPI_SR_TFUF | SPI_SR_TFFF | SPI_SR_RFOF | SPI_SR_RFDF);
+        
+        if let ChipSelect::Gpio(pin) = self.cs {
+            gpio::set(pin, false);
+        }
+        self.write_reg(SPI_MCR, mcr & !SPI_MCR_HALT);
+        
+        let result = self.shift(buf, width);
+        
+        self.write_reg(SPI_MCR, self.read_reg(SPI_MCR) | SPI_MCR_HALT);
+        if let ChipSelect::Gpio(pin) = self.cs {
+            gpio::set(pin, true);
+        }
+        
+        result
+    }
+    
+    /**
+     * Keep up to a FIFO's worth of frames in flight until every frame is back
+     */
+    fn shift(&self, buf: &mut [u8], width: usize) -> Result<(), SpiError> {
+        let frames = buf.len() / width;
+        let pcs = match self.cs {
+            ChipSelect::Hardware(line) => 1 << (SPI_PUSHR_PCS_SHIFT + line as u32),
+            _ => 0,
+        };
+        
+        let mut sent = 0;
+        let mut received = 0;
+        let mut polls = 0;
+        while received < frames {
+            let sr = self.read_reg(SPI_SR);
+            if sr & SPI_SR_RFOF != 0 {
+                return Err(SpiError::Overrun);
+            }
+            if sr & SPI_SR_TFUF != 0 {
+                return Err(SpiError::Underrun);
+            }
+            
+            if sr & SPI_SR_RFDF != 0 {
+                let data = self.read_reg(SPI_POPR);
+                self.write_reg(SPI_SR, SPI_SR_RFDF);
+                if width == 2 {
+                    buf[received * 2] = (data >> 8) as u8;
+                    buf[received * 2 + 1] = data as u8;
+                } else {
+                    buf[received] = data as u8;
+                }
+                received += 1;
+                polls = 0;
+                continue;
+            }
+            
+            if sent < frames && sent - received < SPI_FIFO_DEPTH && sr & SPI_SR_TFFF != 0 {
+                let data = if width == 2 {
+                    ((buf[sent * 2] as u32) << 8) | buf[sent * 2 + 1] as u32
+                } else {
+                    buf[sent] as u32
+                };
+                // Hold the hardware chip select between frames
+                let cont = if sent + 1 < frames { SPI_PUSHR_CONT } else { 0 };
+                self.write_reg(SPI_PUSHR, cont | pcs | data);
+                self.write_reg(SPI_SR, SPI_SR_TFFF);
+                sent += 1;
+                polls = 0;
+                continue;
+            }
+            
+            polls += 1;
+            if polls > SPI_POLL_LIMIT {
+                return Err(SpiError::Timeout);
+            }
+        }
+        
+        Ok(())
+    }
+}
@@ -1,176 +1,726 @@
 use core::fmt;
 use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use spin::Mutex;
+use crate::arch::{aarch64, clocks, gic};
+use crate::arch::board::BOARD;
+use crate::arch::gic::GicError;
 use crate::arch::s32g3::{
-    UART_BASE,
-    LINFLEX_LINCR1, LINFLEX_LINSR, LINFLEX_UARTCR, LINFLEX_UARTSR,
-    LINFLEX_LINIBRR, LINFLEX_LINFBRR, LINFLEX_BDRL, LINFLEX_UARTPTO,
+    S32G_LINFLEX0_IRQ, S32G_LINFLEX1_IRQ, LINFLEX_LINIER, LINIER_DRIE,
+    LINFLEX_LINCR1, LINFLEX_LINSR, LINFLEX_GCR, GCR_STOP, LINIBRR_MAX,
+    UARTCR_PCE, UARTCR_WL1, LINFLEX_UARTCR, LINFLEX_UARTSR,
+    LINFLEX_LINIBRR, LINFLEX_LINFBRR, LINFLEX_BDRL, LINFLEX_BDRM, LINFLEX_UARTPTO,
     LINCR1_INIT, LINCR1_MME, LINSR_LINS_MASK, LINSR_LINS_INITMODE,
     UARTCR_UART, UARTCR_WL0, UARTCR_PC0, UARTCR_PC1, UARTCR_TXEN,
     UARTCR_RXEN, UARTCR_TFBM, UARTCR_RFBM, UARTCR_ROSE, UARTCR_TFC,
-    UARTSR_DTF, UART_CLOCK_HZ, UART_BAUD_RATE, LDIV_MULTIPLIER
+    UARTSR_DTF, UARTSR_DRFRFE, UARTSR_RMB, UART_BAUD_RATE, LDIV_MULTIPLIER
 };
 
 /**
- * Calculate and set the baud rate generator registers
+ * Operations a console device provides to the print macros
  */
-fn linflex_set_brg(clock: u32, baud: u32) {
-    unsafe {
-        let linibrr = (UART_BASE + LINFLEX_LINIBRR) as *mut u32;
-        let linfbrr = (UART_BASE + LINFLEX_LINFBRR) as *mut u32;
-        let uartcr = (UART_BASE + LINFLEX_UARTCR) as *mut u32;
-        let mut ldiv_mult = LDIV_MULTIPLIER;
-
-        // Check if Reduced Oversampling is enabled
-        let cr_val = read_volatile(uartcr);
-        if cr_val & UARTCR_ROSE != 0 {
-            // Extract OSR field if ROSE is set
-            ldiv_mult = (cr_val >> 24) & 0xF;
-        }
+pub trait SerialOps: Sync {
+    // Send a single byte
+    fn putc(&self, c: u8);
+    
+    // Wait until all sent bytes have left the device
+    fn flush(&self);
+    
+    // Check if a received byte is waiting
+    fn have_rx_data(&self) -> bool;
+    
+    // Receive a byte, waiting for one to arrive
+    fn getchar(&self) -> u8;
+}
 
-        // Calculate integer and fractional dividers
-        let dividr = baud * ldiv_mult;
-        let divisr = clock;
-        
-        let ibr = divisr / dividr;
-        let mut fbr = ((divisr % dividr) * 16) / dividr;
-        fbr &= 0xF;
+/**
+ * One LinFLEX instance
+ * Each instance keeps its own RX ring, used once its RX interrupt is enabled
+ */
+pub struct Uart {
+    base: usize,            // LinFLEX register block
+    baud: AtomicU32,        // Baud rate programmed by init/configure
+    irq: u32,               // GIC interrupt ID
+    rx_ring: Mutex<RxRing>, // Taken by the RX interrupt, and with IRQs masked by readers
+    rx_overflow: AtomicBool,        // Set when the ring overflowed
+    rx_interrupt_mode: AtomicBool,  // Reception is interrupt-driven
+}
 
-        // Set the baud rate registers
-        write_volatile(linibrr, ibr);
-        write_volatile(linfbrr, fbr);
+impl SerialOps for Uart {
+    fn putc(&self, c: u8) {
+        Uart::putc(self, c);
+    }
+    
+    fn flush(&self) {
+        Uart::flush(self);
+    }
+    
+    fn have_rx_data(&self) -> bool {
+        Uart::have_rx_data(self)
+    }
+    
+    fn getchar(&self) -> u8 {
+        Uart::getchar(self)
     }
 }
 
+// LinFLEX0, the debug console
+pub static LINFLEX0: Uart = Uart::new(BOARD.uart_base, UART_BAUD_RATE, S32G_LINFLEX0_IRQ);
+
+// LinFLEX1, free for a data link
+pub static LINFLEX1: Uart = Uart::new(BOARD.uart1_base, UART_BAUD_RATE, S32G_LINFLEX1_IRQ);
+
+// Instances served by rx_isr
+static UARTS: [&Uart; 2] = [&LINFLEX0, &LINFLEX1];
+
 /**
- * Initialize the LinFLEX UART for console output
+ * The LinFLEX designated as the console
+ * The print macros start out on it and the free functions below drive it
  */
-pub fn init() {
-    unsafe {
-        let lincr1 = (UART_BASE + LINFLEX_LINCR1) as *mut u32;
-        let linsr = (UART_BASE + LINFLEX_LINSR) as *mut u32;
-        let uartcr = (UART_BASE + LINFLEX_UARTCR) as *mut u32;
-        let uartpto = (UART_BASE + LINFLEX_UARTPTO) as *mut u32;
-        
-        // Set master mode and init mode
-        write_volatile(lincr1, LINCR1_INIT);
-        write_volatile(lincr1, LINCR1_MME | LINCR1_INIT);
-        
-        // Wait for init mode entry
-        while (read_volatile(linsr) & LINSR_LINS_MASK) != LINSR_LINS_INITMODE {
-            // Wait
-        }
-        
-        // Set UART bit
-        write_volatile(uartcr, UARTCR_UART);
-        
-        // Set baud rate
-        linflex_set_brg(UART_CLOCK_HZ, UART_BAUD_RATE);
-        
-        // Set preset timeout register value
-        write_volatile(uartpto, 0xF);
-        
-        // 8-bit data, no parity, Tx/Rx enabled, UART mode, FIFO mode
-        write_volatile(uartcr, UARTCR_PC1 | UARTCR_RXEN | UARTCR_TXEN | UARTCR_PC0 | 
-                  UARTCR_WL0 | UARTCR_UART | UARTCR_RFBM | UARTCR_TFBM);
-        
-        // End init mode
-        write_volatile(lincr1, read_volatile(lincr1) & !LINCR1_INIT);
-    }
+pub fn console() -> &'static Uart {
+    &LINFLEX0
 }
 
+// Device the print macros write to
+static CONSOLE: Mutex<&'static dyn SerialOps> = Mutex::new(&LINFLEX0);
+
+// Bytes buffered by the RX interrupt
+const RX_RING_SIZE: usize = 256;
+
 /**
- * Wait for the transmit buffer to be empty
+ * Received bytes waiting for a reader
+ * Filled by the RX interrupt; when full the oldest byte is dropped
  */
-fn uart_wait_tx_complete() {
-    unsafe {
-        let uartcr = (UART_BASE + LINFLEX_UARTCR) as *mut u32;
-        let uartsr = (UART_BASE + LINFLEX_UARTSR) as *mut u32;
-        
-        // Check if FIFO mode or buffer mode
-        let is_fifo_mode = read_volatile(uartcr) & UARTCR_TFBM;
+struct RxRing {
+    buf: [u8; RX_RING_SIZE],
+    head: usize,    // Index of the oldest byte
+    len: usize,
+}
+
+impl RxRing {
+    const fn new() -> Self {
+        RxRing {
+            buf: [0; RX_RING_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+    
+    // Append a byte, dropping the oldest one if full; returns false on overrun
+    fn push(&mut self, c: u8) -> bool {
+        let tail = (self.head + self.len) % RX_RING_SIZE;
+        self.buf[tail] = c;
         
-        if is_fifo_mode != 0 {
-            // FIFO mode - wait for DTF flag to clear
-            while read_volatile(uartsr) & UARTSR_DTF != 0 {
-                // Wait
-            }
+        if self.len == RX_RING_SIZE {
+            self.head = (self.head + 1) % RX_RING_SIZE;
+            false
         } else {
-            // Buffer mode - wait for DTF flag to set, then clear it
-            while read_volatile(uartsr) & UARTSR_DTF == 0 {
-                // Wait
-            }
-            write_volatile(uartsr, UARTSR_DTF);  // Clear the flag in buffer mode
+            self.len += 1;
+            true
         }
     }
+    
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let c = self.buf[self.head];
+        self.head = (self.head + 1) % RX_RING_SIZE;
+        self.len -= 1;
+        Some(c)
+    }
 }
 
 /**
- * Send a single character to UART
+ * Redirect the print macros and puts to another device
+ * Drains the current console first, and holds the console lock so that no
+ * print is interleaved across the switch
  */
-pub fn putc(c: u8) {
-    unsafe {
-        let bdrl = (UART_BASE + LINFLEX_BDRL) as *mut u32;
-        let uartcr = (UART_BASE + LINFLEX_UARTCR) as *mut u32;
-        let uartsr = (UART_BASE + LINFLEX_UARTSR) as *mut u32;
+pub fn set_console(new: &'static dyn SerialOps) {
+    let mut console = CONSOLE.lock();
+    console.flush();
+    *console = new;
+}
+
+/**
+ * Parity setting for configure
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+/**
+ * Reasons configure rejects a frame format
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UartConfigError {
+    UnsupportedBaud,        // Divider out of range for the module clock
+    UnsupportedDataBits,    // Only 7 and 8 data bits are supported
+    UnsupportedStopBits,    // Only 1 and 2 stop bits are supported
+}
+
+/**
+ * Integer and fractional baud rate dividers for a clock and baud rate
+ */
+fn brg_dividers(clock: u32, baud: u32, ldiv_mult: u32) -> (u32, u32) {
+    let dividr = baud * ldiv_mult;
+    let divisr = clock;
+    
+    let ibr = divisr / dividr;
+    let fbr = (((divisr % dividr) * 16) / dividr) & 0xF;
+    (ibr, fbr)
+}
+
+impl Uart {
+    /**
+     * Describe a LinFLEX instance; nothing is touched until init
+     */
+    pub const fn new(base: usize, baud: u32, irq: u32) -> Self {
+        Uart {
+            base,
+            baud: AtomicU32::new(baud),
+            irq,
+            rx_ring: Mutex::new(RxRing::new()),
+            rx_overflow: AtomicBool::new(false),
+            rx_interrupt_mode: AtomicBool::new(false),
+        }
+    }
+    
+    /**
+     * Current baud rate
+     */
+    pub fn baud(&self) -> u32 {
+        self.baud.load(Ordering::Relaxed)
+    }
+    
+    /**
+     * Baud rate divider multiplier, honouring reduced oversampling
+     */
+    fn ldiv_multiplier(&self) -> u32 {
+        unsafe {
+            let uartcr = (self.base + LINFLEX_UARTCR) as *const u32;
+            let cr_val = read_volatile(uartcr);
+            if cr_val & UARTCR_ROSE != 0 {
+                // Extract OSR field if ROSE is set
+                (cr_val >> 24) & 0xF
+            } else {
+                LDIV_MULTIPLIER
+            }
+        }
+    }
+    
+    /**
+     * Calculate and set the baud rate generator registers
+     */
+    fn set_brg(&self, baud: u32) {
+        unsafe {
+            let linibrr = (self.base + LINFLEX_LINIBRR) as *mut u32;
+            let linfbrr = (self.base + LINFLEX_LINFBRR) as *mut u32;
+            
+            // Calculate integer and fractional dividers
+            let (ibr, fbr) = brg_dividers(clocks::uart_clock_hz(), baud, self.ldiv_multiplier());
+            
+            // Set the baud rate registers
+            write_volatile(linibrr, ibr);
+            write_volatile(linfbrr, fbr);
+        }
+    }
+    
+    /**
+     * Enter LinFLEX initialization mode (required to change UARTCR mode bits)
+     */
+    fn enter_init_mode(&self) {
+        unsafe {
+            let lincr1 = (self.base + LINFLEX_LINCR1) as *mut u32;
+            let linsr = (self.base + LINFLEX_LINSR) as *mut u32;
+            
+            // Set master mode and init mode
+            write_volatile(lincr1, LINCR1_INIT);
+            write_volatile(lincr1, LINCR1_MME | LINCR1_INIT);
+            
+            // Wait for init mode entry
+            while (read_volatile(linsr) & LINSR_LINS_MASK) != LINSR_LINS_INITMODE {
+                // Wait
+            }
+        }
+    }
+    
+    /**
+     * Leave LinFLEX initialization mode
+     */
+    fn exit_init_mode(&self) {
+        unsafe {
+            let lincr1 = (self.base + LINFLEX_LINCR1) as *mut u32;
+            write_volatile(lincr1, read_volatile(lincr1) & !LINCR1_INIT);
+        }
+    }
+    
+    /**
+     * Initialize the LinFLEX at its default baud rate, 8N1, FIFO mode
+     */
+    pub fn init(&self) {
+        unsafe {
+            let uartcr = (self.base + LINFLEX_UARTCR) as *mut u32;
+            let uartpto = (self.base + LINFLEX_UARTPTO) as *mut u32;
+            
+            self.enter_init_mode();
+            
+            // Set UART bit
+            write_volatile(uartcr, UARTCR_UART);
+            
+            // Set baud rate
+            self.set_brg(self.baud.load(Ordering::Relaxed));
+            
+            // Set preset timeout register value
+            write_volatile(uartpto, 0xF);
+            
+            // 8-bit data, no parity, Tx/Rx enabled, UART mode, FIFO mode
+            write_volatile(uartcr, UARTCR_PC1 | UARTCR_RXEN | UARTCR_TXEN | UARTCR_PC0 | 
+                      UARTCR_WL0 | UARTCR_UART | UARTCR_RFBM | UARTCR_TFBM);
+            
+            // End init mode
+            self.exit_init_mode();
+        }
+    }
+    
+    /**
+     * Change the baud rate and frame format at runtime
+     * Drains pending output, then reprograms the dividers, word length, parity
+     * and stop bits in init mode. FIFO/buffer mode selection is kept.
+     */
+    pub fn configure(&self, baud: u32, data_bits: u8, parity: Parity, stop_bits: u8) -> Result<(), UartConfigError> {
+        let word_length = match data_bits {
+            7 => 0,
+            8 => UARTCR_WL0,
+            _ => return Err(UartConfigError::UnsupportedDataBits),
+        };
+        let parity_bits = match parity {
+            Parity::None => 0,
+            Parity::Even => UARTCR_PCE,
+            Parity::Odd => UARTCR_PCE | UARTCR_PC0,
+        };
+        let two_stop_bits = match stop_bits {
+            1 => false,
+            2 => true,
+            _ => return Err(UartConfigError::UnsupportedStopBits),
+        };
         
-        // If it's a newline, send carriage return first
-        if c == b'\n' {
-            putc(b'\r');
+        let ldiv_mult = self.ldiv_multiplier();
+        if baud == 0 || baud.checked_mul(ldiv_mult).is_none() {
+            return Err(UartConfigError::UnsupportedBaud);
+        }
+        let (ibr, _) = brg_dividers(clocks::uart_clock_hz(), baud, ldiv_mult);
+        if ibr == 0 || ibr > LINIBRR_MAX {
+            return Err(UartConfigError::UnsupportedBaud);
         }
         
-        // Check if FIFO mode or buffer mode
-        let is_fifo_mode = read_volatile(uartcr) & UARTCR_TFBM;
+        self.flush();
         
-        if is_fifo_mode != 0 {
-            // FIFO mode - wait for DTF flag to clear
-            while read_volatile(uartsr) & UARTSR_DTF != 0 {
-                // Wait
+        unsafe {
+            let uartcr = (self.base + LINFLEX_UARTCR) as *mut u32;
+            let gcr = (self.base + LINFLEX_GCR) as *mut u32;
+            
+            self.enter_init_mode();
+            
+            let mut cr = read_volatile(uartcr)
+                & !(UARTCR_WL0 | UARTCR_WL1 | UARTCR_PCE | UARTCR_PC0 | UARTCR_PC1);
+            cr |= word_length | parity_bits;
+            write_volatile(uartcr, cr);
+            
+            let mut gcr_val = read_volatile(gcr) & !GCR_STOP;
+            if two_stop_bits {
+                gcr_val |= GCR_STOP;
             }
+            write_volatile(gcr, gcr_val);
+            
+            self.set_brg(baud);
+            
+            self.exit_init_mode();
         }
         
-        // Write character to data register
-        write_volatile(bdrl, c as u32);
+        self.baud.store(baud, Ordering::Relaxed);
+        Ok(())
+    }
+    
+    /**
+     * Select FIFO mode (true) or buffer mode (false) for TX and RX
+     * Drains pending output first, since the mode can only change in init mode
+     */
+    pub fn set_fifo_mode(&self, tx: bool, rx: bool) {
+        self.flush();
         
-        if is_fifo_mode == 0 {
-            // Buffer mode - wait for DTF flag to set, then clear it
-            while read_volatile(uartsr) & UARTSR_DTF == 0 {
-                // Wait
+        unsafe {
+            let uartcr = (self.base + LINFLEX_UARTCR) as *mut u32;
+            
+            self.enter_init_mode();
+            
+            let mut cr = read_volatile(uartcr) & !(UARTCR_TFBM | UARTCR_RFBM);
+            if tx {
+                cr |= UARTCR_TFBM;
+            }
+            if rx {
+                cr |= UARTCR_RFBM;
+            }
+            write_volatile(uartcr, cr);
+            
+            self.exit_init_mode();
+        }
+    }
+    
+    /**
+     * Check if TX is in FIFO mode (false means buffer mode)
+     */
+    pub fn tx_fifo_mode(&self) -> bool {
+        unsafe {
+            let uartcr = (self.base + LINFLEX_UARTCR) as *const u32;
+            read_volatile(uartcr) & UARTCR_TFBM != 0
+        }
+    }
+    
+    /**
+     * Check if RX is in FIFO mode (false means buffer mode)
+     */
+    pub fn rx_fifo_mode(&self) -> bool {
+        unsafe {
+            let uartcr = (self.base + LINFLEX_UARTCR) as *const u32;
+            read_volatile(uartcr) & UARTCR_RFBM != 0
+        }
+    }
+    
+    /**
+     * The one TX-complete wait shared by putc
+     * FIFO mode: DTF reads as "TX FIFO full", so wait for room before writing.
+     * Buffer mode: DTF is set once the byte written last has gone out; wait for
+     * it after writing and clear it.
+     */
+    fn wait_tx_complete(&self, is_fifo_mode: bool) {
+        unsafe {
+            let uartsr = (self.base + LINFLEX_UARTSR) as *mut u32;
+            
+            if is_fifo_mode {
+                // FIFO mode - wait for DTF flag to clear
+                while read_volatile(uartsr) & UARTSR_DTF != 0 {
+                    // Wait
+                }
+            } else {
+                // Buffer mode - wait for DTF flag to set, then clear it
+                while read_volatile(uartsr) & UARTSR_DTF == 0 {
+                    // Wait
+                }
+                write_volatile(uartsr, UARTSR_DTF);  // Clear the flag in buffer mode
+            }
+        }
+    }
+    
+    /**
+     * Send a single character to UART
+     */
+    pub fn putc(&self, c: u8) {
+        unsafe {
+            let bdrl = (self.base + LINFLEX_BDRL) as *mut u32;
+            
+            // If it's a newline, send carriage return first
+            if c == b'\n' {
+                self.putc(b'\r');
+            }
+            
+            // Check if FIFO mode or buffer mode
+            let is_fifo_mode = self.tx_fifo_mode();
+            
+            if is_fifo_mode {
+                // FIFO mode - wait for room in the FIFO
+                self.wait_tx_complete(true);
+            }
+            
+            // Write character to data register
+            write_volatile(bdrl, c as u32);
+            
+            if !is_fifo_mode {
+                // Buffer mode - wait for this byte to complete
+                self.wait_tx_complete(false);
+            }
+        }
+    }
+    
+    /**
+     * Flush the transmit buffer
+     */
+    pub fn flush(&self) {
+        unsafe {
+            let uartcr = (self.base + LINFLEX_UARTCR) as *mut u32;
+            
+            // Check if FIFO mode or buffer mode
+            let is_fifo_mode = self.tx_fifo_mode();
+            
+            if is_fifo_mode {
+                // In FIFO mode, wait until TFC counter is zero
+                while (read_volatile(uartcr) & UARTCR_TFC) != 0 {
+                    // Wait
+                }
+            } else {
+                // In buffer mode, putc already waited for each byte (and consumed
+                // its DTF flag), so nothing is left in flight
+            }
+        }
+    }
+    
+    /**
+     * Check if the LinFLEX holds a received byte
+     * In FIFO mode DRFRFE flags an empty RX FIFO; in buffer mode DRF and RMB
+     * together flag a completed byte
+     */
+    fn hw_have_rx_data(&self) -> bool {
+        unsafe {
+            let uartsr = (self.base + LINFLEX_UARTSR) as *const u32;
+            let sr = read_volatile(uartsr);
+            
+            if self.rx_fifo_mode() {
+                sr & UARTSR_DRFRFE == 0
+            } else {
+                sr & (UARTSR_DRFRFE | UARTSR_RMB) == (UARTSR_DRFRFE | UARTSR_RMB)
             }
-            write_volatile(uartsr, UARTSR_DTF);  // Clear the flag in buffer mode
         }
     }
+    
+    /**
+     * Read a received byte from the LinFLEX, without blocking
+     */
+    fn hw_getc(&self) -> Option<u8> {
+        if !self.hw_have_rx_data() {
+            return None;
+        }
+        
+        unsafe {
+            let bdrm = (self.base + LINFLEX_BDRM) as *const u32;
+            let uartsr = (self.base + LINFLEX_UARTSR) as *mut u32;
+            
+            let c = read_volatile(bdrm) as u8;
+            
+            if !self.rx_fifo_mode() {
+                // Buffer mode - release the buffer for the next byte
+                write_volatile(uartsr, UARTSR_DRFRFE | UARTSR_RMB);
+            }
+            
+            Some(c)
+        }
+    }
+    
+    /**
+     * Move every received byte into the ring
+     */
+    fn drain_rx(&self) {
+        let mut ring = self.rx_ring.lock();
+        while let Some(c) = self.hw_getc() {
+            if !ring.push(c) {
+                self.rx_overflow.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+    
+    /**
+     * Switch reception to the RX interrupt
+     * From then on received bytes are buffered in a ring of RX_RING_SIZE bytes
+     * and the read functions below take them from there
+     */
+    pub fn enable_rx_interrupt(&self) -> Result<(), GicError> {
+        gic::register_handler(self.irq, rx_isr)?;
+        self.rx_interrupt_mode.store(true, Ordering::Release);
+        
+        unsafe {
+            let linier = (self.base + LINFLEX_LINIER) as *mut u32;
+            write_volatile(linier, read_volatile(linier) | LINIER_DRIE);
+        }
+        gic::enable_interrupt(self.irq);
+        Ok(())
+    }
+    
+    /**
+     * Check and clear the RX overflow flag
+     * True if received bytes were dropped because the ring was full
+     */
+    pub fn take_rx_overflow(&self) -> bool {
+        self.rx_overflow.swap(false, Ordering::Relaxed)
+    }
+    
+    /**
+     * Check if a received byte is waiting
+     */
+    pub fn have_rx_data(&self) -> bool {
+        if self.rx_interrupt_mode.load(Ordering::Acquire) {
+            aarch64::with_irqs_masked(|| self.rx_ring.lock().len != 0) || self.hw_have_rx_data()
+        } else {
+            self.hw_have_rx_data()
+        }
+    }
+    
+    /**
+     * Receive a byte if one is waiting, without blocking
+     * In interrupt mode a byte still in the LinFLEX is taken once the ring is
+     * empty, so reads also make progress with IRQs masked (e.g. on the panic path)
+     */
+    pub fn getc(&self) -> Option<u8> {
+        if self.rx_interrupt_mode.load(Ordering::Acquire) {
+            aarch64::with_irqs_masked(|| self.rx_ring.lock().pop().or_else(|| self.hw_getc()))
+        } else {
+            self.hw_getc()
+        }
+    }
+    
+    /**
+     * Receive a byte, waiting for one to arrive
+     */
+    pub fn getchar(&self) -> u8 {
+        loop {
+            if let Some(c) = self.getc() {
+                return c;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/**
+ * LinFLEX RX interrupt handler, shared by all instances
+ */
+fn rx_isr() {
+    for uart in UARTS {
+        if uart.rx_interrupt_mode.load(Ordering::Acquire) {
+            uart.drain_rx();
+        }
+    }
+}
+
+/**
+ * Initialize the console LinFLEX
+ */
+pub fn init() {
+    console().init();
+}
+
+/**
+ * Change the console's baud rate and frame format (see Uart::configure)
+ */
+pub fn configure(baud: u32, data_bits: u8, parity: Parity, stop_bits: u8) -> Result<(), UartConfigError> {
+    console().configure(baud, data_bits, parity, stop_bits)
+}
+
+/**
+ * Select FIFO or buffer mode on the console (see Uart::set_fifo_mode)
+ */
+pub fn set_fifo_mode(tx: bool, rx: bool) {
+    console().set_fifo_mode(tx, rx);
+}
+
+/**
+ * Switch console reception to the RX interrupt
+ */
+pub fn enable_rx_interrupt() -> Result<(), GicError> {
+    console().enable_rx_interrupt()
+}
+
+/**
+ * Check and clear the console's RX overflow flag
+ */
+pub fn take_rx_overflow() -> bool {
+    console().take_rx_overflow()
 }
 
 /**
- * Flush the transmit buffer
+ * Send a single character to the console
+ */
+pub fn putc(c: u8) {
+    console().putc(c);
+}
+
+/**
+ * Flush the console's transmit buffer
  */
 pub fn flush() {
-    unsafe {
-        let uartcr = (UART_BASE + LINFLEX_UARTCR) as *mut u32;
-        
-        // Check if FIFO mode or buffer mode
-        let is_fifo_mode = read_volatile(uartcr) & UARTCR_TFBM;
-        
-        if is_fifo_mode != 0 {
-            // In FIFO mode, wait until TFC counter is zero
-            while (read_volatile(uartcr) & UARTCR_TFC) != 0 {
-                // Wait
+    console().flush();
+}
+
+/**
+ * Check if the console has a received byte waiting
+ */
+pub fn have_rx_data() -> bool {
+    console().have_rx_data()
+}
+
+/**
+ * Receive a byte from the console if one is waiting, without blocking
+ */
+pub fn getc() -> Option<u8> {
+    console().getc()
+}
+
+/**
+ * Receive a byte from the console, waiting for one to arrive
+ */
+pub fn getchar() -> u8 {
+    console().getchar()
+}
+
+
+/**
+ * Read a line into `buf`, echoing it back
+ * Stops at CR or LF (not stored) or when `buf` is full, and handles
+ * backspace. Talks to the LinFLEX directly, so it is usable from the panic
+ * path. Returns the number of bytes stored.
+ */
+pub fn gets(buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    
+    while len < buf.len() {
+        match getchar() {
+            b'\r' | b'\n' => break,
+            // Backspace or DEL: erase the last character on screen too
+            0x08 | 0x7F => {
+                if len > 0 {
+                    len -= 1;
+                    puts("\x08 \x08");
+                }
+            },
+            c => {
+                buf[len] = c;
+                len += 1;
+                putc(c);
             }
-        } else {
-            // In buffer mode, just ensure the last character was sent
-            uart_wait_tx_complete();
         }
     }
+    
+    puts("\n");
+    len
 }
 
 /**
- * Send a string to UART
+ * Send a string to the registered console
+ * If the console lock is held (an exception taken mid-print), the string goes
+ * straight to the LinFLEX instead of deadlocking
  */
 pub fn puts(s: &str) {
+    match CONSOLE.try_lock() {
+        Some(console) => {
+            for c in s.bytes() {
+                console.putc(c);
+            }
+            console.flush();  // Ensure the output is flushed
+        },
+        None => raw_puts(s),
+    }
+}
+
+/**
+ * Send a string straight to the console LinFLEX
+ */
+fn raw_puts(s: &str) {
+    let uart = console();
     for c in s.bytes() {
-        putc(c);
+        uart.putc(c);
     }
-    flush();  // Ensure the output is flushed
+    uart.flush();
 }
 
 /**
@@ -222,12 +772,25 @@ pub fn print_core_status(core_id: u32, status: &str) {
     puts("\n");
 }
 
-// Implement formatting traits for UART output
-struct UartWriter;
+// Implement formatting traits for console output
+struct ConsoleWriter<'a>(&'a dyn SerialOps);
 
-impl fmt::Write for UartWriter {
+impl fmt::Write for ConsoleWriter<'_> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        puts(s);
+        for c in s.bytes() {
+            self.0.putc(c);
+        }
+        Ok(())
+    }
+}
+
+// Writer straight to the LinFLEX, bypassing the console lock
+// For exception and panic paths that must not block on another printer
+pub struct RawWriter;
+
+impl fmt::Write for RawWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        raw_puts(s);
         Ok(())
     }
 }
@@ -246,9 +809,20 @@ macro_rules! println {
 }
 
 // Internal print function
+// As with puts, a held console lock (an ISR or higher-priority task cut into
+// another print, or its holder was parked) sends the text straight to the
+// LinFLEX rather than spinning forever
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
-    UartWriter.write_fmt(args).unwrap();
+    match CONSOLE.try_lock() {
+        Some(console) => {
+            let _ = ConsoleWriter(*console).write_fmt(args);
+            console.flush();
+        },
+        None => {
+            let _ = RawWriter.write_fmt(args);
+        },
+    }
 }
 
 // Format helper function that returns a String
@@ -0,0 +1,134 @@
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::arch::aarch64;
+use crate::arch::board::BOARD;
+use crate::arch::s32g3::{
+    SWT_CR, SWT_TO, SWT_SR, SWT_CR_WEN, SWT_CR_FRZ, SWT_CR_SLK, SWT_CR_HLK,
+    SWT_UNLOCK_KEY1, SWT_UNLOCK_KEY2, SWT_SERVICE_KEY1, SWT_SERVICE_KEY2, SWT_TO_MIN
+};
+
+/**
+ * Errors reported by the watchdog driver
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogError {
+    TimeoutOutOfRange,  // Timeout below SWT_TO_MIN counts or above the 32-bit counter
+    Locked,             // Hard lock set; the SWT cannot be reconfigured until reset
+}
+
+// Feed the watchdog from the idle task (see idle_service)
+static IDLE_FEED: AtomicBool = AtomicBool::new(false);
+
+// Cores whose idle task has run since the last idle feed, one bit per core
+static IDLE_SEEN: AtomicU64 = AtomicU64::new(0);
+
+fn reg(offset: usize) -> *mut u32 {
+    (BOARD.swt_base + offset) as *mut u32
+}
+
+/**
+ * Clear the soft lock so CR and TO accept writes
+ * The two keys must reach SR back to back, so IRQs are masked around them
+ */
+fn unlock() -> Result<(), WatchdogError> {
+    unsafe {
+        let cr = read_volatile(reg(SWT_CR));
+        if cr & SWT_CR_HLK != 0 {
+            return Err(WatchdogError::Locked);
+        }
+        if cr & SWT_CR_SLK != 0 {
+            aarch64::with_irqs_masked(|| {
+                write_volatile(reg(SWT_SR), SWT_UNLOCK_KEY1);
+                write_volatile(reg(SWT_SR), SWT_UNLOCK_KEY2);
+            });
+        }
+    }
+    
+    Ok(())
+}
+
+/**
+ * Start the watchdog with a timeout of `timeout_ms`
+ * Once running, the SoC resets unless feed() is called within every timeout
+ * period. The counter is frozen while a debugger halts the core.
+ */
+pub fn init(timeout_ms: u32) -> Result<(), WatchdogError> {
+    let counts = timeout_ms as u64 * BOARD.swt_clock_hz as u64 / 1000;
+    if counts < SWT_TO_MIN as u64 || counts > u32::MAX as u64 {
+        return Err(WatchdogError::TimeoutOutOfRange);
+    }
+    
+    unlock()?;
+    
+    unsafe {
+        // The timeout only loads while the watchdog is disabled
+        let cr = read_volatile(reg(SWT_CR)) & !SWT_CR_WEN;
+        write_volatile(reg(SWT_CR), cr);
+        write_volatile(reg(SWT_TO), counts as u32);
+        write_volatile(reg(SWT_CR), cr | SWT_CR_FRZ | SWT_CR_WEN);
+    }
+    
+    Ok(())
+}
+
+/**
+ * Restart the watchdog timeout
+ */
+pub fn feed() {
+    aarch64::with_irqs_masked(|| unsafe {
+        write_volatile(reg(SWT_SR), SWT_SERVICE_KEY1);
+        write_volatile(reg(SWT_SR), SWT_SERVICE_KEY2);
+    });
+}
+
+/**
+ * Stop the watchdog
+ */
+pub fn disable() -> Result<(), WatchdogError> {
+    IDLE_FEED.store(false, Ordering::Release);
+    unlock()?;
+    
+    unsafe {
+        let cr = read_volatile(reg(SWT_CR));
+        write_volatile(reg(SWT_CR), cr & !SWT_CR_WEN);
+    }
+    
+    Ok(())
+}
+
+/**
+ * Feed the watchdog from the idle tasks instead of from application code
+ * The watchdog is only fed once the idle task of every core running the
+ * scheduler has run since the last feed. An idle task only runs when every
+ * other task on its core is blocked, so a task that hangs without blocking
+ * (at any priority above idle, on any core) stops the feeding and the
+ * watchdog resets the system, which is the intended behavior. Pick a timeout
+ * longer than the longest time tasks legitimately keep a core busy.
+ */
+pub fn set_idle_feed(enabled: bool) {
+    IDLE_SEEN.store(0, Ordering::Relaxed);
+    IDLE_FEED.store(enabled, Ordering::Release);
+}
+
+/**
+ * Called by the idle task of core `core_pos` on each pass
+ * `scheduler_cores` is the mask of cores running the scheduler. Feeds the
+ * watchdog when enabled and every one of them has passed through idle.
+ */
+pub fn idle_service(core_pos: usize, scheduler_cores: u64) {
+    if !IDLE_FEED.load(Ordering::Acquire) {
+        return;
+    }
+    
+    let bit = 1u64 << core_pos;
+    let seen = IDLE_SEEN.fetch_or(bit, Ordering::AcqRel) | bit;
+    if seen & scheduler_cores != scheduler_cores {
+        return;
+    }
+    
+    // Start the next round. If this fails, another core checked in after us,
+    // also saw the round complete, and resets and feeds instead.
+    if IDLE_SEEN.compare_exchange(seen, 0, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+        feed();
+    }
+}
@@ -0,0 +1,190 @@
+use core::cell::UnsafeCell;
+use crate::freertos::{enter_critical_section, exit_critical_section, port, with_critical_from_isr};
+use crate::freertos::tasks::{self, TaskHandle};
+use crate::freertos::wait_list::{remaining_wait, POLL_INTERVAL_US};
+use alloc::vec::Vec;
+
+// Bits an event group can hold; the top byte is reserved, as in FreeRTOS
+pub const EVENT_BITS_MASK: u32 = 0x00FF_FFFF;
+
+// A task blocked in wait_bits and the condition it waits for
+struct Waiter {
+    task: TaskHandle,
+    bits: u32,
+    wait_all: bool,
+    clear_on_exit: bool,
+    result: Option<u32>,    // Group value that satisfied the wait, set on wake
+}
+
+// Set of event flags that tasks can wait on in combination
+// Unlike a WaitList, every waiter has its own condition, so setting bits
+// releases all waiters it satisfies at once. Bits to clear on exit are only
+// cleared after all of them were checked, so two tasks waiting for the same
+// bit both see it.
+pub struct EventGroup {
+    bits: UnsafeCell<u32>,
+    waiters: UnsafeCell<Vec<Waiter>>,
+}
+
+unsafe impl Sync for EventGroup {}
+
+fn satisfied(value: u32, bits: u32, wait_all: bool) -> bool {
+    if wait_all {
+        value & bits == bits
+    } else {
+        value & bits != 0
+    }
+}
+
+impl EventGroup {
+    // Create a group with all bits clear
+    pub const fn new() -> Self {
+        EventGroup {
+            bits: UnsafeCell::new(0),
+            waiters: UnsafeCell::new(Vec::new()),
+        }
+    }
+    
+    // Current value of the group
+    pub fn get_bits(&self) -> u32 {
+        enter_critical_section();
+        let value = unsafe { *self.bits.get() };
+        exit_critical_section();
+        value
+    }
+    
+    // Set `bits`, waking every task whose wait they satisfy
+    // Returns the group value once the woken tasks' exit clears were applied
+    pub fn set_bits(&self, bits: u32) -> u32 {
+        enter_critical_section();
+        let (value, _) = unsafe { self.set_locked(bits) };
+        exit_critical_section();
+        value
+    }
+    
+    // Set bits from an interrupt handler; never re-enables interrupts
+    // Sets `higher_priority_task_woken` if a woken task outranks the
+    // interrupted one; pass it to port::yield_from_isr
+    pub fn set_bits_from_isr(&self, bits: u32, higher_priority_task_woken: &mut bool) -> u32 {
+        debug_assert!(port::is_inside_isr(), "set_bits_from_isr called outside an ISR");
+        
+        with_critical_from_isr(|| {
+            let (value, woken) = unsafe { self.set_locked(bits) };
+            if woken {
+                *higher_priority_task_woken = true;
+            }
+            value
+        })
+    }
+    
+    // Clear `bits`, returning the group value from before the clear
+    pub fn clear_bits(&self, bits: u32) -> u32 {
+        enter_critical_section();
+        let value = unsafe {
+            let value = &mut *self.bits.get();
+            let before = *value;
+            *value &= !bits;
+            before
+        };
+        exit_critical_section();
+        value
+    }
+    
+    // Block until any (or with `wait_all`, every) bit in `bits` is set, for at
+    // most `timeout` ticks (None waits forever)
+    // Returns the group value that satisfied the wait, before `clear_on_exit`
+    // cleared `bits`, or the current value on timeout; check the result
+    // against `bits` to tell the two apart.
+    pub fn wait_bits(&self, bits: u32, wait_all: bool, clear_on_exit: bool, timeout: Option<u64>) -> u32 {
+        let bits = bits & EVENT_BITS_MASK;
+        debug_assert!(bits != 0, "wait_bits called with no bits");
+        let start_tick = tasks::get_tick_count();
+        
+        loop {
+            enter_critical_section();
+            
+            let value = unsafe { *self.bits.get() };
+            if satisfied(value, bits, wait_all) {
+                if clear_on_exit {
+                    unsafe { *self.bits.get() &= !bits };
+                }
+                exit_critical_section();
+                return value;
+            }
+            
+            let remaining = match remaining_wait(start_tick, timeout) {
+                Some(remaining) => remaining,
+                None => {
+                    exit_critical_section();
+                    return value;
+                }
+            };
+            
+            let blocked = tasks::mark_current_blocked(remaining);
+            if blocked {
+                unsafe {
+                    (*self.waiters.get()).push(Waiter {
+                        task: tasks::get_current_task(),
+                        bits,
+                        wait_all,
+                        clear_on_exit,
+                        result: None,
+                    });
+                }
+            }
+            exit_critical_section();
+            
+            if !blocked {
+                // Not called from a task: poll
+                crate::arch::wait_for_event_timeout(POLL_INTERVAL_US, || false);
+                continue;
+            }
+            
+            tasks::wait_while_blocked();
+            
+            // Leave the list whether woken or timed out; set_bits has already
+            // applied the exit clear if the wait was satisfied
+            let me = tasks::get_current_task();
+            enter_critical_section();
+            let result = unsafe {
+                let waiters = &mut *self.waiters.get();
+                waiters.iter().position(|w| w.task == me)
+                    .and_then(|i| waiters.remove(i).result)
+            };
+            exit_critical_section();
+            
+            if let Some(value) = result {
+                return value;
+            }
+        }
+    }
+    
+    // Set bits and release the waiters they satisfy
+    // Returns the new value and whether a woken task outranks the running one.
+    // Must be called with interrupts masked
+    unsafe fn set_locked(&self, bits: u32) -> (u32, bool) {
+        let value = &mut *self.bits.get();
+        *value |= bits & EVENT_BITS_MASK;
+        
+        let mut clear = 0;
+        let mut woken = false;
+        (*self.waiters.get()).retain_mut(|w| {
+            if w.result.is_some() || !satisfied(*value, w.bits, w.wait_all) {
+                return true;
+            }
+            // A waiter that is no longer blocked (timed out, deleted) is dropped
+            if !tasks::wake_task_locked(w.task) {
+                return false;
+            }
+            w.result = Some(*value);
+            if w.clear_on_exit {
+                clear |= w.bits;
+            }
+            woken |= tasks::outranks_running(w.task);
+            true
+        });
+        
+        *value &= !clear;
+        (*value, woken)
+    }
+}
@@ -0,0 +1,101 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::freertos::{enter_critical_section, exit_critical_section, port, with_critical_from_isr};
+use crate::freertos::tasks;
+use crate::freertos::wait_list::{remaining_wait, WaitList, POLL_INTERVAL_US};
+
+// Countdown latch for fan-out/fan-in coordination
+// A master task creates the latch with the number of workers, each worker
+// calls count_down() when it finishes and the master blocks in wait()
+pub struct CountdownLatch {
+    count: AtomicUsize,
+    waiters: WaitList,      // Tasks blocked until the count reaches zero
+}
+
+impl CountdownLatch {
+    // Create a new latch that opens after `count` calls to count_down()
+    pub const fn new(count: usize) -> Self {
+        CountdownLatch {
+            count: AtomicUsize::new(count),
+            waiters: WaitList::new(),
+        }
+    }
+
+    // Decrement the count, saturating at zero, and wake every waiter once
+    // the latch opens
+    pub fn count_down(&self) {
+        enter_critical_section();
+        unsafe { self.count_down_locked() };
+        exit_critical_section();
+    }
+
+    // Count down from an interrupt handler; never re-enables interrupts
+    // Sets `higher_priority_task_woken` if a woken waiter outranks the
+    // interrupted task; pass it to port::yield_from_isr
+    pub fn count_down_from_isr(&self, higher_priority_task_woken: &mut bool) {
+        debug_assert!(port::is_inside_isr(), "count_down_from_isr called outside an ISR");
+
+        with_critical_from_isr(|| {
+            if unsafe { self.count_down_locked() } {
+                *higher_priority_task_woken = true;
+            }
+        })
+    }
+
+    // Returns true if a woken waiter outranks the running task
+    // Must be called with interrupts masked
+    unsafe fn count_down_locked(&self) -> bool {
+        let previous = self.count.fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+            count.checked_sub(1)
+        });
+        if previous != Ok(1) {
+            return false;
+        }
+
+        let mut outranks = false;
+        while let Some(woken) = self.waiters.wake_one() {
+            outranks |= tasks::outranks_running(woken);
+        }
+        outranks
+    }
+
+    // Get the remaining count
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    // Check if the latch has opened
+    pub fn is_open(&self) -> bool {
+        self.count() == 0
+    }
+
+    // Block until the count reaches zero
+    // Returns false if `max_wait` ticks elapsed before the latch opened
+    pub fn wait(&self, max_wait: Option<u64>) -> bool {
+        let start_tick = tasks::get_tick_count();
+
+        loop {
+            enter_critical_section();
+
+            if self.is_open() {
+                exit_critical_section();
+                return true;
+            }
+
+            let blocked = match remaining_wait(start_tick, max_wait) {
+                Some(remaining) => unsafe { self.waiters.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return false;
+                }
+            };
+            exit_critical_section();
+
+            if blocked {
+                self.waiters.wait(true);
+            } else {
+                // Not called from a task: poll
+                crate::arch::wait_for_event_timeout(POLL_INTERVAL_US, || self.is_open());
+            }
+        }
+    }
+}
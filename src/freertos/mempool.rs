@@ -0,0 +1,131 @@
+use core::cell::UnsafeCell;
+use core::ptr;
+use crate::freertos::{enter_critical, exit_critical};
+#[cfg(debug_assertions)]
+use alloc::vec::Vec;
+
+// Fixed-size block pool with O(1) alloc and free
+// Free blocks are chained through their first word, so the pool needs no
+// memory beyond its backing buffer (debug builds add one bit per block to
+// catch double and foreign frees). Safe to use from ISRs and from any core:
+// the free list is only touched inside a kernel critical section.
+pub struct Pool {
+    start: usize,
+    block_size: usize,
+    block_count: usize,
+    free_head: UnsafeCell<*mut u8>,     // First free block, null when exhausted
+    free_count: UnsafeCell<usize>,
+    #[cfg(debug_assertions)]
+    allocated: UnsafeCell<Vec<u64>>,    // One bit per block, set while handed out
+}
+
+unsafe impl Sync for Pool {}
+unsafe impl Send for Pool {}
+
+impl Pool {
+    // Carve `block_count` blocks of `block_size` bytes out of `backing`
+    // Blocks are rounded up to a multiple of 8 bytes (and at least one
+    // pointer) and aligned to 8; panics if `backing` cannot hold them all
+    pub fn new(block_size: usize, block_count: usize, backing: &'static mut [u8]) -> Self {
+        let block_size = block_size.max(core::mem::size_of::<usize>()).next_multiple_of(8);
+        
+        let base = backing.as_mut_ptr() as usize;
+        let start = base.next_multiple_of(8);
+        let needed = block_size.checked_mul(block_count).expect("pool size overflows");
+        assert!(start - base + needed <= backing.len(),
+                "pool of {} x {} bytes does not fit in {} bytes", block_count, block_size, backing.len());
+        
+        // Chain every block onto the free list, lowest address first
+        let mut head = ptr::null_mut();
+        for i in (0..block_count).rev() {
+            let block = (start + i * block_size) as *mut u8;
+            unsafe { (block as *mut *mut u8).write(head) };
+            head = block;
+        }
+        
+        Pool {
+            start,
+            block_size,
+            block_count,
+            free_head: UnsafeCell::new(head),
+            free_count: UnsafeCell::new(block_count),
+            #[cfg(debug_assertions)]
+            allocated: UnsafeCell::new(alloc::vec![0; block_count.div_ceil(64)]),
+        }
+    }
+    
+    // Take a block, None if the pool is exhausted
+    pub fn alloc(&self) -> Option<*mut u8> {
+        let token = enter_critical();
+        let block = unsafe {
+            let head = *self.free_head.get();
+            if !head.is_null() {
+                *self.free_head.get() = (head as *mut *mut u8).read();
+                *self.free_count.get() -= 1;
+                
+                #[cfg(debug_assertions)]
+                self.mark(head, true);
+            }
+            head
+        };
+        exit_critical(token);
+        
+        (!block.is_null()).then_some(block)
+    }
+    
+    // Return a block obtained from alloc
+    // Debug builds panic on a pointer that is not a block of this pool or
+    // that is already free
+    pub fn free(&self, block: *mut u8) {
+        #[cfg(debug_assertions)]
+        assert!(self.index_of(block).is_some(), "pool: freeing foreign pointer {:p}", block);
+        
+        let token = enter_critical();
+        unsafe {
+            #[cfg(debug_assertions)]
+            self.mark(block, false);
+            
+            (block as *mut *mut u8).write(*self.free_head.get());
+            *self.free_head.get() = block;
+            *self.free_count.get() += 1;
+        }
+        exit_critical(token);
+    }
+    
+    // Size of each block after rounding
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+    
+    // Number of blocks currently free
+    pub fn available(&self) -> usize {
+        let token = enter_critical();
+        let count = unsafe { *self.free_count.get() };
+        exit_critical(token);
+        count
+    }
+    
+    // Index of the block starting at `block`, None if it is not one of ours
+    #[cfg(debug_assertions)]
+    fn index_of(&self, block: *mut u8) -> Option<usize> {
+        let offset = (block as usize).checked_sub(self.start)?;
+        let index = offset / self.block_size;
+        (offset % self.block_size == 0 && index < self.block_count).then_some(index)
+    }
+    
+    // Record a block as handed out or returned, catching double frees
+    // Must be called inside a critical section
+    #[cfg(debug_assertions)]
+    unsafe fn mark(&self, block: *mut u8, allocated: bool) {
+        let index = self.index_of(block).expect("pool: block outside pool");
+        let bits = &mut *self.allocated.get();
+        let (word, bit) = (index / 64, 1u64 << (index % 64));
+        
+        if allocated {
+            bits[word] |= bit;
+        } else {
+            assert!(bits[word] & bit != 0, "pool: double free of {:p}", block);
+            bits[word] &= !bit;
+        }
+    }
+}
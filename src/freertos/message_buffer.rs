@@ -0,0 +1,180 @@
+use crate::freertos::{enter_critical_section, exit_critical_section, port, with_critical_from_isr};
+use crate::freertos::stream_buffer::StreamBuffer;
+use crate::freertos::tasks;
+use crate::freertos::wait_list::{remaining_wait, WaitList};
+
+// Bytes of the length prefix stored ahead of each message
+const LENGTH_BYTES: usize = core::mem::size_of::<u32>();
+
+// Variable-length messages over a stream buffer
+// Each message is stored as a length prefix followed by its bytes, and is
+// always sent and received whole: a send that does not fit writes nothing.
+pub struct MessageBuffer {
+    stream: StreamBuffer,
+    senders: WaitList,      // Tasks blocked until a message fits
+    receivers: WaitList,    // Tasks blocked on an empty buffer
+}
+
+impl MessageBuffer {
+    // Create a buffer of `capacity` bytes, including the length prefixes
+    pub fn new(capacity: usize) -> Self {
+        MessageBuffer {
+            stream: StreamBuffer::new(capacity, 1),
+            senders: WaitList::new(),
+            receivers: WaitList::new(),
+        }
+    }
+    
+    // Largest message the buffer can ever hold
+    pub fn max_message_len(&self) -> usize {
+        self.stream.capacity().saturating_sub(LENGTH_BYTES)
+    }
+    
+    // Length of the next message to be received, if any
+    pub fn next_message_len(&self) -> Option<usize> {
+        enter_critical_section();
+        let len = unsafe { self.next_len_locked() };
+        exit_critical_section();
+        len
+    }
+    
+    // Send `msg` as one message, blocking until it fits for at most `max_wait`
+    // ticks (None waits forever)
+    // Returns false on timeout, or at once for a message longer than
+    // max_message_len()
+    pub fn send(&self, msg: &[u8], max_wait: Option<u64>) -> bool {
+        if msg.len() > self.max_message_len() {
+            return false;
+        }
+        let start_tick = tasks::get_tick_count();
+        
+        loop {
+            enter_critical_section();
+            
+            if unsafe { self.write_locked(msg) } {
+                // Hand the message to a waiting receiver
+                unsafe {
+                    self.receivers.wake_one();
+                }
+                exit_critical_section();
+                return true;
+            }
+            
+            // No room: block on the sender list
+            let blocked = match remaining_wait(start_tick, max_wait) {
+                Some(remaining) => unsafe { self.senders.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return false;
+                }
+            };
+            exit_critical_section();
+            
+            self.senders.wait(blocked);
+        }
+    }
+    
+    // Receive the next message into `buf`, blocking while the buffer is empty
+    // for at most `max_wait` ticks (None waits forever)
+    // Returns the message length, or None on timeout. A message longer than
+    // `buf` is left in the buffer and None is returned; see next_message_len.
+    pub fn receive(&self, buf: &mut [u8], max_wait: Option<u64>) -> Option<usize> {
+        let start_tick = tasks::get_tick_count();
+        
+        loop {
+            enter_critical_section();
+            
+            if let Some(len) = unsafe { self.next_len_locked() } {
+                let received = unsafe { self.read_locked(buf, len) };
+                if received.is_some() {
+                    // Space is free for a waiting sender
+                    unsafe {
+                        self.senders.wake_one();
+                    }
+                }
+                exit_critical_section();
+                return received;
+            }
+            
+            // Empty: block on the receiver list
+            let blocked = match remaining_wait(start_tick, max_wait) {
+                Some(remaining) => unsafe { self.receivers.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return None;
+                }
+            };
+            exit_critical_section();
+            
+            self.receivers.wait(blocked);
+        }
+    }
+    
+    // Send from an interrupt handler; never blocks or re-enables interrupts
+    // Returns false if the message does not fit. Sets
+    // `higher_priority_task_woken` if a receiver that outranks the interrupted
+    // task was woken; pass it to port::yield_from_isr.
+    pub fn send_from_isr(&self, msg: &[u8], higher_priority_task_woken: &mut bool) -> bool {
+        debug_assert!(port::is_inside_isr(), "send_from_isr called outside an ISR");
+        
+        with_critical_from_isr(|| {
+            if msg.len() > self.max_message_len() || !unsafe { self.write_locked(msg) } {
+                return false;
+            }
+            let woken = unsafe { self.receivers.wake_one() };
+            if woken.is_some_and(tasks::outranks_running) {
+                *higher_priority_task_woken = true;
+            }
+            true
+        })
+    }
+    
+    // Receive from an interrupt handler; never blocks
+    // Returns None if empty or the message is longer than `buf`. Sets
+    // `higher_priority_task_woken` as for send_from_isr.
+    pub fn receive_from_isr(&self, buf: &mut [u8], higher_priority_task_woken: &mut bool) -> Option<usize> {
+        debug_assert!(port::is_inside_isr(), "receive_from_isr called outside an ISR");
+        
+        with_critical_from_isr(|| {
+            let len = unsafe { self.next_len_locked()? };
+            let received = unsafe { self.read_locked(buf, len)? };
+            let woken = unsafe { self.senders.wake_one() };
+            if woken.is_some_and(tasks::outranks_running) {
+                *higher_priority_task_woken = true;
+            }
+            Some(received)
+        })
+    }
+    
+    // Length prefix of the message at the front, if any
+    // Must be called with interrupts masked
+    unsafe fn next_len_locked(&self) -> Option<usize> {
+        let mut prefix = [0u8; LENGTH_BYTES];
+        if self.stream.peek_locked(&mut prefix) < LENGTH_BYTES {
+            return None;
+        }
+        Some(u32::from_le_bytes(prefix) as usize)
+    }
+    
+    // Write the prefix and message if both fit, otherwise nothing
+    // Must be called with interrupts masked
+    unsafe fn write_locked(&self, msg: &[u8]) -> bool {
+        if self.stream.space_available() < LENGTH_BYTES + msg.len() {
+            return false;
+        }
+        self.stream.write_locked(&(msg.len() as u32).to_le_bytes());
+        self.stream.write_locked(msg);
+        true
+    }
+    
+    // Remove the front message of length `len` into `buf`, if it fits
+    // Must be called with interrupts masked
+    unsafe fn read_locked(&self, buf: &mut [u8], len: usize) -> Option<usize> {
+        if len > buf.len() {
+            return None;
+        }
+        self.stream.read_locked(&mut [0u8; LENGTH_BYTES]);
+        self.stream.read_locked(&mut buf[..len]);
+        Some(len)
+    }
+}
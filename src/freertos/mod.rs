@@ -1,8 +1,20 @@
 pub mod port;
 pub mod tasks;
 pub mod queue;
+pub mod latch;
+pub mod event_groups;
+pub mod mempool;
+pub mod message_buffer;
+pub mod mutex;
+pub mod semaphore;
+pub mod stream_buffer;
+pub mod timers;
+pub mod wait_list;
 
+use core::sync::atomic::Ordering;
 use crate::arch;
+#[cfg(feature = "smp")]
+use crate::arch::sync::RawSpinLock;
 
 // Initialize the FreeRTOS system
 pub fn init() {
@@ -12,17 +24,95 @@ pub fn init() {
     queue::init();
 }
 
+// Serializes the task list and other kernel state between cores
+// Held for the outermost critical section, for ISR dispatch and for the
+// context switch, which between them cover every access to that state.
+#[cfg(feature = "smp")]
+pub static KERNEL_LOCK: RawSpinLock = RawSpinLock::new();
+
 // Critical section management
+// Sections nest: IRQs are masked on entry, and the IRQ state from before the
+// outermost entry is only restored when the outermost section exits. The
+// depth is per core. A task must not block or yield inside a section: in SMP
+// builds the outermost one holds KERNEL_LOCK, which the context switch takes
+// again (port::yield_task asserts this).
 pub fn enter_critical_section() {
-    arch::disable_interrupts();
+    let daif = arch::aarch64::save_and_disable_irqs();
+    let cpu = arch::percpu::this_cpu();
+    if cpu.critical_nesting.fetch_add(1, Ordering::Relaxed) == 0 {
+        cpu.critical_daif.store(daif, Ordering::Relaxed);
+        #[cfg(feature = "smp")]
+        KERNEL_LOCK.lock();
+    }
 }
 
 pub fn exit_critical_section() {
-    arch::enable_interrupts();
+    let cpu = arch::percpu::this_cpu();
+    let nesting = cpu.critical_nesting.load(Ordering::Relaxed);
+    debug_assert!(nesting > 0, "exit_critical_section without a matching enter");
+    if nesting == 0 {
+        return;
+    }
+    
+    cpu.critical_nesting.store(nesting - 1, Ordering::Relaxed);
+    if nesting == 1 {
+        #[cfg(feature = "smp")]
+        KERNEL_LOCK.unlock();
+        arch::aarch64::restore_irqs(cpu.critical_daif.load(Ordering::Relaxed));
+    }
+}
+
+// Proof of an entered critical section, to be handed back to exit_critical
+// Holds the depth the section was entered at.
+#[must_use]
+pub struct CriticalToken(u32);
+
+// Token-based critical section
+// The same section as enter_critical_section (IRQs masked, KERNEL_LOCK held
+// by the outermost one); the token makes a missing exit a compile warning.
+// Tokens must be handed back in reverse order, which debug builds check.
+pub fn enter_critical() -> CriticalToken {
+    enter_critical_section();
+    CriticalToken(arch::percpu::this_cpu().critical_nesting.load(Ordering::Relaxed))
+}
+
+pub fn exit_critical(token: CriticalToken) {
+    debug_assert_eq!(arch::percpu::this_cpu().critical_nesting.load(Ordering::Relaxed), token.0,
+                     "exit_critical out of order");
+    exit_critical_section();
+}
+
+// Priority-based critical section, usable from tasks and ISRs
+// Raises the GIC priority mask to port::MAX_SYSCALL_INTERRUPT_PRIORITY instead
+// of masking IRQs in DAIF, so more urgent interrupts are still taken. Returns
+// the previous mask, which must be passed to exit_critical_from_isr.
+pub fn enter_critical_from_isr() -> u8 {
+    let saved = arch::gic::priority_mask();
+    let threshold = (port::MAX_SYSCALL_INTERRUPT_PRIORITY & 0x0F) << 4;
+    
+    // Only ever raise the mask; a nested section must not unmask
+    if threshold < saved {
+        arch::gic::set_priority_mask(threshold);
+    }
+    saved
+}
+
+pub fn exit_critical_from_isr(saved: u8) {
+    arch::gic::set_priority_mask(saved);
+}
+
+// Run `f` inside a priority-based critical section
+// Used by the *_from_isr APIs, whose handler may be preempted by a more
+// urgent one (see exception_handler_irq)
+pub fn with_critical_from_isr<R>(f: impl FnOnce() -> R) -> R {
+    let saved = enter_critical_from_isr();
+    let result = f();
+    exit_critical_from_isr(saved);
+    result
 }
 
 // FreeRTOS system tick handler
-// Would be called by timer interrupt
+// Called from the STM0 tick interrupt (see port::init)
 pub fn tick_handler() {
     let inside_isr = port::is_inside_isr();
     
@@ -31,4 +121,6 @@ pub fn tick_handler() {
     } else {
         tasks::increment_tick();
     }
+    
+    timers::process(tasks::get_tick_count());
 }
\ No newline at end of file
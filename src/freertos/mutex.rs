@@ -0,0 +1,141 @@
+use core::cell::UnsafeCell;
+use crate::freertos::{enter_critical_section, exit_critical_section};
+use crate::freertos::tasks::{self, TaskHandle};
+use crate::freertos::wait_list::{remaining_wait, WaitList};
+
+// Errors returned by Mutex::lock and Mutex::unlock
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MutexError {
+    NotOwner,       // The calling task does not hold the mutex
+    AlreadyOwned,   // The calling task already holds it (the mutex is not recursive)
+    Timeout,        // The mutex was not released within the timeout
+}
+
+// Mutex with priority inheritance
+// While higher-priority tasks are blocked on the mutex, the owner runs at the
+// highest of their priorities, so a medium-priority task cannot starve them
+// all. The boost follows the waiters as they come and go (time out) and is
+// dropped on unlock, except for what other mutexes the owner holds still lend
+// it. Ownership is handed directly to the woken waiter.
+// The mutex must not move while it is held.
+pub struct Mutex {
+    owner: UnsafeCell<Option<TaskHandle>>,
+    waiters: WaitList,
+}
+
+unsafe impl Sync for Mutex {}
+
+impl Mutex {
+    // Create an unlocked mutex
+    pub const fn new() -> Self {
+        Mutex {
+            owner: UnsafeCell::new(None),
+            waiters: WaitList::new(),
+        }
+    }
+    
+    // Take the mutex if it is free, without blocking
+    pub fn try_lock(&self) -> bool {
+        enter_critical_section();
+        let locked = unsafe { self.try_lock_locked() };
+        exit_critical_section();
+        locked
+    }
+    
+    // Must be called inside a critical section
+    unsafe fn try_lock_locked(&self) -> bool {
+        let owner = &mut *self.owner.get();
+        if owner.is_none() {
+            let me = tasks::get_current_task();
+            *owner = Some(me);
+            tasks::add_held_lock(me, &self.waiters);
+            true
+        } else {
+            false
+        }
+    }
+    
+    // Take the mutex, blocking for at most `timeout` ticks (None waits forever)
+    pub fn lock(&self, timeout: Option<u64>) -> Result<(), MutexError> {
+        let me = tasks::get_current_task();
+        let start_tick = tasks::get_tick_count();
+        
+        loop {
+            enter_critical_section();
+            
+            if unsafe { self.try_lock_locked() } {
+                exit_critical_section();
+                return Ok(());
+            }
+            
+            // Waiting for ourselves would never end
+            let owner = unsafe { *self.owner.get() };
+            if owner == Some(me) {
+                exit_critical_section();
+                return Err(MutexError::AlreadyOwned);
+            }
+            
+            // Wait for the hand-off, boosting the owner to our priority
+            let blocked = match remaining_wait(start_tick, timeout) {
+                Some(remaining) => unsafe { self.waiters.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return Err(MutexError::Timeout);
+                }
+            };
+            if let Some(owner) = owner {
+                tasks::update_inherited_priority(owner);
+            }
+            exit_critical_section();
+            
+            self.waiters.wait(blocked);
+            
+            enter_critical_section();
+            let owner = unsafe { *self.owner.get() };
+            if let Some(owner) = owner.filter(|&owner| owner != me) {
+                // We left the list without the mutex: take back what we lent
+                tasks::update_inherited_priority(owner);
+            }
+            exit_critical_section();
+            
+            if owner == Some(me) {
+                return Ok(());
+            }
+        }
+    }
+    
+    // Release the mutex, handing it to the highest-priority waiter
+    pub fn unlock(&self) -> Result<(), MutexError> {
+        let me = tasks::get_current_task();
+        
+        enter_critical_section();
+        let result = unsafe {
+            let owner = &mut *self.owner.get();
+            if *owner != Some(me) {
+                Err(MutexError::NotOwner)
+            } else {
+                tasks::remove_held_lock(me, &self.waiters);
+                *owner = self.waiters.wake_one();
+                
+                // The new owner inherits from whoever is still waiting
+                if let Some(new_owner) = *owner {
+                    tasks::add_held_lock(new_owner, &self.waiters);
+                    tasks::update_inherited_priority(new_owner);
+                }
+                tasks::update_inherited_priority(me);
+                Ok(())
+            }
+        };
+        exit_critical_section();
+        
+        result
+    }
+    
+    // Task currently holding the mutex
+    pub fn owner(&self) -> Option<TaskHandle> {
+        enter_critical_section();
+        let owner = unsafe { *self.owner.get() };
+        exit_critical_section();
+        owner
+    }
+}
@@ -1,43 +1,235 @@
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::Ordering;
 use crate::arch;
+use crate::arch::exceptions::{TrapFrame, TRAP_FRAME_SIZE};
+use crate::freertos::tasks;
 
-// Track if we're inside an ISR context
-static IN_ISR: AtomicBool = AtomicBool::new(false);
+// Interrupts at this priority (as passed to set_interrupt_priority, 0-15) or
+// a lower priority are masked by priority-based critical sections; more urgent
+// interrupts keep firing. Those must not call into the kernel.
+pub const MAX_SYSCALL_INTERRUPT_PRIORITY: u8 = 4;
+
+// Iterations between reschedule checks in maybe_yield!()
+pub const YIELD_CHECK_INTERVAL: usize = 64;
+
+// Priority of the tick interrupt: the least urgent, so it never delays
+// device interrupts and is always masked by critical sections
+pub const TICK_INTERRUPT_PRIORITY: u8 = 15;
 
 // Initialize the port-specific features
+// Takes over the STM0 tick interrupt so each tick drives the scheduler
 pub fn init() {
-    // Set up timer for system ticks (simplified for this example)
-    // In a real implementation, would configure a hardware timer
+    if let Err(err) = arch::gic::register_handler(arch::s32g3::S32G_STM0_IRQ, tick_isr) {
+        panic!("Failed to register the tick handler: {:?}", err);
+    }
+    arch::set_interrupt_priority(arch::s32g3::S32G_STM0_IRQ, TICK_INTERRUPT_PRIORITY);
+    arch::enable_interrupt(arch::s32g3::S32G_STM0_IRQ);
+    
+    if let Err(err) = arch::gic::register_handler(arch::gic::SGI_RESCHEDULE, reschedule_isr) {
+        panic!("Failed to register the reschedule handler: {:?}", err);
+    }
+}
+
+// Per-core setup for a core about to run tasks
+// SGIs are banked per core, so each core enables its own reschedule SGI
+pub fn init_core() {
+    arch::set_interrupt_priority(arch::gic::SGI_RESCHEDULE, TICK_INTERRUPT_PRIORITY);
+    arch::enable_interrupt(arch::gic::SGI_RESCHEDULE);
+}
+
+// Reschedule SGI handler
+// The sender already set this core's need_resched; taking the interrupt is
+// what matters, as the switch happens on the way out of it
+fn reschedule_isr() {}
+
+// STM0 interrupt handler
+// Ticks missed while interrupts were masked are replayed so delays and
+// timeouts stay in step with the timer
+fn tick_isr() {
+    for _ in 0..arch::s32g3::timer::handle_tick() {
+        super::tick_handler();
+    }
 }
 
 // Check if currently in ISR/exception context
+// The IRQ and FIQ vectors bracket every handler with enter_isr/exit_isr, so
+// the per-CPU nesting count is authoritative
 pub fn is_inside_isr() -> bool {
-    IN_ISR.load(Ordering::Relaxed)
+    arch::percpu::in_isr()
 }
 
 // Mark the start of ISR processing
+// Nests: an interrupt taken inside a handler bumps the count again. In the
+// SMP build the handler also runs inside a critical section, so the
+// *_from_isr APIs hold the kernel lock like their task-level versions.
 pub fn enter_isr() {
-    IN_ISR.store(true, Ordering::Relaxed);
+    arch::percpu::this_cpu().isr_nesting.fetch_add(1, Ordering::Relaxed);
+    #[cfg(feature = "smp")]
+    super::enter_critical_section();
 }
 
 // Mark the end of ISR processing
 pub fn exit_isr() {
-    IN_ISR.store(false, Ordering::Relaxed);
+    #[cfg(feature = "smp")]
+    super::exit_critical_section();
+    let nesting = &arch::percpu::this_cpu().isr_nesting;
+    let _ = nesting.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+}
+
+// Flag that a reschedule is needed at the next opportunity
+pub fn set_need_resched() {
+    arch::percpu::this_cpu().need_resched.store(true, Ordering::Release);
+}
+
+// Flag a reschedule on another core and interrupt it so it happens now
+pub fn set_need_resched_on(core_pos: usize) {
+    if core_pos == arch::percpu::this_cpu().core_pos {
+        set_need_resched();
+        return;
+    }
+    if let Some(cpu) = arch::percpu::cpu(core_pos) {
+        cpu.need_resched.store(true, Ordering::Release);
+        let _ = arch::gic::send_sgi_to_core(core_pos, arch::gic::SGI_RESCHEDULE);
+    }
+}
+
+// Finish an ISR that may have woken a task
+// Pass the flag filled in by the *_from_isr APIs. If set, the IRQ vector's
+// exit path (port_switch_context) switches to the woken task instead of
+// returning to the interrupted one, so it runs as soon as the ISR returns.
+pub fn yield_from_isr(higher_priority_task_woken: bool) {
+    if higher_priority_task_woken {
+        set_need_resched();
+    }
+}
+
+// Check if a reschedule has been requested
+pub fn need_resched() -> bool {
+    arch::percpu::this_cpu().need_resched.load(Ordering::Acquire)
+}
+
+// Consume a pending reschedule request and yield
+pub fn request_reschedule() {
+    arch::percpu::this_cpu().need_resched.store(false, Ordering::Release);
+    yield_task();
+}
+
+// Cooperatively yield from a long-running loop
+// Every N iterations (default YIELD_CHECK_INTERVAL) of the call site, yields if
+// a higher-priority task became ready and the caller may yield (see
+// can_yield); otherwise the loop just carries on
+#[macro_export]
+macro_rules! maybe_yield {
+    () => {
+        $crate::maybe_yield!($crate::freertos::port::YIELD_CHECK_INTERVAL)
+    };
+    ($every:expr) => {{
+        static ITERATIONS: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+        let count = ITERATIONS.fetch_add(1, core::sync::atomic::Ordering::Relaxed) + 1;
+        if $crate::freertos::port::is_every(count, $every)
+            && $crate::freertos::port::need_resched()
+            && $crate::freertos::port::can_yield()
+        {
+            $crate::freertos::port::request_reschedule();
+        }
+    }};
+}
+
+// Check if the caller may yield: a task with IRQs unmasked, so not an ISR and
+// not inside a critical section or spinlock; used by maybe_yield
+pub fn can_yield() -> bool {
+    tasks::is_scheduler_running() && !is_inside_isr() && !arch::aarch64::irqs_masked()
+}
+
+// Check if `count` is a multiple of `every` (always true for an interval
+// of 0 or 1); used by maybe_yield
+#[inline(always)]
+pub fn is_every(count: usize, every: usize) -> bool {
+    every <= 1 || count.is_multiple_of(every)
 }
 
 // Yield processor - trigger a context switch
+// Before the scheduler runs there is nothing to switch to, so just wait
 pub fn yield_task() {
-    // In a real implementation, would trigger SVC exception
-    // For our minimal port, we'll simulate time slicing
-    arch::wait_for_interrupt();
+    if !tasks::is_scheduler_running() {
+        arch::wait_for_interrupt();
+        return;
+    }
+    
+    set_need_resched();
+    
+    // From an interrupt handler the switch happens on the way out of the IRQ
+    if arch::aarch64::is_in_irq() {
+        return;
+    }
+    
+    // The switch takes KERNEL_LOCK, which an open section already holds
+    assert!(arch::percpu::this_cpu().critical_nesting.load(Ordering::Relaxed) == 0,
+            "yield_task called inside a critical section");
+    unsafe {
+        core::arch::asm!("svc #0", options(nostack));
+    }
 }
 
-// Start the first task
-pub fn start_first_task(sp: *const usize) {
+// SPSR for a new task: EL1h with all exceptions unmasked
+const TASK_INITIAL_SPSR: u64 = 0x5;
+
+// Build the initial frame of a new task at the top of its stack
+// The first restore of this frame "returns" into `entry` with `arg` in x0 and
+// sp at the top of the stack. Returns the frame address for TCB.stack_pointer.
+pub fn init_task_stack(stack_top: *mut u8, entry: extern "C" fn(usize) -> !, arg: usize) -> *mut usize {
+    let top = (stack_top as usize) & !0xF;
+    let frame = (top - TRAP_FRAME_SIZE) as *mut TrapFrame;
+    
     unsafe {
-        // In a real implementation, would set up the stack and jump to the task
-        // For our minimal port, we'll just call the task function directly
-        let task_fn: fn() = core::mem::transmute(sp);
-        task_fn();
+        frame.write(TrapFrame {
+            regs: [0; 31],
+            sp_el0: 0,
+            elr: entry as u64,
+            spsr: TASK_INITIAL_SPSR,
+        });
+        (*frame).regs[0] = arg as u64;
+    }
+    
+    frame as *mut usize
+}
+
+// Start the first task by restoring its initial frame; never returns
+pub fn start_first_task(sp: *const usize) -> ! {
+    extern "C" {
+        fn port_restore_context(frame: *const usize) -> !;
+    }
+    unsafe { port_restore_context(sp) }
+}
+
+// Called on the way out of the EL1 IRQ and SVC vectors with the interrupted
+// task's saved frame; returns the frame to resume
+#[no_mangle]
+extern "C" fn port_switch_context(frame: *mut TrapFrame) -> *mut TrapFrame {
+    // A nested IRQ returns into the handler it preempted, not into a task;
+    // the reschedule waits for the outermost handler's exit
+    let need_resched = &arch::percpu::this_cpu().need_resched;
+    if !tasks::is_scheduler_running() || is_inside_isr() || !need_resched.swap(false, Ordering::AcqRel) {
+        return frame;
+    }
+    
+    // No critical section is held here, and switch_context swaps the
+    // per-task nesting, so the kernel lock is taken directly. It stays held
+    // until port_switch_finish: once the outgoing task is Ready another core
+    // may resume it, and this core is still running on its stack.
+    #[cfg(feature = "smp")]
+    {
+        super::KERNEL_LOCK.lock();
+        arch::percpu::this_cpu().switch_locked.store(true, Ordering::Relaxed);
+    }
+    tasks::switch_context(frame as *mut usize) as *mut TrapFrame
+}
+
+// Called by the IRQ and SVC exit paths once sp points at the frame
+// port_switch_context returned, i.e. off the previous task's stack
+#[no_mangle]
+extern "C" fn port_switch_finish() {
+    #[cfg(feature = "smp")]
+    if arch::percpu::this_cpu().switch_locked.swap(false, Ordering::Relaxed) {
+        super::KERNEL_LOCK.unlock();
     }
 }
\ No newline at end of file
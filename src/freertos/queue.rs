@@ -1,6 +1,8 @@
 use core::cell::UnsafeCell;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use crate::freertos::{enter_critical_section, exit_critical_section};
+use crate::freertos::{enter_critical_section, exit_critical_section, port, with_critical_from_isr};
+use crate::freertos::tasks;
+use crate::freertos::wait_list::{remaining_wait, WaitList};
 use alloc::vec::Vec;
 
 // Simplified queue implementation
@@ -10,6 +12,12 @@ pub struct Queue<T> {
     length: AtomicUsize,
     head: AtomicUsize,
     tail: AtomicUsize,
+    senders: WaitList,      // Tasks blocked on a full queue
+    receivers: WaitList,    // Tasks blocked on an empty queue
+    #[cfg(feature = "queue-debug")]
+    item_size: usize,
+    #[cfg(feature = "queue-debug")]
+    type_tag: &'static str,
 }
 
 unsafe impl<T: Send> Sync for Queue<T> {}
@@ -35,17 +43,22 @@ impl<T: Copy> Queue<T> {
             length: AtomicUsize::new(0),
             head: AtomicUsize::new(0),
             tail: AtomicUsize::new(0),
+            senders: WaitList::new(),
+            receivers: WaitList::new(),
+            #[cfg(feature = "queue-debug")]
+            item_size: core::mem::size_of::<T>(),
+            #[cfg(feature = "queue-debug")]
+            type_tag: core::any::type_name::<T>(),
         }
     }
     
     // Enqueue an item
+    // Blocks while the queue is full, for at most `max_wait` ticks (None waits
+    // forever); a receiver wakes the highest-priority blocked sender
     pub fn send(&self, item: T, max_wait: Option<u64>) -> bool {
-        let mut success = false;
+        let start_tick = tasks::get_tick_count();
         
-        // Simple implementation with retries
-        let start_tick = crate::freertos::tasks::get_tick_count();
-        
-        while !success {
+        loop {
             enter_critical_section();
             
             let length = self.length.load(Ordering::Relaxed);
@@ -66,36 +79,36 @@ impl<T: Copy> Queue<T> {
                 // Update length
                 self.length.fetch_add(1, Ordering::Relaxed);
                 
-                success = true;
+                // Hand the item to a waiting receiver
+                unsafe {
+                    self.receivers.wake_one();
+                }
+                
+                exit_critical_section();
+                return true;
             }
             
+            // Full: block on the sender list
+            let blocked = match remaining_wait(start_tick, max_wait) {
+                Some(remaining) => unsafe { self.senders.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return false;
+                }
+            };
             exit_critical_section();
             
-            if !success {
-                // Check if we've exceeded the timeout
-                if let Some(wait_ticks) = max_wait {
-                    let current_tick = crate::freertos::tasks::get_tick_count();
-                    if current_tick - start_tick >= wait_ticks {
-                        return false;
-                    }
-                }
-                
-                // Yield to allow other tasks to run
-                crate::arch::wait_for_interrupt();
-            }
+            self.senders.wait(blocked);
         }
-        
-        true
     }
     
     // Dequeue an item
+    // Blocks while the queue is empty, for at most `max_wait` ticks (None waits
+    // forever); a sender wakes the highest-priority blocked receiver
     pub fn receive(&self, max_wait: Option<u64>) -> Option<T> {
-        let mut item = None;
-        
-        // Simple implementation with retries
-        let start_tick = crate::freertos::tasks::get_tick_count();
+        let start_tick = tasks::get_tick_count();
         
-        while item.is_none() {
+        loop {
             enter_critical_section();
             
             let length = self.length.load(Ordering::Relaxed);
@@ -105,35 +118,94 @@ impl<T: Copy> Queue<T> {
                 let head = self.head.load(Ordering::Relaxed);
                 
                 // Get the item
-                unsafe {
+                let item = unsafe {
                     let data_ref = &*self.data.get();
-                    item = Some(data_ref[head]);
-                }
+                    data_ref[head]
+                };
                 
                 // Update head pointer
                 self.head.store((head + 1) % self.capacity, Ordering::Relaxed);
                 
                 // Update length
                 self.length.fetch_sub(1, Ordering::Relaxed);
+                
+                // A slot is free for a waiting sender
+                unsafe {
+                    self.senders.wake_one();
+                }
+                
+                exit_critical_section();
+                return Some(item);
             }
             
+            // Empty: block on the receiver list
+            let blocked = match remaining_wait(start_tick, max_wait) {
+                Some(remaining) => unsafe { self.receivers.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return None;
+                }
+            };
             exit_critical_section();
             
-            if item.is_none() {
-                // Check if we've exceeded the timeout
-                if let Some(wait_ticks) = max_wait {
-                    let current_tick = crate::freertos::tasks::get_tick_count();
-                    if current_tick - start_tick >= wait_ticks {
-                        return None;
-                    }
-                }
-                
-                // Yield to allow other tasks to run
-                crate::arch::wait_for_interrupt();
-            }
+            self.receivers.wait(blocked);
         }
+    }
+    
+    // Enqueue an item from an interrupt handler
+    // Never blocks and never re-enables interrupts. Sets
+    // `higher_priority_task_woken` if a receiver that outranks the interrupted
+    // task was woken; pass it to port::yield_from_isr to switch on ISR exit.
+    pub fn send_from_isr(&self, item: T, higher_priority_task_woken: &mut bool) -> bool {
+        debug_assert!(port::is_inside_isr(), "send_from_isr called outside an ISR");
         
-        item
+        with_critical_from_isr(|| {
+            let length = self.length.load(Ordering::Relaxed);
+            if length >= self.capacity {
+                return false;
+            }
+            
+            let tail = self.tail.load(Ordering::Relaxed);
+            unsafe {
+                let data_ref = &mut *self.data.get();
+                data_ref[tail] = item;
+            }
+            self.tail.store((tail + 1) % self.capacity, Ordering::Relaxed);
+            self.length.fetch_add(1, Ordering::Relaxed);
+            
+            let woken = unsafe { self.receivers.wake_one() };
+            if woken.is_some_and(tasks::outranks_running) {
+                *higher_priority_task_woken = true;
+            }
+            true
+        })
+    }
+    
+    // Dequeue an item from an interrupt handler
+    // Never blocks; sets `higher_priority_task_woken` as for send_from_isr
+    pub fn receive_from_isr(&self, higher_priority_task_woken: &mut bool) -> Option<T> {
+        debug_assert!(port::is_inside_isr(), "receive_from_isr called outside an ISR");
+        
+        with_critical_from_isr(|| {
+            let length = self.length.load(Ordering::Relaxed);
+            if length == 0 {
+                return None;
+            }
+            
+            let head = self.head.load(Ordering::Relaxed);
+            let item = unsafe {
+                let data_ref = &*self.data.get();
+                data_ref[head]
+            };
+            self.head.store((head + 1) % self.capacity, Ordering::Relaxed);
+            self.length.fetch_sub(1, Ordering::Relaxed);
+            
+            let woken = unsafe { self.senders.wake_one() };
+            if woken.is_some_and(tasks::outranks_running) {
+                *higher_priority_task_woken = true;
+            }
+            Some(item)
+        })
     }
     
     // Check if queue is empty
@@ -150,4 +222,47 @@ impl<T: Copy> Queue<T> {
     pub fn len(&self) -> usize {
         self.length.load(Ordering::Relaxed)
     }
-}
\ No newline at end of file
+    
+    // Size of one item, as recorded at creation
+    #[cfg(feature = "queue-debug")]
+    pub fn item_size(&self) -> usize {
+        self.item_size
+    }
+    
+    // Name of the item type, as recorded at creation
+    #[cfg(feature = "queue-debug")]
+    pub fn type_tag(&self) -> &'static str {
+        self.type_tag
+    }
+    
+    // Type-erased send for callers that only know the item as raw bytes
+    // Panics if `bytes` is not exactly one item, which means the sender and
+    // the queue's creator disagree about the element type
+    //
+    // Safety: `bytes` must hold a valid T, e.g. bytes produced by
+    // receive_bytes on a queue of the same T. The size check does not make
+    // arbitrary bytes a valid bool, enum or reference.
+    #[cfg(feature = "queue-debug")]
+    pub unsafe fn send_bytes(&self, bytes: &[u8], max_wait: Option<u64>) -> bool {
+        assert_eq!(bytes.len(), self.item_size,
+                   "queue of {} sent a {}-byte item", self.type_tag, bytes.len());
+        
+        let item = core::ptr::read_unaligned(bytes.as_ptr() as *const T);
+        self.send(item, max_wait)
+    }
+    
+    // Type-erased receive into a caller buffer of exactly one item
+    #[cfg(feature = "queue-debug")]
+    pub fn receive_bytes(&self, out: &mut [u8], max_wait: Option<u64>) -> bool {
+        assert_eq!(out.len(), self.item_size,
+                   "queue of {} received into a {}-byte buffer", self.type_tag, out.len());
+        
+        match self.receive(max_wait) {
+            Some(item) => {
+                unsafe { core::ptr::write_unaligned(out.as_mut_ptr() as *mut T, item) };
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use core::cell::UnsafeCell;
+use crate::freertos::{enter_critical_section, exit_critical_section, port, with_critical_from_isr};
+use crate::freertos::tasks;
+use crate::freertos::wait_list::{remaining_wait, WaitList};
+
+// Counting semaphore; a binary semaphore is one with max == 1
+pub struct Semaphore {
+    count: UnsafeCell<u32>,
+    max: u32,
+    takers: WaitList,       // Tasks blocked on a zero count
+}
+
+unsafe impl Sync for Semaphore {}
+
+impl Semaphore {
+    // Create a semaphore with `initial` of at most `max` units available
+    pub const fn new(initial: u32, max: u32) -> Self {
+        Semaphore {
+            count: UnsafeCell::new(if initial < max { initial } else { max }),
+            max,
+            takers: WaitList::new(),
+        }
+    }
+    
+    // Take one unit, blocking for at most `max_wait` ticks (None waits forever)
+    pub fn take(&self, max_wait: Option<u64>) -> bool {
+        let start_tick = tasks::get_tick_count();
+        
+        loop {
+            enter_critical_section();
+            
+            unsafe {
+                let count = &mut *self.count.get();
+                if *count > 0 {
+                    *count -= 1;
+                    exit_critical_section();
+                    return true;
+                }
+            }
+            
+            let blocked = match remaining_wait(start_tick, max_wait) {
+                Some(remaining) => unsafe { self.takers.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return false;
+                }
+            };
+            exit_critical_section();
+            
+            self.takers.wait(blocked);
+        }
+    }
+    
+    // Give one unit back, waking the highest-priority taker
+    // Returns false if the semaphore is already at its maximum
+    pub fn give(&self) -> bool {
+        enter_critical_section();
+        let given = unsafe { self.give_locked().is_some() };
+        exit_critical_section();
+        given
+    }
+    
+    // Give from an interrupt handler; never re-enables interrupts
+    // Sets `higher_priority_task_woken` if the woken taker outranks the
+    // interrupted task; pass it to port::yield_from_isr
+    pub fn give_from_isr(&self, higher_priority_task_woken: &mut bool) -> bool {
+        debug_assert!(port::is_inside_isr(), "give_from_isr called outside an ISR");
+        
+        with_critical_from_isr(|| {
+            match unsafe { self.give_locked() } {
+                Some(woken) => {
+                    if woken.is_some_and(tasks::outranks_running) {
+                        *higher_priority_task_woken = true;
+                    }
+                    true
+                },
+                None => false,
+            }
+        })
+    }
+    
+    // Returns None if full, otherwise the taker woken (if any)
+    // Must be called with interrupts masked
+    unsafe fn give_locked(&self) -> Option<Option<tasks::TaskHandle>> {
+        let count = &mut *self.count.get();
+        if *count >= self.max {
+            return None;
+        }
+        *count += 1;
+        Some(self.takers.wake_one())
+    }
+    
+    // Units currently available
+    pub fn count(&self) -> u32 {
+        unsafe { *self.count.get() }
+    }
+}
@@ -0,0 +1,230 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::freertos::{enter_critical_section, exit_critical_section, port, with_critical_from_isr};
+use crate::freertos::tasks::{self, TaskHandle};
+use crate::freertos::wait_list::{remaining_wait, WaitList};
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Byte stream between tasks, or from an ISR to a task
+// Unlike a Queue, sends and receives move any number of bytes. A receiver
+// blocked on an empty buffer is only woken once the trigger level is reached
+// (or its wait times out), so a parser task is not woken for every byte.
+pub struct StreamBuffer {
+    data: UnsafeCell<Vec<u8>>,
+    capacity: usize,
+    length: AtomicUsize,
+    head: AtomicUsize,
+    trigger_level: AtomicUsize,
+    senders: WaitList,      // Tasks blocked on a full buffer
+    receivers: WaitList,    // Tasks blocked on an empty buffer
+}
+
+unsafe impl Sync for StreamBuffer {}
+
+impl StreamBuffer {
+    // Create a buffer holding `capacity` bytes
+    // `trigger_level` is clamped to 1..=capacity
+    pub fn new(capacity: usize, trigger_level: usize) -> Self {
+        StreamBuffer {
+            data: UnsafeCell::new(vec![0; capacity]),
+            capacity,
+            length: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            trigger_level: AtomicUsize::new(trigger_level.clamp(1, capacity.max(1))),
+            senders: WaitList::new(),
+            receivers: WaitList::new(),
+        }
+    }
+    
+    // Change the number of bytes that must be buffered to wake a receiver
+    // Returns false if `level` exceeds the capacity
+    pub fn set_trigger_level(&self, level: usize) -> bool {
+        if level > self.capacity {
+            return false;
+        }
+        self.trigger_level.store(level.max(1), Ordering::Relaxed);
+        true
+    }
+    
+    // Size of the ring in bytes
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    
+    // Bytes waiting to be received
+    pub fn bytes_available(&self) -> usize {
+        self.length.load(Ordering::Relaxed)
+    }
+    
+    // Bytes that can be sent without blocking
+    pub fn space_available(&self) -> usize {
+        self.capacity - self.bytes_available()
+    }
+    
+    // Send all of `data`, blocking while the buffer is full for at most
+    // `max_wait` ticks (None waits forever)
+    // Returns the number of bytes sent, less than data.len() on timeout
+    pub fn send(&self, data: &[u8], max_wait: Option<u64>) -> usize {
+        let start_tick = tasks::get_tick_count();
+        let mut sent = 0;
+        
+        loop {
+            enter_critical_section();
+            
+            unsafe {
+                sent += self.write_locked(&data[sent..]);
+                self.wake_receiver();
+            }
+            if sent == data.len() {
+                exit_critical_section();
+                return sent;
+            }
+            
+            // Full: block on the sender list
+            let blocked = match remaining_wait(start_tick, max_wait) {
+                Some(remaining) => unsafe { self.senders.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return sent;
+                }
+            };
+            exit_critical_section();
+            
+            self.senders.wait(blocked);
+        }
+    }
+    
+    // Receive up to buf.len() bytes
+    // Returns at once if any bytes are buffered; otherwise blocks until the
+    // trigger level is reached, for at most `max_wait` ticks (None waits
+    // forever). Returns the number of bytes received, 0 on timeout.
+    pub fn receive(&self, buf: &mut [u8], max_wait: Option<u64>) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        let start_tick = tasks::get_tick_count();
+        
+        loop {
+            enter_critical_section();
+            
+            let received = unsafe { self.read_locked(buf) };
+            if received > 0 {
+                // Space is free for a waiting sender
+                unsafe {
+                    self.senders.wake_one();
+                }
+                exit_critical_section();
+                return received;
+            }
+            
+            // Empty: block on the receiver list
+            let blocked = match remaining_wait(start_tick, max_wait) {
+                Some(remaining) => unsafe { self.receivers.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return 0;
+                }
+            };
+            exit_critical_section();
+            
+            self.receivers.wait(blocked);
+        }
+    }
+    
+    // Send from an interrupt handler; never blocks or re-enables interrupts
+    // Returns the number of bytes that fitted. Sets `higher_priority_task_woken`
+    // if a receiver that outranks the interrupted task was woken; pass it to
+    // port::yield_from_isr.
+    pub fn send_from_isr(&self, data: &[u8], higher_priority_task_woken: &mut bool) -> usize {
+        debug_assert!(port::is_inside_isr(), "send_from_isr called outside an ISR");
+        
+        with_critical_from_isr(|| {
+            let sent = unsafe { self.write_locked(data) };
+            if sent > 0 && self.woke(unsafe { self.wake_receiver() }) {
+                *higher_priority_task_woken = true;
+            }
+            sent
+        })
+    }
+    
+    // Receive from an interrupt handler; never blocks
+    // Sets `higher_priority_task_woken` as for send_from_isr
+    pub fn receive_from_isr(&self, buf: &mut [u8], higher_priority_task_woken: &mut bool) -> usize {
+        debug_assert!(port::is_inside_isr(), "receive_from_isr called outside an ISR");
+        
+        with_critical_from_isr(|| {
+            let received = unsafe { self.read_locked(buf) };
+            if received > 0 && self.woke(unsafe { self.senders.wake_one() }) {
+                *higher_priority_task_woken = true;
+            }
+            received
+        })
+    }
+    
+    fn woke(&self, task: Option<TaskHandle>) -> bool {
+        task.is_some_and(tasks::outranks_running)
+    }
+    
+    // Wake a blocked receiver once the trigger level is reached
+    // Must be called with interrupts masked
+    unsafe fn wake_receiver(&self) -> Option<TaskHandle> {
+        if self.length.load(Ordering::Relaxed) < self.trigger_level.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.receivers.wake_one()
+    }
+    
+    // Copy as much of `data` as fits into the ring; returns the number of
+    // bytes copied
+    // Must be called with interrupts masked
+    pub unsafe fn write_locked(&self, data: &[u8]) -> usize {
+        let length = self.length.load(Ordering::Relaxed);
+        let count = data.len().min(self.capacity - length);
+        if count == 0 {
+            return 0;
+        }
+        
+        let ring = &mut *self.data.get();
+        let tail = (self.head.load(Ordering::Relaxed) + length) % self.capacity;
+        let first = count.min(self.capacity - tail);
+        ring[tail..tail + first].copy_from_slice(&data[..first]);
+        ring[..count - first].copy_from_slice(&data[first..count]);
+        
+        self.length.store(length + count, Ordering::Relaxed);
+        count
+    }
+    
+    // Copy up to buf.len() bytes out of the ring; returns the number of bytes
+    // copied
+    // Must be called with interrupts masked
+    pub unsafe fn read_locked(&self, buf: &mut [u8]) -> usize {
+        let count = self.peek_locked(buf);
+        if count == 0 {
+            return 0;
+        }
+        
+        let length = self.length.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Relaxed);
+        self.head.store((head + count) % self.capacity, Ordering::Relaxed);
+        self.length.store(length - count, Ordering::Relaxed);
+        count
+    }
+    
+    // Copy up to buf.len() bytes from the front of the ring without removing
+    // them; returns the number of bytes copied
+    // Must be called with interrupts masked
+    pub unsafe fn peek_locked(&self, buf: &mut [u8]) -> usize {
+        let count = buf.len().min(self.length.load(Ordering::Relaxed));
+        if count == 0 {
+            return 0;
+        }
+        
+        let ring = &*self.data.get();
+        let head = self.head.load(Ordering::Relaxed);
+        let first = count.min(self.capacity - head);
+        buf[..first].copy_from_slice(&ring[head..head + first]);
+        buf[first..count].copy_from_slice(&ring[..count - first]);
+        count
+    }
+}
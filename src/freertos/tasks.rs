@@ -1,106 +1,1062 @@
-use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use core::mem::MaybeUninit;
-use crate::freertos::{enter_critical_section, exit_critical_section};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use core::cell::UnsafeCell;
+use crate::freertos::{enter_critical_section, exit_critical_section, port, with_critical_from_isr};
 use crate::arch;
+use crate::arch::board::{BOARD, MAX_CORES};
+use crate::arch::core_set::CoreSet;
+use crate::freertos::wait_list::{remaining_wait, WaitList};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 // Simplified task control block
 pub struct TCB {
-    stack_pointer: *mut usize,
-    priority: u8,
+    stack_pointer: *mut usize,          // Saved TrapFrame while not running
+    stack_base: *mut u8,
+    priority: u8,                       // Effective priority, may be boosted
+    base_priority: u8,                  // Priority as last set by the application
     name: &'static str,
     state: TaskState,
     stack_size: usize,
-    function: fn(),
+    entry: Option<Box<dyn FnOnce()>>,   // Taken when the task is first dispatched
+    on_exit: Option<TaskExitHook>,
+    wake_tick: Option<u64>,             // Tick at which a Blocked task times out
+    wake_reason: WakeReason,
+    notification_value: u32,
+    notification_state: NotifyState,
+    fp_context: Option<Box<arch::aarch64::FpContext>>,  // Only for tasks using FP/SIMD
+    critical_nesting: u32,              // Critical section depth while switched out
+    critical_daif: u64,                 // IRQ state its outermost section restores
+    run_time: u64,                      // Generic timer counts spent Running
+    affinity: u64,                      // Cores it may run on, one bit per core position
+    released: Option<Arc<AtomicBool>>,  // Set once its JoinHandle is joined or dropped
+    held_locks: Vec<*const WaitList>,   // Waiters of each mutex it holds, for inheritance
 }
 
 // Task states
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskState {
     Ready,
     Running,
     Blocked,
-    Suspended,
+    Suspended,  // Not scheduled, woken or timed out until resume_task
+    Terminated, // Its function returned; reclaimed once its JoinHandle is released
+    Deleted,    // Removed by delete_task; the slot is reused once its stack is freed
 }
 
 // Task handle type
 pub type TaskHandle = usize;
 
+// Snapshot of one task, as reported by list and info
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    pub handle: TaskHandle,
+    pub name: &'static str,
+    pub priority: u8,                   // Effective priority, including any boost
+    pub state: TaskState,
+    pub stack_high_water: Option<usize>,    // As min_free_stack
+}
+
+// CPU time used by one task, as reported by task_stats
+#[derive(Clone, Debug)]
+pub struct TaskRuntime {
+    pub name: &'static str,
+    pub handle: TaskHandle,
+    pub ticks: u64,     // Generic timer counts spent Running
+    pub percent: u8,    // Share of the time used by all tasks
+}
+
+// Why a blocked task became ready again
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WakeReason {
+    Signaled,   // Woken by wake_task (notification, semaphore, queue, ...)
+    TimedOut,   // The timeout expired first
+}
+
+// Direct-to-task notification state
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NotifyState {
+    NotWaiting,
+    Waiting,    // Blocked in notify_wait/notify_take
+    Received,   // A notification is pending
+}
+
+// How notify updates the target's notification value
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NotifyAction {
+    SetBits(u32),   // OR the bits in
+    Increment,      // Add one (a lightweight counting semaphore)
+    SetValue(u32),  // Set, unless a notification is already pending
+    Overwrite(u32), // Set unconditionally
+}
+
+// Hook called on each pass of the idle task; must not block
+pub type IdleHook = fn();
+
+// Hook called when a task's stack overflow is detected
+pub type StackOverflowHook = fn(TaskHandle, &'static str);
+
+// Hook called when a task's function returns
+pub type TaskExitHook = fn(TaskHandle);
+
+// Errors returned by JoinHandle::join
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum JoinError {
+    Timeout,        // The task did not finish within the timeout
+    AlreadyJoined,  // The result has already been taken
+}
+
+// Errors returned when creating a task
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SpawnError {
+    OutOfMemory,    // No room on the heap for the task's stack
+}
+
+// Result slot shared between a task and its JoinHandle
+struct JoinSlot<T> {
+    done: AtomicBool,
+    result: UnsafeCell<Option<T>>,
+    joiners: WaitList,      // Tasks blocked in join
+}
+
+unsafe impl<T: Send> Sync for JoinSlot<T> {}
+
+impl<T> JoinSlot<T> {
+    fn new() -> Self {
+        JoinSlot {
+            done: AtomicBool::new(false),
+            result: UnsafeCell::new(None),
+            joiners: WaitList::new(),
+        }
+    }
+
+    // Store the task's return value and wake the joiners (called once, by
+    // the task itself on its way out)
+    fn complete(&self, value: T) {
+        enter_critical_section();
+        unsafe {
+            *self.result.get() = Some(value);
+            self.done.store(true, Ordering::Release);
+            while self.joiners.wake_one().is_some() {}
+        }
+        exit_critical_section();
+    }
+}
+
+// Handle for waiting on a task and collecting its return value
+pub struct JoinHandle<T = ()> {
+    handle: TaskHandle,
+    slot: Arc<JoinSlot<T>>,
+    released: Arc<AtomicBool>,  // Shared with the TCB, which is reclaimed once set
+}
+
+impl<T> JoinHandle<T> {
+    // Get the underlying task handle
+    pub fn task(&self) -> TaskHandle {
+        self.handle
+    }
+
+    // Check if the task function has returned
+    pub fn is_finished(&self) -> bool {
+        self.slot.done.load(Ordering::Acquire)
+    }
+
+    // Block until the task function returns and take its return value
+    // Returns Err(Timeout) if `max_wait` ticks elapse first
+    pub fn join(&self, max_wait: Option<u64>) -> Result<T, JoinError> {
+        let start_tick = get_tick_count();
+
+        loop {
+            enter_critical_section();
+
+            if self.is_finished() {
+                let result = unsafe { (*self.slot.result.get()).take() };
+                self.released.store(true, Ordering::Release);
+                exit_critical_section();
+                return result.ok_or(JoinError::AlreadyJoined);
+            }
+
+            // The task wakes us from JoinSlot::complete
+            let blocked = match remaining_wait(start_tick, max_wait) {
+                Some(remaining) => unsafe { self.slot.joiners.block(remaining) },
+                None => {
+                    exit_critical_section();
+                    return Err(JoinError::Timeout);
+                }
+            };
+            exit_critical_section();
+
+            self.slot.joiners.wait(blocked);
+        }
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    // A task that has returned is reclaimed by the idle task from now on
+    fn drop(&mut self) {
+        self.released.store(true, Ordering::Release);
+    }
+}
+
 // System tick counter
 static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
 
-// Current running task
-static CURRENT_TASK: AtomicUsize = AtomicUsize::new(0);
+// Stack size and priority of the idle task created by start_scheduler
+const IDLE_STACK_SIZE: usize = 4096;
+pub const IDLE_PRIORITY: u8 = 0;
+
+// Priority given to tasks at creation
+pub const DEFAULT_PRIORITY: u8 = 1;
+
+// Number of priority levels; higher priorities are capped at MAX_PRIORITIES - 1
+pub const MAX_PRIORITIES: u8 = 32;
+
+// Byte every new stack is filled with, for overflow and high-water checks
+const STACK_PAINT: u8 = 0xA5;
+
+// Bytes at the stack limit that must still hold the paint at each switch
+const STACK_GUARD_BYTES: usize = 32;
+
+// User idle hook, null when none is registered
+static IDLE_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+// Handle of each core's idle task, valid once that core runs the scheduler
+static IDLE_TASKS: [AtomicUsize; MAX_CORES] = [const { AtomicUsize::new(0) }; MAX_CORES];
+
+// Cores running the scheduler
+static SCHEDULER_CORES: CoreSet = CoreSet::new();
+
+// Affinity of a task that may run on any core
+pub const AFFINITY_ANY: u64 = u64::MAX;
+
+// User stack overflow hook, null for the default (panic)
+static STACK_OVERFLOW_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+// Set once start_scheduler has dispatched the first task
+static SCHEDULER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Window over which per-core load is computed
+const LOAD_WINDOW_NS: u64 = 1_000_000_000;
 
-// Task list (simplified)
-static mut TASKS: MaybeUninit<Vec<TCB>> = MaybeUninit::uninit();
-static mut NUM_TASKS: usize = 0;
+// Tasks listed by print_top
+const TOP_TASKS: usize = 5;
+
+// load_percent before the first window has closed
+const LOAD_UNKNOWN: u8 = u8::MAX;
+
+// Per-core load, sampled from the idle task's run time
+struct CoreIdleStats {
+    window_start: AtomicU64,    // Generic timer count the window opened at
+    idle_at_start: AtomicU64,   // Idle task run time when it opened
+    load_percent: AtomicU8,     // Load over the last closed window
+}
+
+impl CoreIdleStats {
+    const fn new() -> Self {
+        CoreIdleStats {
+            window_start: AtomicU64::new(0),
+            idle_at_start: AtomicU64::new(0),
+            load_percent: AtomicU8::new(LOAD_UNKNOWN),
+        }
+    }
+}
+
+static CORE_IDLE_STATS: [CoreIdleStats; MAX_CORES] = [const { CoreIdleStats::new() }; MAX_CORES];
+
+// Ready tasks one core may run, first come first served within each priority
+// A task that may run on several cores is queued on each of them, and leaves
+// them all once one picks it.
+struct ReadyQueue {
+    levels: [VecDeque<TaskHandle>; MAX_PRIORITIES as usize],
+    occupied: u32,      // Bit n set while levels[n] is non-empty
+}
+
+impl ReadyQueue {
+    const fn new() -> Self {
+        ReadyQueue {
+            levels: [const { VecDeque::new() }; MAX_PRIORITIES as usize],
+            occupied: 0,
+        }
+    }
+    
+    fn push(&mut self, handle: TaskHandle, priority: u8) {
+        self.levels[priority as usize].push_back(handle);
+        self.occupied |= 1 << priority;
+    }
+    
+    fn remove(&mut self, handle: TaskHandle, priority: u8) {
+        let level = &mut self.levels[priority as usize];
+        level.retain(|&h| h != handle);
+        if level.is_empty() {
+            self.occupied &= !(1 << priority);
+        }
+    }
+    
+    // Longest-waiting task at the highest non-empty level
+    fn front(&self) -> Option<TaskHandle> {
+        let top = u32::BITS.checked_sub(self.occupied.leading_zeros() + 1)?;
+        self.levels[top as usize].front().copied()
+    }
+    
+    fn clear(&mut self) {
+        self.levels.iter_mut().for_each(VecDeque::clear);
+        self.occupied = 0;
+    }
+}
+
+struct ReadyQueues {
+    queues: UnsafeCell<[ReadyQueue; MAX_CORES]>,
+}
+
+unsafe impl Sync for ReadyQueues {}
+
+static READY_QUEUES: ReadyQueues = ReadyQueues {
+    queues: UnsafeCell::new([const { ReadyQueue::new() }; MAX_CORES]),
+};
+
+// A core's ready queue
+// Must be called with interrupts masked
+unsafe fn ready_queue(core_pos: usize) -> &'static mut ReadyQueue {
+    &mut (*READY_QUEUES.queues.get())[core_pos]
+}
+
+// Cores whose ready queue holds a Ready task with this affinity
+fn queue_cores(affinity: u64) -> impl Iterator<Item = usize> {
+    (0..BOARD.num_cores.min(MAX_CORES)).filter(move |&core| affinity & core_bit(core) != 0)
+}
+
+// Must be called with interrupts masked
+fn enqueue_ready(handle: TaskHandle, task: &TCB) {
+    for core in queue_cores(task.affinity) {
+        unsafe { ready_queue(core).push(handle, task.priority) };
+    }
+}
+
+// Must be called with interrupts masked
+fn dequeue_ready(handle: TaskHandle, task: &TCB) {
+    for core in queue_cores(task.affinity) {
+        unsafe { ready_queue(core).remove(handle, task.priority) };
+    }
+}
+
+// Change a task's state, keeping it on the ready queues exactly while Ready
+// Must be called with interrupts masked
+fn set_state(handle: TaskHandle, task: &mut TCB, state: TaskState) {
+    match (task.state == TaskState::Ready, state == TaskState::Ready) {
+        (true, false) => dequeue_ready(handle, task),
+        (false, true) => enqueue_ready(handle, task),
+        _ => {},
+    }
+    task.state = state;
+}
+
+// Change a task's priority or affinity with `change`, moving it to the
+// matching ready queues if it is Ready
+// Must be called with interrupts masked
+fn requeue(handle: TaskHandle, task: &mut TCB, change: impl FnOnce(&mut TCB)) {
+    let ready = task.state == TaskState::Ready;
+    if ready {
+        dequeue_ready(handle, task);
+    }
+    change(task);
+    if ready {
+        enqueue_ready(handle, task);
+    }
+}
+
+// Every task, indexed by handle
+struct TaskList {
+    tasks: UnsafeCell<Vec<TCB>>,
+}
+
+unsafe impl Sync for TaskList {}
+
+static TASKS: TaskList = TaskList { tasks: UnsafeCell::new(Vec::new()) };
+
+// The task list
+// Must be called with interrupts masked
+unsafe fn task_list() -> &'static Vec<TCB> {
+    &*TASKS.tasks.get()
+}
+
+// The task list, for changing it
+// Must be called with interrupts masked
+unsafe fn task_list_mut() -> &'static mut Vec<TCB> {
+    &mut *TASKS.tasks.get()
+}
 
 // Initialize the task subsystem
 pub fn init() {
+    enter_critical_section();
     unsafe {
-        TASKS = MaybeUninit::new(Vec::new());
+        task_list_mut().clear();
+        (0..MAX_CORES).for_each(|core| ready_queue(core).clear());
+    }
+    exit_critical_section();
+}
+
+// How a task is created, for spawn_with
+#[derive(Copy, Clone, Debug)]
+pub struct TaskOptions {
+    pub name: &'static str,
+    pub stack_size: usize,
+    pub priority: u8,       // Capped at MAX_PRIORITIES - 1
+    pub uses_fpu: bool,     // Save q0-q31, FPCR and FPSR across context switches
+    pub affinity: u64,      // Cores it may run on; see set_task_affinity
+}
+
+impl TaskOptions {
+    // Options for a task at DEFAULT_PRIORITY that may run on any core and
+    // does not use FP/SIMD
+    pub const fn new(name: &'static str, stack_size: usize) -> Self {
+        TaskOptions { name, stack_size, priority: DEFAULT_PRIORITY, uses_fpu: false, affinity: AFFINITY_ANY }
     }
 }
 
 // Create a new task
-pub fn create_task(function: fn(), name: &'static str, stack_size: usize) -> TaskHandle {
+pub fn create_task(function: fn(), name: &'static str, stack_size: usize) -> Result<JoinHandle, SpawnError> {
+    spawn(function, name, stack_size)
+}
+
+// Create a new task whose return value can be collected with JoinHandle::join
+pub fn spawn<T: 'static>(function: fn() -> T, name: &'static str,
+                         stack_size: usize) -> Result<JoinHandle<T>, SpawnError> {
+    spawn_with(function, TaskOptions::new(name, stack_size))
+}
+
+// Create a task that only runs on the cores in `affinity` (one bit per core
+// position); see set_task_affinity
+pub fn create_task_pinned(function: fn(), name: &'static str, stack_size: usize,
+                          affinity: u64) -> Result<JoinHandle, SpawnError> {
+    spawn_with(function, TaskOptions { affinity, ..TaskOptions::new(name, stack_size) })
+}
+
+// Create a task that uses FP/SIMD registers
+// Only these tasks have q0-q31, FPCR and FPSR saved across context switches
+pub fn create_fpu_task(function: fn(), name: &'static str, stack_size: usize) -> Result<JoinHandle, SpawnError> {
+    spawn_with(function, TaskOptions { uses_fpu: true, ..TaskOptions::new(name, stack_size) })
+}
+
+// Create a task as described by `options`
+pub fn spawn_with<T: 'static>(function: fn() -> T, options: TaskOptions) -> Result<JoinHandle<T>, SpawnError> {
+    let slot = Arc::new(JoinSlot::new());
+    let task_slot = slot.clone();
+    let entry = Box::new(move || task_slot.complete(function()));
+    let released = Arc::new(AtomicBool::new(false));
+    
+    let handle = add_task(entry, &options, Some(released.clone()))?;
+    
+    Ok(JoinHandle { handle, slot, released })
+}
+
+// Allocate a stack and TCB and add the task to the task list
+// A task with no `released` flag is never reclaimed after returning.
+fn add_task(entry: Box<dyn FnOnce()>, options: &TaskOptions,
+            released: Option<Arc<AtomicBool>>) -> Result<TaskHandle, SpawnError> {
+    let TaskOptions { name, stack_size, priority, uses_fpu, affinity } = *options;
+    let priority = priority.min(MAX_PRIORITIES - 1);
     let task_id;
     
     enter_critical_section();
     
     unsafe {
-        // Allocate stack (simplified)
-        let stack = alloc::alloc::alloc(
-            alloc::alloc::Layout::from_size_align(stack_size, 8).unwrap()
-        ) as *mut usize;
+        // Allocate the stack and frame it so the first switch lands in task_start
+        let stack = alloc::alloc::alloc(stack_layout(stack_size));
+        if stack.is_null() {
+            exit_critical_section();
+            return Err(SpawnError::OutOfMemory);
+        }
+        core::ptr::write_bytes(stack, STACK_PAINT, stack_size);
+        
+        // Reuse the slot of a deleted task whose stack is gone, so that task
+        // churn does not grow the task list
+        let tasks = task_list_mut();
+        let free_slot = tasks.iter().position(|task| {
+            task.state == TaskState::Deleted && task.stack_base.is_null()
+        });
+        task_id = free_slot.unwrap_or(tasks.len());
+        let stack_pointer = port::init_task_stack(stack.add(stack_size), task_start, task_id);
         
         // Create TCB
         let tcb = TCB {
-            stack_pointer: stack,
-            priority: 1,
+            stack_pointer,
+            stack_base: stack,
+            priority,
+            base_priority: priority,
             name,
             state: TaskState::Ready,
             stack_size,
-            function,
+            entry: Some(entry),
+            on_exit: None,
+            wake_tick: None,
+            wake_reason: WakeReason::Signaled,
+            notification_value: 0,
+            notification_state: NotifyState::NotWaiting,
+            fp_context: if uses_fpu { Some(Box::new(arch::aarch64::FpContext::new())) } else { None },
+            critical_nesting: 0,
+            critical_daif: 0,
+            run_time: 0,
+            affinity,
+            released,
+            held_locks: Vec::new(),
         };
         
         // Add to task list
-        TASKS.assume_init_mut().push(tcb);
-        task_id = NUM_TASKS;
-        NUM_TASKS += 1;
+        enqueue_ready(task_id, &tcb);
+        match free_slot {
+            Some(slot) => tasks[slot] = tcb,
+            None => tasks.push(tcb),
+        }
     }
     
     exit_critical_section();
     
-    task_id
+    Ok(task_id)
+}
+
+// Layout of a task stack, shared by allocation and reclamation
+fn stack_layout(stack_size: usize) -> alloc::alloc::Layout {
+    alloc::alloc::Layout::from_size_align(stack_size, 16).unwrap()
+}
+
+// Free the stack of a deleted task
+// Must be called with interrupts masked, and never for the running task
+unsafe fn free_task_stack(task: &mut TCB) {
+    if !task.stack_base.is_null() {
+        alloc::alloc::dealloc(task.stack_base, stack_layout(task.stack_size));
+        task.stack_base = core::ptr::null_mut();
+        task.stack_pointer = core::ptr::null_mut();
+    }
+}
+
+// Delete a task and free its stack
+// A task deleting itself, or running on another core, keeps running on its
+// stack until its core switches away, so its stack is freed later by an idle
+// task. Handles of a deleted task
+// must not be used again: the slot may be reused by a new task.
+pub fn delete_task(handle: TaskHandle) -> bool {
+    enter_critical_section();
+    let (found, is_self) = unsafe {
+        match task_list_mut().get_mut(handle) {
+            Some(task) if task.state != TaskState::Deleted => {
+                let is_self = is_scheduler_running() && handle == get_current_task();
+                set_state(handle, task, TaskState::Deleted);
+                task.wake_tick = None;
+                task.on_exit = None;
+                task.entry = None;
+                task.fp_context = None;
+                task.released = None;
+                match running_on(handle) {
+                    // The idle task frees the stack once it has switched out
+                    Some(core) if core != arch::percpu::this_cpu().core_pos => port::set_need_resched_on(core),
+                    Some(_) => {},
+                    None => free_task_stack(task),
+                }
+                (true, is_self)
+            },
+            _ => (false, false),
+        }
+    };
+    exit_critical_section();
+    
+    if is_self {
+        // Never scheduled again
+        loop {
+            port::yield_task();
+        }
+    }
+    found
+}
+
+// Core whose current task is `handle`, if any
+// A Deleted task stays current on its core, still running on its stack,
+// until that core switches away from it.
+// Must be called with interrupts masked
+fn running_on(handle: TaskHandle) -> Option<usize> {
+    if !is_scheduler_running() {
+        return None;
+    }
+    (0..BOARD.num_cores).find(|&core| {
+        SCHEDULER_CORES.is_online(core)
+            && arch::percpu::cpu(core).is_some_and(|cpu| cpu.current_task.load(Ordering::Relaxed) == handle)
+    })
+}
+
+// Free the stacks of deleted tasks, and of terminated tasks whose JoinHandle
+// has been released, once no core is still running on them (run by the idle
+// tasks). A reclaimed terminated task becomes Deleted, so its slot is reused.
+fn reclaim_finished_tasks() {
+    enter_critical_section();
+    unsafe {
+        for (handle, task) in task_list_mut().iter_mut().enumerate() {
+            if running_on(handle).is_some() {
+                continue;
+            }
+            let released = task.released.as_ref().is_some_and(|flag| flag.load(Ordering::Acquire));
+            if task.state == TaskState::Terminated && released {
+                set_state(handle, task, TaskState::Deleted);
+                task.on_exit = None;
+                task.fp_context = None;
+                task.released = None;
+            }
+            if task.state == TaskState::Deleted {
+                free_task_stack(task);
+            }
+        }
+    }
+    exit_critical_section();
 }
 
 // Start the scheduler
+// Dispatches the first ready task on this core's stack and never returns.
 pub fn start_scheduler() {
-    // This is a simplified implementation
-    // In a real port, would set up timer interrupt and context switching
+    run_scheduler_here();
+}
+
+// Have a secondary core take tasks from the running scheduler
+// Never returns; returns at once if start_scheduler has not run yet.
+pub fn join_scheduler() {
+    if is_scheduler_running() {
+        run_scheduler_here();
+    }
+}
+
+// Dispatch this core's first task
+// Each core gets its own idle task, pinned to it, so there is always
+// something it may run.
+fn run_scheduler_here() {
+    let core = arch::percpu::this_cpu().core_pos;
     
-    if unsafe { NUM_TASKS == 0 } {
-        // No tasks created
-        return;
+    // Runs whenever no other task is ready for this core
+    let options = TaskOptions {
+        priority: IDLE_PRIORITY,
+        affinity: core_bit(core),
+        ..TaskOptions::new("idle", IDLE_STACK_SIZE)
+    };
+    let idle = add_task(Box::new(idle_task), &options, None).expect("no memory for the idle task");
+    IDLE_TASKS[core].store(idle, Ordering::Relaxed);
+    port::init_core();
+    
+    arch::disable_interrupts();
+    enter_critical_section();
+    let sp = unsafe {
+        let tasks = task_list_mut();
+        
+        // The idle task is always queued here, so there is a first task
+        let first = next_ready_task().unwrap_or(idle);
+        set_state(first, &mut tasks[first], TaskState::Running);
+        arch::percpu::this_cpu().current_task.store(first, Ordering::Relaxed);
+        tasks[first].stack_pointer
+    };
+    let now = arch::generic_timer::count();
+    arch::percpu::this_cpu().switched_in_at.store(now, Ordering::Relaxed);
+    CORE_IDLE_STATS[core].window_start.store(now, Ordering::Relaxed);
+    SCHEDULER_CORES.mark_online(core);
+    SCHEDULER_RUNNING.store(true, Ordering::Release);
+    exit_critical_section();
+    
+    // Wake secondaries waiting in wfe to join
+    arch::aarch64::sev();
+    
+    // The first frame's SPSR unmasks interrupts
+    port::start_first_task(sp);
+}
+
+// Body of the idle task
+// Runs at IDLE_PRIORITY, so only when every other task is blocked
+fn idle_task() {
+    loop {
+        reclaim_finished_tasks();
+        
+        let hook = IDLE_HOOK.load(Ordering::Acquire);
+        if !hook.is_null() {
+            let hook: IdleHook = unsafe { core::mem::transmute(hook) };
+            hook();
+        }
+        
+        crate::drivers::watchdog::idle_service(arch::core_pos(), SCHEDULER_CORES.mask());
+        idle_wait();
+        port::yield_task();
     }
+}
+
+// Register a hook run by the idle task before each wait for interrupt
+// (e.g. to enter a low-power state or kick a watchdog); None removes it
+pub fn set_idle_hook(hook: Option<IdleHook>) {
+    let ptr = hook.map_or(core::ptr::null_mut(), |hook| hook as *mut ());
+    IDLE_HOOK.store(ptr, Ordering::Release);
+}
+
+// Handle of this core's idle task
+pub fn idle_task_handle() -> TaskHandle {
+    IDLE_TASKS[arch::percpu::this_cpu().core_pos].load(Ordering::Relaxed)
+}
+
+// Affinity bit of a core position, zero if out of range
+fn core_bit(core_pos: usize) -> u64 {
+    if core_pos < 64 { 1 << core_pos } else { 0 }
+}
+
+// Check whether a task may run on this core
+fn eligible_here(task: &TCB) -> bool {
+    task.affinity & core_bit(arch::percpu::this_cpu().core_pos) != 0
+}
+
+// Highest-priority Ready task this core may run, the longest-waiting one of
+// its level, so equal priorities round-robin
+// Must be called with interrupts masked
+fn next_ready_task() -> Option<TaskHandle> {
+    unsafe { ready_queue(arch::percpu::this_cpu().core_pos).front() }
+}
+
+// Check whether a Ready task should take over from the running one
+// `same_level` also counts equal priorities, for time slicing on the tick.
+// Must be called with interrupts masked
+fn preemption_due(same_level: bool) -> bool {
+    let current = get_current_task();
+    let tasks = unsafe { task_list() };
+    let running = match tasks.get(current) {
+        Some(task) if task.state == TaskState::Running && eligible_here(task) => task.priority,
+        _ => return true,
+    };
     
-    // Set first task as current
-    CURRENT_TASK.store(0, Ordering::Relaxed);
+    match next_ready_task() {
+        Some(next) if same_level => tasks[next].priority >= running,
+        Some(next) => tasks[next].priority > running,
+        None => false,
+    }
+}
+
+// Change a task's priority, rescheduling if it now outranks the running task
+// Priorities above MAX_PRIORITIES - 1 are capped.
+pub fn set_task_priority(handle: TaskHandle, priority: u8) -> bool {
+    enter_critical_section();
+    let found = unsafe {
+        match task_list_mut().get_mut(handle) {
+            Some(task) => {
+                task.base_priority = priority.min(MAX_PRIORITIES - 1);
+                true
+            },
+            None => false,
+        }
+    };
+    // Keeps any boost inherited through a mutex it holds
+    if found {
+        update_inherited_priority(handle);
+    }
+    let reschedule = found && is_scheduler_running() && preemption_due(false);
+    exit_critical_section();
     
-    // Start first task
+    if reschedule {
+        port::yield_task();
+    }
+    found
+}
+
+// Get a task's priority
+pub fn get_task_priority(handle: TaskHandle) -> Option<u8> {
+    enter_critical_section();
+    let priority = unsafe { task_list().get(handle).map(|task| task.priority) };
+    exit_critical_section();
+    priority
+}
+
+// Take a task out of scheduling until resume_task
+// A Blocked task leaves its wait: it is no longer woken by the object it
+// waited on or by its timeout, and once resumed its wait returns as timed out
+// (the object calls then re-check and block again if time remains).
+// Suspending the running task yields at once.
+pub fn suspend_task(handle: TaskHandle) -> bool {
+    enter_critical_section();
+    let (found, is_self) = unsafe {
+        match task_list_mut().get_mut(handle) {
+            Some(task) if matches!(task.state, TaskState::Ready | TaskState::Running
+                                   | TaskState::Blocked | TaskState::Suspended) => {
+                let running = task.state == TaskState::Running;
+                set_state(handle, task, TaskState::Suspended);
+                task.wake_tick = None;
+                task.wake_reason = WakeReason::TimedOut;
+                
+                // A task running on another core is switched out by its core
+                let here = arch::percpu::this_cpu().core_pos;
+                match running_on(handle) {
+                    Some(core) if running && core != here => {
+                        port::set_need_resched_on(core);
+                        (true, false)
+                    },
+                    _ => (true, running),
+                }
+            },
+            _ => (false, false),
+        }
+    };
+    exit_critical_section();
+    
+    if is_self {
+        port::yield_task();
+    }
+    found
+}
+
+// Make a suspended task Ready again, yielding if it outranks the caller
+// Returns false if the task was not suspended
+pub fn resume_task(handle: TaskHandle) -> bool {
+    enter_critical_section();
+    let resumed = resume_locked(handle);
+    let reschedule = resumed && request_preemption(handle);
+    exit_critical_section();
+    
+    if reschedule {
+        port::yield_task();
+    }
+    resumed
+}
+
+// Resume from an interrupt handler; never re-enables interrupts
+// Sets `higher_priority_task_woken` if the task outranks the interrupted one;
+// pass it to port::yield_from_isr
+pub fn resume_from_isr(handle: TaskHandle, higher_priority_task_woken: &mut bool) -> bool {
+    debug_assert!(port::is_inside_isr(), "resume_from_isr called outside an ISR");
+    
+    with_critical_from_isr(|| {
+        let resumed = resume_locked(handle);
+        if resumed {
+            request_preemption(handle);
+            if outranks_running(handle) {
+                *higher_priority_task_woken = true;
+            }
+        }
+        resumed
+    })
+}
+
+// Must be called with interrupts masked
+fn resume_locked(handle: TaskHandle) -> bool {
     unsafe {
-        let task = &TASKS.assume_init_ref()[0];
-        (task.function)();
+        match task_list_mut().get_mut(handle) {
+            Some(task) if task.state == TaskState::Suspended => {
+                set_state(handle, task, TaskState::Ready);
+                true
+            },
+            _ => false,
+        }
     }
+}
+
+// Save the running task's frame and pick the task to resume
+// Called from port_switch_context, in exception context with IRQs masked.
+// If no other task is ready the current one keeps running.
+pub fn switch_context(sp: *mut usize) -> *mut usize {
+    let _span = arch::profile::start("context_switch");
+    let current = get_current_task();
     
-    // This should never be reached in a real implementation
+    unsafe {
+        let tasks = task_list_mut();
+        tasks[current].stack_pointer = sp;
+        check_stack(current, &tasks[current]);
+        
+        let next = match next_ready_task() {
+            Some(next) => next,
+            None => return sp,
+        };
+        
+        // A running task only gives way to an equal or higher priority,
+        // unless its affinity no longer includes this core
+        let movable = !eligible_here(&tasks[current]);
+        if tasks[current].state == TaskState::Running
+            && tasks[next].priority < tasks[current].priority
+            && !movable
+        {
+            return sp;
+        }
+        
+        // Behind the others of its level, which is what round-robins them
+        if tasks[current].state == TaskState::Running {
+            set_state(current, &mut tasks[current], TaskState::Ready);
+            if movable {
+                request_preemption(current);
+            }
+        }
+        
+        // FP state is switched here, as nothing in the kernel touches it
+        if let Some(fp) = tasks[current].fp_context.as_deref_mut() {
+            arch::aarch64::save_fp(fp);
+        }
+        if let Some(fp) = tasks[next].fp_context.as_deref() {
+            arch::aarch64::restore_fp(fp);
+        }
+        
+        // The critical section depth belongs to the task, like its SPSR
+        let cpu = arch::percpu::this_cpu();
+        tasks[current].critical_nesting = cpu.critical_nesting.load(Ordering::Relaxed);
+        tasks[current].critical_daif = cpu.critical_daif.load(Ordering::Relaxed);
+        cpu.critical_nesting.store(tasks[next].critical_nesting, Ordering::Relaxed);
+        cpu.critical_daif.store(tasks[next].critical_daif, Ordering::Relaxed);
+        
+        // Charge the outgoing task for the time since it was switched in
+        let now = arch::generic_timer::count();
+        tasks[current].run_time += now.wrapping_sub(cpu.switched_in_at.swap(now, Ordering::Relaxed));
+        
+        set_state(next, &mut tasks[next], TaskState::Running);
+        cpu.current_task.store(next, Ordering::Relaxed);
+        tasks[next].stack_pointer
+    }
+}
+
+// Check a task's saved SP is inside its stack and the guard area is intact
+// Must be called with interrupts masked
+fn check_stack(handle: TaskHandle, task: &TCB) {
+    if task.stack_base.is_null() {
+        return;
+    }
+    
+    let base = task.stack_base as usize;
+    let sp = task.stack_pointer as usize;
+    let in_bounds = sp >= base && sp < base + task.stack_size;
+    let guard_intact = unsafe {
+        core::slice::from_raw_parts(task.stack_base, STACK_GUARD_BYTES.min(task.stack_size))
+            .iter()
+            .all(|&b| b == STACK_PAINT)
+    };
+    
+    if !in_bounds || !guard_intact {
+        let hook = STACK_OVERFLOW_HOOK.load(Ordering::Acquire);
+        if hook.is_null() {
+            panic!("Stack overflow in task {} ({})", handle, task.name);
+        }
+        let hook: StackOverflowHook = unsafe { core::mem::transmute(hook) };
+        hook(handle, task.name);
+    }
+}
+
+// Register a hook called on a detected stack overflow, instead of panicking
+pub fn set_stack_overflow_hook(hook: StackOverflowHook) {
+    STACK_OVERFLOW_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+// Smallest amount of stack, in bytes, that has stayed free since the task was
+// created (the high-water mark), found by scanning for untouched paint
+pub fn min_free_stack(handle: TaskHandle) -> Option<usize> {
+    enter_critical_section();
+    let free = unsafe { task_list().get(handle).and_then(stack_high_water) };
+    exit_critical_section();
+    free
+}
+
+// Untouched paint at the bottom of a task's stack, None once it is freed
+fn stack_high_water(task: &TCB) -> Option<usize> {
+    if task.stack_base.is_null() {
+        return None;
+    }
+    let stack = unsafe { core::slice::from_raw_parts(task.stack_base, task.stack_size) };
+    Some(stack.iter().take_while(|&&b| b == STACK_PAINT).count())
+}
+
+fn task_info(handle: TaskHandle, task: &TCB) -> TaskInfo {
+    TaskInfo {
+        handle,
+        name: task.name,
+        priority: task.priority,
+        state: task.state,
+        stack_high_water: stack_high_water(task),
+    }
+}
+
+// Snapshot every task (deleted slots are skipped)
+// Taken in one critical section, so it is consistent even while other tasks
+// create or delete tasks
+pub fn list() -> Vec<TaskInfo> {
+    enter_critical_section();
+    let tasks = unsafe {
+        task_list().iter().enumerate()
+            .filter(|(_, task)| task.state != TaskState::Deleted)
+            .map(|(handle, task)| task_info(handle, task))
+            .collect()
+    };
+    exit_critical_section();
+    tasks
+}
+
+// Snapshot of one task, None if the handle is unknown or deleted
+pub fn info(handle: TaskHandle) -> Option<TaskInfo> {
+    enter_critical_section();
+    let info = unsafe {
+        task_list().get(handle)
+            .filter(|task| task.state != TaskState::Deleted)
+            .map(|task| task_info(handle, task))
+    };
+    exit_critical_section();
+    info
+}
+
+// First code run by every task, entered from its initial frame
+extern "C" fn task_start(handle: usize) -> ! {
+    task_trampoline(handle);
+    
+    // Terminated tasks are never picked again
+    loop {
+        port::yield_task();
+    }
+}
+
+// Entry wrapper for every task
+// A task function that returns ends up in task_exit rather than returning
+// into scheduler internals
+fn task_trampoline(handle: TaskHandle) {
+    enter_critical_section();
+    let entry = unsafe { task_list_mut()[handle].entry.take() };
+    exit_critical_section();
+    
+    if let Some(entry) = entry {
+        entry();
+    }
+    
+    task_exit(handle);
+}
+
+// Terminate a task whose function returned and reschedule
+fn task_exit(handle: TaskHandle) {
+    enter_critical_section();
+    let on_exit = unsafe {
+        let task = &mut task_list_mut()[handle];
+        set_state(handle, task, TaskState::Terminated);
+        task.on_exit
+    };
+    exit_critical_section();
+    
+    if let Some(hook) = on_exit {
+        hook(handle);
+    }
+    
+    // The task will never run again
+    port::set_need_resched();
+}
+
+// Set a hook called when the task's function returns
+pub fn set_exit_hook(handle: TaskHandle, hook: Option<TaskExitHook>) -> bool {
+    enter_critical_section();
+    let found = unsafe {
+        match task_list_mut().get_mut(handle) {
+            Some(task) => {
+                task.on_exit = hook;
+                true
+            },
+            None => false,
+        }
+    };
+    exit_critical_section();
+    found
+}
+
+// Check if the scheduler has been started
+pub fn is_scheduler_running() -> bool {
+    SCHEDULER_RUNNING.load(Ordering::Acquire)
 }
 
 // Get current task handle
 pub fn get_current_task() -> TaskHandle {
-    CURRENT_TASK.load(Ordering::Relaxed)
+    arch::percpu::this_cpu().current_task.load(Ordering::Relaxed)
 }
 
 // Increment system tick
@@ -110,9 +1066,21 @@ pub fn increment_tick() {
 }
 
 // Increment system tick from ISR
+// Takes a priority-based critical section rather than masking IRQs. Requests a
+// switch on the way out of the ISR if a woken or waiting task should preempt,
+// or time-slice with, the running one.
 pub fn increment_tick_from_isr() {
-    TICK_COUNT.fetch_add(1, Ordering::Relaxed);
-    // In a real implementation, would defer task unblocking to the exit from ISR
+    with_critical_from_isr(|| {
+        let now = TICK_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+        
+        if !is_scheduler_running() {
+            return;
+        }
+        wake_expired_tasks(now);
+        if preemption_due(true) {
+            port::set_need_resched();
+        }
+    })
 }
 
 // Get current tick count
@@ -120,20 +1088,595 @@ pub fn get_tick_count() -> u64 {
     TICK_COUNT.load(Ordering::Relaxed)
 }
 
-// Delay the current task
+// Block the current task for `ticks` ticks
+// Lower-priority tasks run in the meantime.
 pub fn delay(ticks: u32) {
-    // For our simple implementation, we'll just busy-wait
     let start = get_tick_count();
-    let target = start + ticks as u64;
+    let ticks = ticks as u64;
+    
+    loop {
+        let elapsed = get_tick_count().wrapping_sub(start);
+        if elapsed >= ticks {
+            return;
+        }
+        
+        // An early wake_task only shortens this round of the wait
+        block_with_timeout(Some(ticks - elapsed));
+    }
+}
+
+// Delay until `*last_wake + period` for drift-free periodic work
+// `*last_wake` always advances by exactly one period, even if the task overran
+// and the deadline has already passed (then this returns immediately).
+// Initialize it with get_tick_count() before the first call. Tick arithmetic
+// wraps, so deadlines straddling counter wraparound behave the same.
+pub fn delay_until(last_wake: &mut u64, period: u64) {
+    let start = *last_wake;
+    *last_wake = start.wrapping_add(period);
+    
+    loop {
+        let elapsed = get_tick_count().wrapping_sub(start);
+        if elapsed >= period {
+            return;
+        }
+        
+        // An early wake_task only shortens this round of the wait
+        block_with_timeout(Some(period - elapsed));
+    }
+}
+
+// Wait for an interrupt
+// Load is measured from the idle task's run time, so this is not counted
+// as idle.
+pub fn idle_wait() {
+    arch::wait_for_interrupt();
+}
+
+// Run time of a task, charging a core that is running it up to `now`
+// Must be called with interrupts masked
+fn run_time_at(handle: TaskHandle, task: &TCB, now: u64) -> u64 {
+    let running_for = running_on(handle)
+        .and_then(arch::percpu::cpu)
+        .map_or(0, |cpu| now.wrapping_sub(cpu.switched_in_at.load(Ordering::Relaxed)));
+    task.run_time + running_for
+}
+
+// Percentage of the last load window the core spent outside its idle task
+// Computed from the idle task's run time, whether or not anyone on that core
+// ever idles. Until the first window closes, covers the time since the core
+// started the scheduler. 0 for a core not running the scheduler.
+pub fn core_load(core_pos: usize) -> u8 {
+    let Some(stats) = CORE_IDLE_STATS.get(core_pos) else { return 0 };
+    if !SCHEDULER_CORES.is_online(core_pos) {
+        return 0;
+    }
+    
+    enter_critical_section();
+    let now = arch::generic_timer::count();
+    let idle_task = IDLE_TASKS[core_pos].load(Ordering::Relaxed);
+    let idle = unsafe {
+        task_list().get(idle_task).map_or(0, |task| run_time_at(idle_task, task, now))
+    };
+    exit_critical_section();
+    
+    let elapsed = now.wrapping_sub(stats.window_start.load(Ordering::Relaxed));
+    let last = stats.load_percent.load(Ordering::Relaxed);
+    let window_closed = arch::generic_timer::ticks_to_ns(elapsed) >= LOAD_WINDOW_NS;
+    if elapsed == 0 || (!window_closed && last != LOAD_UNKNOWN) {
+        return if last == LOAD_UNKNOWN { 0 } else { last };
+    }
     
-    while get_tick_count() < target {
-        // Yield to other tasks (in a real implementation)
-        arch::wait_for_interrupt();
+    let idle_in_window = idle.wrapping_sub(stats.idle_at_start.load(Ordering::Relaxed)).min(elapsed);
+    let load = (100 - idle_in_window as u128 * 100 / elapsed as u128) as u8;
+    if window_closed {
+        stats.window_start.store(now, Ordering::Relaxed);
+        stats.idle_at_start.store(idle, Ordering::Relaxed);
+        stats.load_percent.store(load, Ordering::Relaxed);
+    }
+    load
+}
+
+// Print a top-style summary: per-core load, then the tasks using the most CPU
+pub fn print_top() {
+    crate::println!("CORE  LOAD");
+    for core_pos in 0..arch::board::BOARD.num_cores {
+        crate::println!("{:>4}  {:>3}%", core_pos, core_load(core_pos));
+    }
+    
+    let mut stats = task_stats();
+    stats.sort_unstable_by_key(|s| core::cmp::Reverse(s.ticks));
+    crate::println!("TASK              CPU");
+    for s in stats.iter().take(TOP_TASKS) {
+        crate::println!("{:<16} {:>3}%", s.name, s.percent);
+    }
+}
+
+// CPU time used by every task (including idle) since the scheduler started
+// The running task is charged up to now. Percentages are of the time used by
+// all tasks, so they add up to about 100 per core.
+pub fn task_stats() -> Vec<TaskRuntime> {
+    enter_critical_section();
+    let now = arch::generic_timer::count();
+    let mut stats: Vec<TaskRuntime> = unsafe {
+        task_list().iter().enumerate()
+            .filter(|(_, task)| task.state != TaskState::Deleted)
+            .map(|(handle, task)| TaskRuntime {
+                name: task.name,
+                handle,
+                ticks: run_time_at(handle, task, now),
+                percent: 0,
+            })
+            .collect()
+    };
+    exit_critical_section();
+    
+    let total: u64 = stats.iter().map(|s| s.ticks).sum();
+    if total > 0 {
+        for s in &mut stats {
+            s.percent = (s.ticks as u128 * 100 / total as u128) as u8;
+        }
+    }
+    stats
+}
+
+// Print per-task CPU usage, the equivalent of vTaskGetRunTimeStats
+pub fn print_task_stats() {
+    crate::println!("TASK              TIME(us)   CPU");
+    for s in task_stats() {
+        let us = arch::generic_timer::ticks_to_ns(s.ticks) / 1000;
+        crate::println!("{:<16} {:>10}  {:>3}%", s.name, us, s.percent);
     }
 }
 
 // Check for tasks that should be unblocked
 fn check_delayed_tasks() {
-    // In a real implementation, would check for tasks whose delay has expired
-    // and move them from the Blocked state to the Ready state
-}
\ No newline at end of file
+    let now = get_tick_count();
+    
+    enter_critical_section();
+    wake_expired_tasks(now);
+    exit_critical_section();
+}
+
+// Move every Blocked task whose timeout has expired to Ready
+// Must be called with interrupts masked
+fn wake_expired_tasks(now: u64) {
+    // The tick only reaches the boot core, so a task another core should run
+    // is handed over with the reschedule SGI
+    for handle in 0..unsafe { task_list().len() } {
+        if timeout_if_expired(handle, unsafe { &mut task_list_mut()[handle] }, now) {
+            request_preemption(handle);
+        }
+    }
+}
+
+// Move a Blocked task whose timeout has expired to Ready
+// Returns true if it did.
+// Must be called inside a critical section
+fn timeout_if_expired(handle: TaskHandle, task: &mut TCB, now: u64) -> bool {
+    if task.state == TaskState::Blocked {
+        if let Some(wake_tick) = task.wake_tick {
+            if now >= wake_tick {
+                set_state(handle, task, TaskState::Ready);
+                task.wake_tick = None;
+                task.wake_reason = WakeReason::TimedOut;
+                return true;
+            }
+        }
+    }
+    false
+}
+
+// Block the current task until it is signaled with wake_task or `timeout`
+// ticks elapse, whichever happens first (None waits forever)
+pub fn block_with_timeout(timeout: Option<u64>) -> WakeReason {
+    let start_tick = get_tick_count();
+    
+    enter_critical_section();
+    let found = mark_current_blocked(timeout);
+    exit_critical_section();
+    
+    // Not called from a task: nothing can signal us, just wait out the timeout
+    if !found {
+        while timeout.is_none_or(|ticks| get_tick_count() - start_tick < ticks) {
+            idle_wait();
+        }
+        return WakeReason::TimedOut;
+    }
+    
+    wait_while_blocked()
+}
+
+// Mark the current task Blocked, with an optional timeout in ticks
+// Lets a caller register itself on a wait list and block atomically, so a
+// wakeup between the two cannot be lost. Follow with wait_while_blocked.
+// Returns false if not called from a task.
+// Must be called with interrupts masked
+pub fn mark_current_blocked(timeout: Option<u64>) -> bool {
+    let handle = get_current_task();
+    let now = get_tick_count();
+    unsafe {
+        match task_list_mut().get_mut(handle) {
+            Some(task) if task.state == TaskState::Running => {
+                set_state(handle, task, TaskState::Blocked);
+                task.wake_tick = timeout.map(|ticks| now.saturating_add(ticks));
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+// Wait until the current task, marked Blocked, is signaled or times out
+pub fn wait_while_blocked() -> WakeReason {
+    let handle = get_current_task();
+    
+    loop {
+        // The state change and the reason are written together under the
+        // critical section, so whichever of signal/timeout came first wins
+        enter_critical_section();
+        let woken = unsafe {
+            let task = &mut task_list_mut()[handle];
+            timeout_if_expired(handle, task, get_tick_count());
+            if task.state != TaskState::Blocked && task.state != TaskState::Suspended {
+                set_state(handle, task, TaskState::Running);
+                Some(task.wake_reason)
+            } else {
+                None
+            }
+        };
+        exit_critical_section();
+        
+        if let Some(reason) = woken {
+            return reason;
+        }
+        
+        // A Blocked task is not picked again until it is woken
+        if is_scheduler_running() {
+            port::yield_task();
+        } else {
+            idle_wait();
+        }
+    }
+}
+
+// Wake a task blocked in block_with_timeout
+// Returns false if the task was not blocked (e.g. it already timed out)
+pub fn wake_task(handle: TaskHandle) -> bool {
+    enter_critical_section();
+    let woken = wake_task_locked(handle);
+    exit_critical_section();
+    woken
+}
+
+// wake_task for callers already holding the critical section
+// Requests a reschedule if the woken task outranks the running one
+pub fn wake_task_locked(handle: TaskHandle) -> bool {
+    let woken = unsafe {
+        match task_list_mut().get_mut(handle) {
+            Some(task) if task.state == TaskState::Blocked => {
+                set_state(handle, task, TaskState::Ready);
+                task.wake_tick = None;
+                task.wake_reason = WakeReason::Signaled;
+                true
+            },
+            _ => false,
+        }
+    };
+    if woken {
+        request_preemption(handle);
+    }
+    woken
+}
+
+// Have a core that may run the now Ready `handle` reschedule if the task
+// outranks what it is running. This core is preferred; another core is
+// interrupted with the reschedule SGI. Returns true if this core should switch.
+// Must be called with interrupts masked
+fn request_preemption(handle: TaskHandle) -> bool {
+    if !is_scheduler_running() {
+        return false;
+    }
+    let tasks = unsafe { task_list() };
+    let Some(task) = tasks.get(handle) else { return false };
+    
+    if eligible_here(task) && preemption_due(false) {
+        port::set_need_resched();
+        return true;
+    }
+    
+    let here = arch::percpu::this_cpu().core_pos;
+    for core in 0..BOARD.num_cores {
+        if core == here || task.affinity & core_bit(core) == 0 || !SCHEDULER_CORES.is_online(core) {
+            continue;
+        }
+        let running = arch::percpu::cpu(core).map(|cpu| cpu.current_task.load(Ordering::Relaxed));
+        let outranked = running.and_then(|r| tasks.get(r))
+            .is_none_or(|r| r.state != TaskState::Running || r.priority < task.priority);
+        if outranked {
+            port::set_need_resched_on(core);
+            break;
+        }
+    }
+    false
+}
+
+// Restrict a task to the cores in `affinity` (one bit per core position)
+// A task running on a core it may no longer use moves at once; the caller
+// yields if that is itself. Returns false for an unknown task or a mask
+// naming no existing core.
+pub fn set_task_affinity(handle: TaskHandle, affinity: u64) -> bool {
+    let cores = if BOARD.num_cores >= 64 { u64::MAX } else { (1 << BOARD.num_cores) - 1 };
+    if affinity & cores == 0 {
+        return false;
+    }
+    
+    enter_critical_section();
+    let (found, yield_now) = unsafe {
+        match task_list_mut().get_mut(handle) {
+            Some(task) if task.state != TaskState::Deleted => {
+                requeue(handle, task, |task| task.affinity = affinity);
+                let state = task.state;
+                let mut yield_now = false;
+                if state == TaskState::Running {
+                    // Find the core running it and move it off if needed
+                    for core in 0..BOARD.num_cores {
+                        let runs_it = arch::percpu::cpu(core)
+                            .is_some_and(|cpu| cpu.current_task.load(Ordering::Relaxed) == handle);
+                        if runs_it && affinity & core_bit(core) == 0 {
+                            if core == arch::percpu::this_cpu().core_pos {
+                                yield_now = true;
+                            } else {
+                                port::set_need_resched_on(core);
+                            }
+                        }
+                    }
+                } else if state == TaskState::Ready {
+                    request_preemption(handle);
+                }
+                (true, yield_now)
+            },
+            _ => (false, false),
+        }
+    };
+    exit_critical_section();
+    
+    if yield_now {
+        port::yield_task();
+    }
+    found
+}
+
+// Record that a task holds a mutex whose blocked tasks are `waiters`
+// They lend it their priority until remove_held_lock. The list must not move
+// while the lock is held.
+// Must be called with interrupts masked
+pub fn add_held_lock(handle: TaskHandle, waiters: &WaitList) {
+    if let Some(task) = unsafe { task_list_mut().get_mut(handle) } {
+        task.held_locks.push(waiters);
+    }
+}
+
+// Undo add_held_lock, on releasing the mutex
+// Must be called with interrupts masked
+pub fn remove_held_lock(handle: TaskHandle, waiters: &WaitList) {
+    if let Some(task) = unsafe { task_list_mut().get_mut(handle) } {
+        task.held_locks.retain(|&held| !core::ptr::eq(held, waiters));
+    }
+}
+
+// Recompute a task's effective priority: the application-set priority, raised
+// to that of the highest task blocked on any mutex it holds (priority
+// inheritance). Call whenever one of those waiters arrives or leaves, or the
+// task takes or releases a mutex. Reschedules if the change calls for it.
+// Must be called with interrupts masked
+pub fn update_inherited_priority(handle: TaskHandle) {
+    let (priority, state) = {
+        let Some(task) = (unsafe { task_list().get(handle) }) else { return };
+        let inherited = task.held_locks.iter()
+            .filter_map(|&waiters| unsafe { (*waiters).highest_priority() })
+            .max();
+        (inherited.map_or(task.base_priority, |p| p.max(task.base_priority)), task.state)
+    };
+    let task = unsafe { &mut task_list_mut()[handle] };
+    if task.priority != priority {
+        requeue(handle, task, |task| task.priority = priority);
+    }
+    
+    if state == TaskState::Ready {
+        request_preemption(handle);
+    } else if is_scheduler_running() && preemption_due(false) {
+        port::set_need_resched();
+    }
+}
+
+// Highest priority among the tasks on a wait list that are still Blocked
+// Must be called with interrupts masked
+pub fn highest_waiting_priority(waiters: &[TaskHandle]) -> Option<u8> {
+    let tasks = unsafe { task_list() };
+    waiters.iter()
+        .filter_map(|&h| tasks.get(h))
+        .filter(|task| task.state == TaskState::Blocked)
+        .map(|task| task.priority)
+        .max()
+}
+
+// Check if a task may run on this core and has a higher priority than the
+// task running here
+// Must be called with interrupts masked
+pub fn outranks_running(handle: TaskHandle) -> bool {
+    let tasks = unsafe { task_list() };
+    match (tasks.get(handle), tasks.get(get_current_task())) {
+        (Some(task), Some(running)) => eligible_here(task) && task.priority > running.priority,
+        _ => false,
+    }
+}
+
+// Remove the highest-priority task from a wait list and wake it
+// Tasks no longer blocked (timed out, deleted) are dropped from the list.
+// Must be called with interrupts masked
+pub fn wake_highest_priority(waiters: &mut Vec<TaskHandle>) -> Option<TaskHandle> {
+    let tasks = unsafe { task_list() };
+    waiters.retain(|&h| tasks.get(h).is_some_and(|task| task.state == TaskState::Blocked));
+    
+    // First of the highest priority, so equal priorities are served FIFO
+    let mut best: Option<usize> = None;
+    for (i, &h) in waiters.iter().enumerate() {
+        if best.is_none_or(|b| tasks[h].priority > tasks[waiters[b]].priority) {
+            best = Some(i);
+        }
+    }
+    
+    let handle = waiters.remove(best?);
+    wake_task_locked(handle);
+    Some(handle)
+}
+
+// Send a notification to a task, waking it if it is waiting for one
+// Returns false only for SetValue when a notification is already pending,
+// or for an unknown task
+pub fn notify(handle: TaskHandle, action: NotifyAction) -> bool {
+    enter_critical_section();
+    let sent = notify_locked(handle, action).is_some();
+    exit_critical_section();
+    sent
+}
+
+// Notify from an interrupt handler; never re-enables interrupts
+// Sets `higher_priority_task_woken` if the woken task outranks the
+// interrupted one; pass it to port::yield_from_isr
+pub fn notify_from_isr(handle: TaskHandle, action: NotifyAction,
+                       higher_priority_task_woken: &mut bool) -> bool {
+    debug_assert!(port::is_inside_isr(), "notify_from_isr called outside an ISR");
+    
+    with_critical_from_isr(|| {
+        match notify_locked(handle, action) {
+            Some(woken) => {
+                if woken && outranks_running(handle) {
+                    *higher_priority_task_woken = true;
+                }
+                true
+            },
+            None => false,
+        }
+    })
+}
+
+// Returns None if not sent, otherwise whether the task was woken
+// Must be called with interrupts masked
+fn notify_locked(handle: TaskHandle, action: NotifyAction) -> Option<bool> {
+    let task = unsafe { task_list_mut().get_mut(handle)? };
+    let previous = task.notification_state;
+    
+    match action {
+        NotifyAction::SetBits(bits) => task.notification_value |= bits,
+        NotifyAction::Increment => {
+            task.notification_value = task.notification_value.wrapping_add(1);
+        },
+        NotifyAction::SetValue(value) => {
+            if previous == NotifyState::Received {
+                return None;
+            }
+            task.notification_value = value;
+        },
+        NotifyAction::Overwrite(value) => task.notification_value = value,
+    }
+    task.notification_state = NotifyState::Received;
+    
+    Some(previous == NotifyState::Waiting && wake_task_locked(handle))
+}
+
+// Wait for a notification to the current task
+// Bits in `clear_on_entry` are cleared before waiting (if none is pending)
+// and bits in `clear_on_exit` after one is received. Returns the value as it
+// was before the exit clear, or None on timeout.
+pub fn notify_wait(clear_on_entry: u32, clear_on_exit: u32, timeout: Option<u64>) -> Option<u32> {
+    let value = wait_for_notification(timeout, |task| task.notification_value &= !clear_on_entry)?;
+    
+    enter_critical_section();
+    if let Some(task) = unsafe { task_list_mut().get_mut(get_current_task()) } {
+        task.notification_value &= !clear_on_exit;
+    }
+    exit_critical_section();
+    Some(value)
+}
+
+// Take a notification used as a counting semaphore (see NotifyAction::Increment)
+// Decrements the value, or zeroes it with `clear_on_exit`, and returns the
+// value before that; 0 on timeout
+pub fn notify_take(clear_on_exit: bool, timeout: Option<u64>) -> u32 {
+    enter_critical_section();
+    let pending = unsafe {
+        task_list().get(get_current_task()).map_or(0, |task| task.notification_value)
+    };
+    exit_critical_section();
+    
+    let value = if pending != 0 {
+        pending
+    } else {
+        match wait_for_notification(timeout, |_| {}) {
+            Some(value) => value,
+            None => return 0,
+        }
+    };
+    
+    enter_critical_section();
+    if let Some(task) = unsafe { task_list_mut().get_mut(get_current_task()) } {
+        task.notification_value = if clear_on_exit { 0 } else { task.notification_value.saturating_sub(1) };
+        task.notification_state = NotifyState::NotWaiting;
+    }
+    exit_critical_section();
+    value
+}
+
+// Block until the current task has a pending notification and consume it
+// `on_entry` runs on the TCB before blocking, if nothing is pending yet
+fn wait_for_notification(timeout: Option<u64>, on_entry: impl FnOnce(&mut TCB)) -> Option<u32> {
+    let handle = get_current_task();
+    let start_tick = get_tick_count();
+    
+    enter_critical_section();
+    let blocked = unsafe {
+        match task_list_mut().get_mut(handle) {
+            Some(task) if task.notification_state != NotifyState::Received => {
+                on_entry(task);
+                task.notification_state = NotifyState::Waiting;
+                mark_current_blocked(timeout)
+            },
+            _ => false,
+        }
+    };
+    exit_critical_section();
+    
+    if blocked {
+        wait_while_blocked();
+    } else {
+        // Not called from a task (or already pending): poll
+        while !notification_pending(handle)
+            && timeout.is_none_or(|ticks| get_tick_count() - start_tick < ticks)
+        {
+            idle_wait();
+        }
+    }
+    
+    enter_critical_section();
+    let value = unsafe {
+        task_list_mut().get_mut(handle).and_then(|task| {
+            let received = task.notification_state == NotifyState::Received;
+            task.notification_state = NotifyState::NotWaiting;
+            if received { Some(task.notification_value) } else { None }
+        })
+    };
+    exit_critical_section();
+    value
+}
+
+// Check if a task has a notification pending
+fn notification_pending(handle: TaskHandle) -> bool {
+    enter_critical_section();
+    let pending = unsafe {
+        task_list().get(handle)
+            .is_some_and(|task| task.notification_state == NotifyState::Received)
+    };
+    exit_critical_section();
+    pending
+}
@@ -0,0 +1,118 @@
+use core::cell::UnsafeCell;
+use crate::freertos::{enter_critical_section, exit_critical_section, tasks};
+use alloc::vec::Vec;
+
+// Called when a timer expires, from the tick interrupt
+// Keep it short and use the _from_isr kernel APIs, ending with
+// port::yield_from_isr if one of them woke a task
+pub type TimerCallback = fn(Timer);
+
+// Per-timer state, indexed by Timer
+struct TimerControl {
+    period: u64,
+    auto_reload: bool,
+    callback: TimerCallback,
+}
+
+// All timers, plus the active ones as (expiry tick, timer) sorted by expiry
+struct TimerList {
+    timers: UnsafeCell<Vec<TimerControl>>,
+    active: UnsafeCell<Vec<(u64, usize)>>,
+}
+
+unsafe impl Sync for TimerList {}
+
+static TIMER_LIST: TimerList = TimerList {
+    timers: UnsafeCell::new(Vec::new()),
+    active: UnsafeCell::new(Vec::new()),
+};
+
+// Run `f` on the timer list with interrupts masked
+fn with_list<R>(f: impl FnOnce(&mut Vec<TimerControl>, &mut Vec<(u64, usize)>) -> R) -> R {
+    enter_critical_section();
+    let result = unsafe { f(&mut *TIMER_LIST.timers.get(), &mut *TIMER_LIST.active.get()) };
+    exit_critical_section();
+    result
+}
+
+// Insert keeping the list sorted; equal expiries fire in arming order
+fn arm(active: &mut Vec<(u64, usize)>, id: usize, expiry: u64) {
+    active.retain(|&(_, t)| t != id);
+    let pos = active.partition_point(|&(e, _)| e <= expiry);
+    active.insert(pos, (expiry, id));
+}
+
+// Handle to a software timer
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Timer(usize);
+
+impl Timer {
+    // Create a stopped timer firing `period` ticks after start
+    // Auto-reload timers re-arm themselves each time they fire
+    pub fn new(period: u64, auto_reload: bool, callback: TimerCallback) -> Timer {
+        with_list(|timers, _| {
+            timers.push(TimerControl {
+                period: period.max(1),
+                auto_reload,
+                callback,
+            });
+            Timer(timers.len() - 1)
+        })
+    }
+    
+    // Arm the timer to fire one period from now; restarts it if active
+    pub fn start(&self) {
+        let now = tasks::get_tick_count();
+        with_list(|timers, active| arm(active, self.0, now + timers[self.0].period));
+    }
+    
+    // Disarm the timer
+    pub fn stop(&self) {
+        with_list(|_, active| active.retain(|&(_, t)| t != self.0));
+    }
+    
+    // Restart the period from now (same as start)
+    pub fn reset(&self) {
+        self.start();
+    }
+    
+    // Set a new period and restart the timer from now
+    pub fn change_period(&self, period: u64) {
+        let now = tasks::get_tick_count();
+        with_list(|timers, active| {
+            timers[self.0].period = period.max(1);
+            arm(active, self.0, now + timers[self.0].period);
+        });
+    }
+    
+    // Check if the timer is armed
+    pub fn is_active(&self) -> bool {
+        with_list(|_, active| active.iter().any(|&(_, t)| t == self.0))
+    }
+}
+
+// Fire every timer due at `now`; called from the tick handler
+pub fn process(now: u64) {
+    loop {
+        // Pop one due timer at a time so callbacks can (re)arm timers
+        let due = with_list(|timers, active| {
+            match active.first() {
+                Some(&(expiry, id)) if expiry <= now => {
+                    active.remove(0);
+                    let timer = &timers[id];
+                    if timer.auto_reload {
+                        // Re-arm from the deadline, not from now, to avoid drift
+                        arm(active, id, expiry + timer.period);
+                    }
+                    Some((id, timer.callback))
+                },
+                _ => None,
+            }
+        });
+        
+        match due {
+            Some((id, callback)) => callback(Timer(id)),
+            None => break,
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use core::cell::UnsafeCell;
+use crate::freertos::{enter_critical_section, exit_critical_section};
+use crate::freertos::tasks::{self, TaskHandle, WakeReason};
+use alloc::vec::Vec;
+
+// How long a caller outside a task sleeps before rechecking a kernel object
+// it cannot block on. Passed to arch::wait_for_event_timeout, which returns
+// early if the caller's condition comes true first.
+pub const POLL_INTERVAL_US: u32 = 1000;
+
+// Tasks blocked waiting for a kernel object (queue space, queue data,
+// semaphore count, ...). The object checks its condition and calls block
+// inside one critical section, then calls wait after leaving it; the other
+// side calls wake_one when the condition may have become true.
+pub struct WaitList {
+    tasks: UnsafeCell<Vec<TaskHandle>>,
+}
+
+unsafe impl Sync for WaitList {}
+
+impl WaitList {
+    pub const fn new() -> Self {
+        WaitList {
+            tasks: UnsafeCell::new(Vec::new()),
+        }
+    }
+    
+    // Add the current task to the list and mark it Blocked
+    // Returns false when not called from a task, which then has to poll.
+    // Must be called inside the critical section that checked the condition
+    pub unsafe fn block(&self, timeout: Option<u64>) -> bool {
+        if !tasks::mark_current_blocked(timeout) {
+            return false;
+        }
+        (*self.tasks.get()).push(tasks::get_current_task());
+        true
+    }
+    
+    // Wait after block, leaving the list if the wait timed out
+    pub fn wait(&self, blocked: bool) -> WakeReason {
+        if !blocked {
+            // Yield to allow other tasks to run
+            crate::arch::wait_for_event_timeout(POLL_INTERVAL_US, || false);
+            return WakeReason::TimedOut;
+        }
+        
+        let reason = tasks::wait_while_blocked();
+        if reason == WakeReason::TimedOut {
+            let me = tasks::get_current_task();
+            enter_critical_section();
+            unsafe {
+                (*self.tasks.get()).retain(|&h| h != me);
+            }
+            exit_critical_section();
+        }
+        reason
+    }
+    
+    // Wake the highest-priority waiter
+    // Must be called with interrupts masked
+    pub unsafe fn wake_one(&self) -> Option<TaskHandle> {
+        tasks::wake_highest_priority(&mut *self.tasks.get())
+    }
+    
+    // Priority of the highest-priority waiter still blocked
+    // Must be called with interrupts masked
+    pub unsafe fn highest_priority(&self) -> Option<u8> {
+        tasks::highest_waiting_priority(&*self.tasks.get())
+    }
+}
+
+// Ticks left of a wait that started at `start_tick`
+// Returns None once the wait has expired, Some(None) for an unbounded wait
+pub fn remaining_wait(start_tick: u64, max_wait: Option<u64>) -> Option<Option<u64>> {
+    match max_wait {
+        None => Some(None),
+        Some(wait_ticks) => {
+            let elapsed = tasks::get_tick_count() - start_tick;
+            if elapsed >= wait_ticks {
+                None
+            } else {
+                Some(Some(wait_ticks - elapsed))
+            }
+        }
+    }
+}
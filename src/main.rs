@@ -14,13 +14,13 @@ use core::arch::global_asm;
 use core::arch::asm;
 use core::panic::PanicInfo;
 
-use arch::s32g3;
+use arch::init::InitPhase;
 // Import for heap allocator
-use linked_list_allocator::LockedHeap;
+use arch::mem::IrqSafeHeap;
 
 // Define a global allocator
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: IrqSafeHeap = IrqSafeHeap::empty();
 
 // Single allocation error handler
 #[alloc_error_handler]
@@ -31,6 +31,8 @@ fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
 mod arch;
 mod drivers;
 mod freertos;
+#[cfg(feature = "self-test")]
+mod self_test;
 
 // Boot section assembly code
 // ATF will load our image and jump to _start
@@ -43,13 +45,24 @@ global_asm!(
     "",
     "   // Set up stack pointer for each CPU core",
     "   mrs x1, mpidr_el1",
-    "   and x1, x1, #0xFF        // Extract CPU ID",
-    "   cbz x1, primary_core     // If CPU0, branch to primary core init",
+    "   and x1, x1, #0xFFFF      // Extract cluster and CPU ID",
+    "   cbz x1, primary_core     // If cluster 0 CPU0, branch to primary core init",
     "",
     "secondary_cores:",
-    "   // Secondary cores wait in a loop until woken",
-    "1: wfe",
+    "   // Linear core position = cluster * cores_per_cluster + CPU ID",
+    "   ubfx x2, x1, #8, #8",
+    "   and x1, x1, #0xFF",
+    "   mov x3, #{cores_per_cluster}",
+    "   madd x1, x2, x3, x1",
+    "",
+    "   // Wait until the boot core publishes a stack for this core",
+    "   adrp x2, SECONDARY_STACK_TOP",
+    "   add x2, x2, :lo12:SECONDARY_STACK_TOP",
+    "1: ldr x0, [x2, x1, lsl #3]",
+    "   cbnz x0, 2f",
+    "   wfe",
     "   b 1b",
+    "2: b secondary_entry",
     "",
     "primary_core:",
     "   // Set up stack pointer using ADRP",
@@ -94,48 +107,124 @@ global_asm!(
     "",
     "   // Return to caller",
     "   ret",
+    cores_per_cluster = const arch::board::BOARD.cores_per_cluster,
 );
 
+// What the panic handler does once it has reported the panic
+#[derive(Copy, Clone, PartialEq)]
+enum PanicAction {
+    Halt,               // Park this core with IRQs masked
+    Reset,              // Reset the system through PSCI SYSTEM_RESET
+    ResetAfter(u32),    // Reset after a delay in ms, leaving time to read the report
+}
+
+// Field builds should pick Reset or ResetAfter
+const PANIC_ACTION: PanicAction = PanicAction::Halt;
+
 // Single panic handler
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("\r\n\r\n*** PANIC ***");
+    use core::fmt::Write;
+    
+    // Quiesce the other cores before dumping state
+    arch::gic::stop_all_other_cores();
+    
+    // A parked core may hold the console lock, so bypass it
+    let mut out = drivers::uart::RawWriter;
+    let _ = write!(out, "\r\n\r\n*** PANIC ***\r\n");
     
     if let Some(location) = info.location() {
-        println!("Location: {}:{}", location.file(), location.line());
+        let _ = write!(out, "Location: {}:{}\r\n", location.file(), location.line());
     }
     
     if let Some(message) = info.message() {
-        println!("Message: {}", message);
+        let _ = write!(out, "Message: {}\r\n", message);
     }
     
-    println!("\r\nSystem halted!");
+    arch::backtrace::print_backtrace();
     
-    // Disable interrupts and enter infinite loop
+    // From here on only static strings: the heap may be what panicked
     unsafe { arch::aarch64::disable_irq(); }
     
+    match PANIC_ACTION {
+        PanicAction::Halt => {},
+        PanicAction::Reset => {
+            drivers::uart::puts("\r\nResetting...\r\n");
+            arch::psci::system_reset();
+        },
+        PanicAction::ResetAfter(ms) => {
+            drivers::uart::puts("\r\nResetting shortly...\r\n");
+            arch::generic_timer::delay_ms(ms as u64);
+            arch::psci::system_reset();
+        },
+    }
+    
+    drivers::uart::puts("\r\nSystem halted!\r\n");
+    
+    loop {
+        arch::aarch64::wfe();
+    }
+}
+
+// Print hello once a second
+fn hello_task() {
+    let mut counter = 0;
+    let mut last_wake = freertos::tasks::get_tick_count();
+    loop {
+        println!("Hello, World from S32G3 Cortex-A in Rust! (count: {})", counter);
+        counter += 1;
+        
+        // Drain anything ISRs and the scheduler traced since the last pass
+        arch::trace::dump();
+        
+        freertos::tasks::delay_until(&mut last_wake, 1000);
+    }
+}
+
+// Kernel entry for secondary cores
+// Reports in, then waits for the boot core to start the scheduler and takes
+// tasks from it. Only SMP builds share the scheduler between cores.
+extern "C" fn secondary_kernel_main(_context: u64) -> ! {
+    println!("Core {} up, MPIDR {:#x}", arch::core_pos(), arch::aarch64::mpidr_raw());
     loop {
+        if cfg!(feature = "smp") {
+            freertos::tasks::join_scheduler();
+        }
         arch::aarch64::wfe();
     }
 }
 
+// Release the secondary cores once the kernel's shared state is set up
+fn start_secondaries() {
+    let started = arch::smp::start_secondaries(secondary_kernel_main, 0);
+    let expected = arch::board::BOARD.num_cores - 1;
+    if started != expected {
+        println!("smp: only {} of {} secondary cores started", started, expected);
+    }
+}
+
 #[no_mangle]
 extern "C" fn kernel_init() -> ! {
-    // Initialize the heap allocator
-    unsafe {
-        extern "C" {
-            static _heap_start: u64;
-            static _heap_end: u64;
+    // Initialize the heap allocator before anything can allocate
+    // (arch::init checks it does not overlap the stacks once the console is up)
+    let heap = arch::mem::heap_region();
+    if heap.is_empty() {
+        // No console yet, so there is nothing useful to report
+        loop {
+            arch::aarch64::wfe();
         }
-        
-        let heap_start = &_heap_start as *const u64 as *mut u8;
-        let heap_end = &_heap_end as *const u64 as usize;
-        let heap_size = heap_end - (heap_start as usize);
-        
-        ALLOCATOR.lock().init(heap_start, heap_size);
+    }
+    unsafe {
+        ALLOCATOR.init(heap.start, heap.len());
     }
     
-    s32g3::init();
+    arch::init();
+    #[cfg(feature = "self-test")]
+    self_test::run_arch_tests();
+    arch::init::run_phase(InitPhase::Kernel, freertos::init);
+    #[cfg(feature = "self-test")]
+    self_test::run_kernel_tests();
+    start_secondaries();
     
     // Print initial hello message
     println!("\r\n\r\nS32G3 Cortex-A Rust port initializing...");
@@ -143,25 +232,20 @@ extern "C" fn kernel_init() -> ! {
     
     // Print CPU information
     unsafe {
-        let mut cpu_id: u64;
-        asm!("mrs {}, mpidr_el1", out(reg) cpu_id);
-        cpu_id &= 0xFF;
-        
         let mut el: u64;
         asm!("mrs {}, CurrentEL", out(reg) el);
         el = (el >> 2) & 0x3;
         
-        println!("Running on CPU {} at EL{}", cpu_id, el);
+        println!("Running on CPU {} at EL{}", arch::core_pos(), el);
     }
     
-    // Main loop that prints hello
-    let mut counter = 0;
+    #[cfg(feature = "self-test")]
+    self_test::start_task_tests();
+    freertos::tasks::create_task(hello_task, "hello", 16 * 1024).expect("no memory for the hello task");
+    freertos::tasks::start_scheduler();
+    
+    // start_scheduler only returns if there was nothing to run
     loop {
-        // Print hello
-        println!("Hello, World from S32G3 Cortex-A in Rust! (count: {})", counter);
-        counter += 1;
-        
-        // Use S32G3 timer for precise delay
-        arch::s32g3::timer::delay_ms(1000);
+        arch::aarch64::wfe();
     }
 }
\ No newline at end of file
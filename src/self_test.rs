@@ -0,0 +1,536 @@
+// Boot self-tests
+// Built only with the self-test feature, so release images neither run them
+// nor carry them. They fault on purpose, map a high alias, churn the heap and
+// tasks, and panic on the first failure: meant for bring-up and CI boards.
+
+use core::arch::asm;
+use crate::{arch, freertos};
+
+// Box a value and drop it, checking the allocator hands the memory back
+fn heap_self_test() {
+    let used_before = crate::ALLOCATOR.with_heap(|heap| heap.used());
+    
+    let boxed = alloc::boxed::Box::new(0x5A5A_5A5A_u64);
+    assert_eq!(*boxed, 0x5A5A_5A5A, "heap self-test: bad readback");
+    assert!(crate::ALLOCATOR.with_heap(|heap| heap.used()) > used_before, "heap self-test: allocation not accounted");
+    drop(boxed);
+    
+    assert_eq!(crate::ALLOCATOR.with_heap(|heap| heap.used()), used_before, "heap self-test: memory not freed");
+}
+
+// Allocate and free mixed sizes in an interleaved order, checking freed
+// blocks are merged and reused rather than exhausting the heap
+fn heap_churn_self_test() {
+    use alloc::vec::Vec;
+    
+    let before = arch::mem::heap_stats();
+    
+    for round in 0..1000 {
+        let mut blocks: Vec<Vec<u8>> = Vec::with_capacity(8);
+        for i in 0..8 {
+            blocks.push(Vec::with_capacity(16 << ((round + i) % 8)));
+        }
+        // Free every other block first so neighbours must be coalesced
+        let mut i = 0;
+        blocks.retain(|_| { i += 1; i % 2 == 0 });
+    }
+    
+    let after = arch::mem::heap_stats();
+    assert_eq!(after.used, before.used, "heap churn self-test: memory not freed");
+    assert_eq!(after.largest_free_block, before.largest_free_block,
+               "heap churn self-test: free space fragmented");
+}
+
+// Create and delete tasks in a loop, checking their stacks and TCB slots are
+// all reclaimed
+fn task_churn_self_test() {
+    fn noop() {}
+    
+    // The first round may grow the task list; measure from after it
+    let handle = freertos::tasks::create_task(noop, "churn", 4096).expect("task self-test: create failed");
+    let info = freertos::tasks::info(handle.task());
+    assert!(info.is_some_and(|info| info.name == "churn"), "task self-test: new task not listed");
+    freertos::tasks::delete_task(handle.task());
+    assert!(freertos::tasks::info(handle.task()).is_none(), "task self-test: deleted task still listed");
+    drop(handle);
+    let used_before = crate::ALLOCATOR.with_heap(|heap| heap.used());
+    
+    for _ in 0..100 {
+        let handle = freertos::tasks::create_task(noop, "churn", 4096).expect("task self-test: create failed");
+        assert!(freertos::tasks::delete_task(handle.task()), "task self-test: delete failed");
+    }
+    
+    assert_eq!(crate::ALLOCATOR.with_heap(|heap| heap.used()), used_before, "task self-test: heap not reclaimed");
+}
+
+// Suspend and resume a task that has not run yet, checking it stays out of
+// scheduling and cannot be woken while suspended
+fn task_suspend_self_test() {
+    use freertos::tasks::{self, TaskState};
+    fn noop() {}
+    
+    let handle = tasks::create_task(noop, "suspend", 4096).expect("suspend self-test: create failed").task();
+    let state = |handle| tasks::info(handle).map(|info| info.state);
+    
+    assert!(tasks::suspend_task(handle), "suspend self-test: suspend failed");
+    assert_eq!(state(handle), Some(TaskState::Suspended), "suspend self-test: not suspended");
+    assert!(!tasks::wake_task(handle), "suspend self-test: suspended task woken");
+    
+    assert!(tasks::resume_task(handle), "suspend self-test: resume failed");
+    assert_eq!(state(handle), Some(TaskState::Ready), "suspend self-test: not ready after resume");
+    assert!(!tasks::resume_task(handle), "suspend self-test: resumed a task that was not suspended");
+    
+    tasks::delete_task(handle);
+}
+
+// Move a Ready task between priorities and cores before the scheduler
+// starts, checking its priority is capped and it stays Ready throughout
+fn task_priority_self_test() {
+    use freertos::tasks::{self, TaskState};
+    fn noop() {}
+    
+    let handle = tasks::create_task(noop, "priority", 4096).expect("priority self-test: create failed").task();
+    let state = |handle| tasks::info(handle).map(|info| info.state);
+    
+    assert!(tasks::set_task_priority(handle, u8::MAX), "priority self-test: set failed");
+    assert_eq!(tasks::get_task_priority(handle), Some(tasks::MAX_PRIORITIES - 1), "priority self-test: not capped");
+    assert!(tasks::set_task_affinity(handle, 1), "priority self-test: affinity failed");
+    assert!(tasks::set_task_priority(handle, tasks::DEFAULT_PRIORITY), "priority self-test: set failed");
+    assert_eq!(state(handle), Some(TaskState::Ready), "priority self-test: not ready after the moves");
+    
+    tasks::delete_task(handle);
+}
+
+// Run two FP tasks side by side, each holding its own value in v16 across
+// forced context switches, checking neither sees the other's register state
+fn fpu_task_self_test() {
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use freertos::tasks;
+    
+    const ROUNDS: u32 = 100;
+    static MISMATCHES: AtomicU32 = AtomicU32::new(0);
+    
+    fn hold_pattern<const PATTERN: u64>() {
+        for _ in 0..ROUNDS {
+            freertos::port::set_need_resched();
+            let seen: u64;
+            // Only the context switch preserves v16; the kernel is built
+            // soft-float, so nothing else here touches it
+            unsafe {
+                asm!(
+                    ".arch_extension fp",
+                    "fmov d16, {pattern}",
+                    "svc #0",
+                    "fmov {seen}, d16",
+                    pattern = in(reg) PATTERN,
+                    seen = out(reg) seen,
+                );
+            }
+            if seen != PATTERN {
+                MISMATCHES.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+    
+    let first = tasks::create_fpu_task(hold_pattern::<0x1111_2222_3333_4444>, "fpu-a", 8192)
+        .expect("fpu self-test: create failed");
+    let second = tasks::create_fpu_task(hold_pattern::<0x5555_6666_7777_8888>, "fpu-b", 8192)
+        .expect("fpu self-test: create failed");
+    assert!(first.join(Some(1000)).is_ok() && second.join(Some(1000)).is_ok(),
+            "fpu self-test: tasks did not finish");
+    assert_eq!(MISMATCHES.load(Ordering::Relaxed), 0, "fpu self-test: FP state leaked between tasks");
+}
+
+// Hold a mutex while a higher-priority task waits on it and times out,
+// checking the holder is boosted only while the waiter is there, and that a
+// recursive lock is refused rather than deadlocking
+fn mutex_self_test() {
+    use freertos::mutex::{Mutex, MutexError};
+    use freertos::tasks;
+    
+    static LOCK: Mutex = Mutex::new();
+    
+    fn waiter() -> Result<(), MutexError> {
+        LOCK.lock(Some(20))
+    }
+    
+    let me = tasks::get_current_task();
+    assert_eq!(LOCK.lock(Some(0)), Ok(()), "mutex self-test: lock failed");
+    assert_eq!(LOCK.lock(Some(0)), Err(MutexError::AlreadyOwned), "mutex self-test: recursive lock allowed");
+    
+    // Run below the waiter, so its boost shows
+    tasks::set_task_priority(me, tasks::IDLE_PRIORITY);
+    let waiting = tasks::spawn(waiter, "waiter", 4096).expect("mutex self-test: create failed");
+    for _ in 0..10 {
+        if tasks::get_task_priority(me) == Some(tasks::DEFAULT_PRIORITY) {
+            break;
+        }
+        tasks::block_with_timeout(Some(1));
+    }
+    assert_eq!(tasks::get_task_priority(me), Some(tasks::DEFAULT_PRIORITY), "mutex self-test: owner not boosted");
+    
+    assert_eq!(waiting.join(Some(1000)), Ok(Err(MutexError::Timeout)), "mutex self-test: waiter got the mutex");
+    assert_eq!(tasks::get_task_priority(me), Some(tasks::IDLE_PRIORITY), "mutex self-test: boost kept after timeout");
+    
+    tasks::set_task_priority(me, tasks::DEFAULT_PRIORITY);
+    assert_eq!(LOCK.unlock(), Ok(()), "mutex self-test: unlock failed");
+    assert_eq!(LOCK.unlock(), Err(MutexError::NotOwner), "mutex self-test: unlocked twice");
+}
+
+// Join a task that returned and drop its handle, checking the idle task
+// reclaims its stack and slot
+fn task_reclaim_self_test() {
+    use freertos::tasks;
+    fn answer() -> u32 { 42 }
+    
+    // Spawn, join and release a task, then wait for it to leave the list
+    let run_and_release = || {
+        let joined = tasks::spawn(answer, "reclaim", 4096).expect("reclaim self-test: create failed");
+        assert_eq!(joined.join(Some(1000)), Ok(42), "reclaim self-test: wrong result");
+        let handle = joined.task();
+        drop(joined);
+        
+        // Give the idle task a chance to run
+        for _ in 0..10 {
+            if tasks::info(handle).is_none() {
+                break;
+            }
+            tasks::block_with_timeout(Some(1));
+        }
+        assert!(tasks::info(handle).is_none(), "reclaim self-test: terminated task still listed");
+    };
+    
+    // The first round may grow the task list; measure from after it
+    run_and_release();
+    let used_before = crate::ALLOCATOR.with_heap(|heap| heap.used());
+    run_and_release();
+    assert_eq!(crate::ALLOCATOR.with_heap(|heap| heap.used()), used_before, "reclaim self-test: heap not reclaimed");
+}
+
+// Have a task suspend itself and another task resume it, checking it stays
+// off the CPU until resumed and then runs to completion
+fn task_resume_self_test() {
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use freertos::tasks::{self, TaskState};
+    
+    static RESUMED: AtomicBool = AtomicBool::new(false);
+    
+    fn sleeper() {
+        tasks::suspend_task(tasks::get_current_task());
+        RESUMED.store(true, Ordering::Release);
+    }
+    
+    // Outranking this task, it runs and suspends itself at once (or on
+    // another core, in an SMP build)
+    let sleeper = tasks::create_task(sleeper, "sleeper", 8192).expect("resume self-test: create failed");
+    tasks::set_task_priority(sleeper.task(), tasks::DEFAULT_PRIORITY + 1);
+    let state = |handle| tasks::info(handle).map(|info| info.state);
+    for _ in 0..10 {
+        if state(sleeper.task()) == Some(TaskState::Suspended) {
+            break;
+        }
+        tasks::block_with_timeout(Some(1));
+    }
+    assert_eq!(state(sleeper.task()), Some(TaskState::Suspended), "resume self-test: task did not suspend itself");
+    
+    // A suspended task stays put across ticks and wakes
+    tasks::block_with_timeout(Some(5));
+    assert!(!tasks::wake_task(sleeper.task()), "resume self-test: suspended task woken");
+    assert!(!RESUMED.load(Ordering::Acquire), "resume self-test: ran while suspended");
+    
+    assert!(tasks::resume_task(sleeper.task()), "resume self-test: resume failed");
+    assert!(sleeper.join(Some(100)).is_ok(), "resume self-test: task did not finish after resume");
+    assert!(RESUMED.load(Ordering::Acquire), "resume self-test: task did not continue after resume");
+}
+
+// Have three one-shot timers set one event bit each from the tick interrupt,
+// checking a wait for all three only returns once the last one is set
+fn event_group_self_test() {
+    use freertos::event_groups::EventGroup;
+    use freertos::timers::Timer;
+    
+    const ALL: u32 = 0b111;
+    static EVENTS: EventGroup = EventGroup::new();
+    
+    fn set_bit<const BIT: u32>(_timer: Timer) {
+        let mut woken = false;
+        EVENTS.set_bits_from_isr(1 << BIT, &mut woken);
+        freertos::port::yield_from_isr(woken);
+    }
+    
+    let start = freertos::tasks::get_tick_count();
+    for (ticks, callback) in [(1, set_bit::<0> as fn(Timer)), (2, set_bit::<1>), (3, set_bit::<2>)] {
+        Timer::new(ticks, false, callback).start();
+    }
+    
+    let value = EVENTS.wait_bits(ALL, true, true, Some(100));
+    assert_eq!(value & ALL, ALL, "event group self-test: wait timed out");
+    assert!(freertos::tasks::get_tick_count() - start >= 3, "event group self-test: woke before all bits were set");
+    assert_eq!(EVENTS.get_bits(), 0, "event group self-test: bits not cleared on exit");
+}
+
+// Run two auto-reload timers with different periods, checking each fired
+// once per period over the same stretch of ticks
+fn timer_self_test() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use freertos::timers::Timer;
+    use freertos::tasks::get_tick_count;
+    
+    const TICKS: u64 = 12;
+    static FAST: AtomicU64 = AtomicU64::new(0);
+    static SLOW: AtomicU64 = AtomicU64::new(0);
+    
+    fn count<const SLOW_TIMER: bool>(_timer: Timer) {
+        let counter = if SLOW_TIMER { &SLOW } else { &FAST };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    let fast = Timer::new(2, true, count::<false>);
+    let slow = Timer::new(3, true, count::<true>);
+    
+    // Start both on the same tick
+    freertos::enter_critical_section();
+    let start = get_tick_count();
+    fast.start();
+    slow.start();
+    freertos::exit_critical_section();
+    
+    while get_tick_count() - start < TICKS {
+        arch::wait_for_interrupt();
+    }
+    
+    // Read the counts and the tick they belong to together
+    freertos::enter_critical_section();
+    let elapsed = get_tick_count() - start;
+    let (fast_count, slow_count) = (FAST.load(Ordering::Relaxed), SLOW.load(Ordering::Relaxed));
+    fast.stop();
+    slow.stop();
+    freertos::exit_critical_section();
+    
+    assert_eq!(fast_count, elapsed / 2, "timer self-test: 2-tick timer fired {} times in {} ticks", fast_count, elapsed);
+    assert_eq!(slow_count, elapsed / 3, "timer self-test: 3-tick timer fired {} times in {} ticks", slow_count, elapsed);
+}
+
+// Have an auto-reload timer give a semaphore from the tick interrupt as the
+// producer and take each unit here as the consumer, checking none is lost and
+// the count never passes its maximum
+fn semaphore_self_test() {
+    use freertos::semaphore::Semaphore;
+    use freertos::timers::Timer;
+    
+    const ITEMS: u32 = 5;
+    static ITEMS_READY: Semaphore = Semaphore::new(0, 2);
+    
+    fn produce(_timer: Timer) {
+        let mut woken = false;
+        ITEMS_READY.give_from_isr(&mut woken);
+        freertos::port::yield_from_isr(woken);
+    }
+    
+    let producer = Timer::new(1, true, produce);
+    producer.start();
+    for item in 0..ITEMS {
+        assert!(ITEMS_READY.take(Some(10)), "semaphore self-test: item {} never given", item);
+        assert!(ITEMS_READY.count() <= 2, "semaphore self-test: count past its maximum");
+    }
+    producer.stop();
+    
+    // Drain what was given after the last take
+    while ITEMS_READY.take(Some(0)) {}
+    assert!(!ITEMS_READY.take(Some(2)), "semaphore self-test: given after the producer stopped");
+}
+
+// Block a task on a latch that a timer counts down, checking the wait
+// times out while the latch is closed and returns once it opens
+fn latch_self_test() {
+    use freertos::latch::CountdownLatch;
+    use freertos::timers::Timer;
+    
+    static WORKERS_DONE: CountdownLatch = CountdownLatch::new(3);
+    
+    fn worker(_timer: Timer) {
+        let mut woken = false;
+        WORKERS_DONE.count_down_from_isr(&mut woken);
+        freertos::port::yield_from_isr(woken);
+    }
+    
+    assert!(!WORKERS_DONE.wait(Some(2)), "latch self-test: opened with no workers done");
+    
+    let workers = Timer::new(1, true, worker);
+    workers.start();
+    assert!(WORKERS_DONE.wait(Some(20)), "latch self-test: never opened");
+    workers.stop();
+    assert_eq!(WORKERS_DONE.count(), 0, "latch self-test: count not saturated at zero");
+}
+
+// Push bytes through a small stream buffer so the ring wraps, checking that
+// sends stop at the capacity and bytes come out in order
+fn stream_buffer_self_test() {
+    let stream = freertos::stream_buffer::StreamBuffer::new(8, 1);
+    let mut buf = [0u8; 8];
+    
+    assert_eq!(stream.send(b"abcde", Some(0)), 5, "stream self-test: short send");
+    assert_eq!(stream.receive(&mut buf[..3], Some(0)), 3, "stream self-test: short receive");
+    assert_eq!(stream.send(b"fghijkl", Some(0)), 6, "stream self-test: send past capacity");
+    assert_eq!(stream.space_available(), 0, "stream self-test: buffer not full");
+    
+    assert_eq!(stream.receive(&mut buf, Some(0)), 8, "stream self-test: bytes lost");
+    assert_eq!(&buf, b"defghijk", "stream self-test: bytes out of order");
+    assert_eq!(stream.receive(&mut buf, Some(0)), 0, "stream self-test: receive from empty buffer");
+}
+
+// Pass messages of different sizes through a message buffer, checking they
+// come out whole and that a send which does not fit writes nothing
+fn message_buffer_self_test() {
+    let messages = freertos::message_buffer::MessageBuffer::new(17);
+    let mut buf = [0u8; 16];
+    
+    assert!(!messages.send(&[0; 14], Some(0)), "message self-test: oversized message accepted");
+    assert!(messages.send(b"ping", Some(0)), "message self-test: send failed");
+    assert!(messages.send(b"pong!", Some(0)), "message self-test: send failed");
+    assert!(!messages.send(b"x", Some(0)), "message self-test: send into a full buffer");
+    
+    assert_eq!(messages.receive(&mut buf[..4], Some(0)), Some(4), "message self-test: bad length");
+    assert_eq!(&buf[..4], b"ping", "message self-test: bad message");
+    assert_eq!(messages.receive(&mut buf[..4], Some(0)), None, "message self-test: message truncated");
+    assert_eq!(messages.receive(&mut buf, Some(0)), Some(5), "message self-test: bad length");
+    assert_eq!(&buf[..5], b"pong!", "message self-test: bad message");
+    assert_eq!(messages.next_message_len(), None, "message self-test: buffer not empty");
+}
+
+// Take and release a spinlock, checking it excludes a second taker and hands
+// the value over intact
+fn spinlock_self_test() {
+    static COUNTER: arch::sync::SpinLock<u32> = arch::sync::SpinLock::new(0);
+    
+    {
+        let mut count = COUNTER.lock();
+        assert!(COUNTER.try_lock().is_none(), "spinlock self-test: taken twice");
+        *count += 1;
+    }
+    assert!(!COUNTER.is_locked(), "spinlock self-test: not released");
+    assert_eq!(*COUNTER.lock(), 1, "spinlock self-test: update lost");
+}
+
+// Alias a heap page above 4 GiB and check writes through the alias land in
+// the page, then unmap it and check bad requests are refused
+fn mmu_map_self_test() {
+    use arch::mmu::{self, MemAttr, MmuError};
+    const ALIAS: usize = 0x1_0000_0000;
+    
+    let layout = alloc::alloc::Layout::from_size_align(4096, 4096).unwrap();
+    let page = unsafe { alloc::alloc::alloc_zeroed(layout) };
+    assert!(!page.is_null(), "mmu self-test: no page");
+    
+    mmu::map(ALIAS, page as usize, 4096, MemAttr::NORMAL_RW).expect("mmu self-test: map failed");
+    unsafe {
+        core::ptr::write_volatile((ALIAS + 8) as *mut u64, 0x5A5A_1234);
+        assert_eq!(core::ptr::read_volatile(page.add(8) as *const u64), 0x5A5A_1234,
+                   "mmu self-test: alias does not reach the page");
+    }
+    assert_eq!(mmu::map(ALIAS, page as usize, 4096, MemAttr::NORMAL_RW), Err(MmuError::Overlap));
+    assert_eq!(mmu::map(0x8000_0000, 0x8000_0000, 4096, MemAttr::DEVICE), Err(MmuError::Overlap));
+    assert_eq!(mmu::map(ALIAS + 1, page as usize, 4096, MemAttr::NORMAL_RW), Err(MmuError::Misaligned));
+    
+    mmu::unmap(ALIAS, 4096).expect("mmu self-test: unmap failed");
+    assert_eq!(mmu::unmap(ALIAS, 4096), Err(MmuError::NotMapped));
+    unsafe { alloc::alloc::dealloc(page, layout) };
+}
+
+// Allocate a DMA buffer and check its alignment, addresses and round trip
+// through the cache maintenance
+fn dma_self_test() {
+    let mut buffer = arch::dma::alloc_coherent(100).expect("dma self-test: allocation failed");
+    assert!(buffer.va().is_multiple_of(4096) && buffer.len() == 4096, "dma self-test: not a whole page");
+    assert_eq!(buffer.va(), buffer.pa(), "dma self-test: not identity mapped");
+    assert!(buffer.as_slice().iter().all(|&b| b == 0), "dma self-test: not zeroed");
+    
+    buffer.as_mut_slice()[..4].copy_from_slice(&[1, 2, 3, 4]);
+    buffer.flush();
+    buffer.invalidate();
+    assert_eq!(buffer.as_slice()[..4], [1, 2, 3, 4], "dma self-test: flushed data lost");
+    arch::dma::free_coherent(buffer);
+}
+
+// Execute an undefined instruction under a fault handler that skips it,
+// checking Resume continues after the faulting instruction
+fn fault_resume_self_test() {
+    use arch::exceptions::{FaultAction, TrapFrame};
+    use core::sync::atomic::{AtomicU32, Ordering};
+    
+    static FAULTS: AtomicU32 = AtomicU32::new(0);
+    
+    fn skip_fault(frame: &mut TrapFrame) -> FaultAction {
+        FAULTS.fetch_add(1, Ordering::Relaxed);
+        frame.skip_instruction();
+        FaultAction::Resume
+    }
+    
+    arch::set_fault_handler(skip_fault);
+    unsafe { asm!("udf #0") };
+    arch::exceptions::clear_fault_handler();
+    assert_eq!(FAULTS.load(Ordering::Relaxed), 1, "fault self-test: handler ran {} times",
+               FAULTS.load(Ordering::Relaxed));
+}
+
+// Record a few trace messages and drain them, checking order, level and
+// truncation of long messages
+fn trace_self_test() {
+    arch::trace::drain(|_| {});
+    
+    crate::trace!("trace self-test {}", 1);
+    crate::trace_at!(arch::trace::TraceLevel::Warn, "{:>60}", "long");
+    
+    let mut seen = 0;
+    let lost = arch::trace::drain(|entry| {
+        match seen {
+            0 => assert_eq!(entry.message(), "trace self-test 1", "trace self-test: wrong message"),
+            _ => {
+                assert_eq!(entry.level, arch::trace::TraceLevel::Warn, "trace self-test: wrong level");
+                assert_eq!(entry.message().len(), arch::trace::MESSAGE_LEN, "trace self-test: not truncated");
+            },
+        }
+        seen += 1;
+    });
+    assert_eq!((seen, lost), (2, 0), "trace self-test: records missing");
+}
+
+// Self-tests that need running tasks; started by kernel_init with the
+// scheduler and exits once they pass
+fn self_test_task() {
+    fpu_task_self_test();
+    task_resume_self_test();
+    task_reclaim_self_test();
+    mutex_self_test();
+    latch_self_test();
+    crate::println!("Task self-tests passed");
+}
+
+// Tests of the arch layer, run by kernel_init right after arch::init
+pub fn run_arch_tests() {
+    heap_self_test();
+    heap_churn_self_test();
+    spinlock_self_test();
+    mmu_map_self_test();
+    dma_self_test();
+    trace_self_test();
+    fault_resume_self_test();
+}
+
+// Tests of the kernel objects, run by kernel_init once freertos::init is done
+// and before the scheduler starts
+pub fn run_kernel_tests() {
+    task_churn_self_test();
+    task_suspend_self_test();
+    task_priority_self_test();
+    timer_self_test();
+    event_group_self_test();
+    semaphore_self_test();
+    stream_buffer_self_test();
+    message_buffer_self_test();
+}
+
+// Create the task that runs the tests needing a running scheduler
+pub fn start_task_tests() {
+    freertos::tasks::create_task(self_test_task, "selftest", 16 * 1024).expect("no memory for the self-test task");
+}